@@ -0,0 +1,242 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::pin,
+    sync::{
+        Arc, Mutex, PoisonError, Weak,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use tokio::sync::Notify;
+
+/// Cooperative cancellation signal shared between the host and a plugin.
+///
+/// The host hands every plugin its own token, derived from the host's root
+/// token. It fires when the host shuts down or when the plugin is unloaded
+/// (which is also what happens on a reload). Plugins are expected to watch it
+/// and return from `main` once it fires.
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    notify: Notify,
+    children: Mutex<Vec<Weak<Self>>>,
+}
+
+impl Inner {
+    fn cancel(&self) {
+        if self.cancelled.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        self.notify.notify_waiters();
+
+        let children = std::mem::take(
+            &mut *self.children.lock().unwrap_or_else(PoisonError::into_inner),
+        );
+        for child in children.iter().filter_map(Weak::upgrade) {
+            child.cancel();
+        }
+    }
+}
+
+impl CancellationToken {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a token that is cancelled together with this one,
+    /// but can also be cancelled on its own without affecting the parent.
+    #[must_use]
+    pub fn child_token(&self) -> Self {
+        let child = Self::new();
+        let mut children = self
+            .inner
+            .children
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if self.is_cancelled() {
+            drop(children);
+            child.cancel();
+        } else {
+            children.retain(|c| c.strong_count() > 0);
+            children.push(Arc::downgrade(&child.inner));
+        }
+        child
+    }
+
+    /// Fires the token, waking up everyone waiting on it.
+    /// Cancelling an already cancelled token does nothing.
+    pub fn cancel(&self) {
+        self.inner.cancel();
+    }
+
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Completes once the token is cancelled.
+    pub async fn cancelled(&self) {
+        loop {
+            let mut notified = pin!(self.inner.notify.notified());
+            notified.as_mut().enable();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Runs `future` until it completes or the token is cancelled,
+    /// whichever happens first.
+    ///
+    /// # Errors
+    /// Returns [`Cancelled`] if the token fired before `future` completed.
+    pub async fn run_until_cancelled<F: Future>(
+        &self,
+        future: F,
+    ) -> Result<F::Output, Cancelled> {
+        let future = pin!(future);
+        let cancelled = pin!(self.cancelled());
+        match futures_select(future, cancelled).await {
+            Either::Left(output) => Ok(output),
+            Either::Right(()) => Err(Cancelled),
+        }
+    }
+}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// Error returned by cancellation-aware helpers when the token fired first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("operation was cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+enum Either<L, R> {
+    Left(L),
+    Right(R),
+}
+
+/// Polls both futures, left first, and resolves with the first to finish.
+/// Unlike `tokio::select!` this does not touch any runtime thread-locals.
+async fn futures_select<L, R>(
+    mut left: std::pin::Pin<&mut L>,
+    mut right: std::pin::Pin<&mut R>,
+) -> Either<L::Output, R::Output>
+where
+    L: Future,
+    R: Future, {
+    std::future::poll_fn(|cx| {
+        if let std::task::Poll::Ready(output) = left.as_mut().poll(cx) {
+            return std::task::Poll::Ready(Either::Left(output));
+        }
+        right.as_mut().poll(cx).map(Either::Right)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::timeout;
+
+    use super::*;
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn cancels_children() {
+        let parent = CancellationToken::new();
+        let child = parent.child_token();
+        let grandchild = child.child_token();
+        let sibling = parent.child_token();
+
+        sibling.cancel();
+        assert!(!parent.is_cancelled());
+        assert!(!child.is_cancelled());
+
+        parent.cancel();
+        assert!(child.is_cancelled());
+        assert!(grandchild.is_cancelled());
+        // Children of a cancelled token start cancelled
+        assert!(parent.child_token().is_cancelled());
+    }
+
+    #[test]
+    fn forgets_dropped_children() {
+        let parent = CancellationToken::new();
+        for _ in 0..10 {
+            drop(parent.child_token());
+        }
+        let _child = parent.child_token();
+        let children = parent.inner.children.lock().unwrap().len();
+        assert_eq!(children, 1);
+    }
+
+    #[tokio::test]
+    async fn wakes_waiters() {
+        let token = CancellationToken::new();
+        let waiters: Vec<_> = (0..3)
+            .map(|_| {
+                let token = token.child_token();
+                tokio::spawn(async move { token.cancelled().await })
+            })
+            .collect();
+        tokio::task::yield_now().await;
+
+        token.cancel();
+        for waiter in waiters {
+            timeout(WAIT, waiter).await.unwrap().unwrap();
+        }
+        // Already cancelled
+        timeout(WAIT, token.cancelled()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn runs_until_cancelled() {
+        let token = CancellationToken::new();
+        let done = token.run_until_cancelled(async { 42 }).await;
+        assert_eq!(done, Ok(42));
+
+        let waiting = {
+            let token = token.clone();
+            tokio::spawn(async move {
+                token
+                    .run_until_cancelled(std::future::pending::<()>())
+                    .await
+            })
+        };
+        tokio::task::yield_now().await;
+        token.cancel();
+        assert_eq!(
+            timeout(WAIT, waiting).await.unwrap().unwrap(),
+            Err(Cancelled)
+        );
+
+        // A future that's ready wins, even once cancelled
+        let ready = token.run_until_cancelled(async { 42 }).await;
+        assert_eq!(ready, Ok(42));
+        let pending = token.run_until_cancelled(std::future::pending::<()>());
+        assert_eq!(timeout(WAIT, pending).await.unwrap(), Err(Cancelled));
+    }
+}
@@ -0,0 +1,696 @@
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+    task::{Context, Poll},
+};
+
+use tokio::{
+    fs::File,
+    io::{AsyncBufReadExt, AsyncSeekExt, BufReader},
+    sync::mpsc,
+};
+
+use crate::{runtime::RuntimeHandle, time::Duration};
+
+/// Host side of a stream of lines.
+pub trait LineSource: Send {
+    /// Next line as read, without its `\n`
+    fn poll_next_line(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<io::Result<Vec<u8>>>>;
+
+    /// Where the last line ends, for followed files
+    fn cursor(&self) -> Option<TailCursor> {
+        None
+    }
+}
+
+impl LineSource for mpsc::Receiver<io::Result<Vec<u8>>> {
+    fn poll_next_line(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<io::Result<Vec<u8>>>> {
+        self.poll_recv(cx)
+    }
+}
+
+/// Lines of a followed file, each sent along with where it ends.
+pub struct TailSource {
+    rx: mpsc::Receiver<io::Result<(Vec<u8>, TailCursor)>>,
+    cursor: Option<TailCursor>,
+}
+
+impl TailSource {
+    pub const fn new(
+        rx: mpsc::Receiver<io::Result<(Vec<u8>, TailCursor)>>,
+    ) -> Self {
+        Self { rx, cursor: None }
+    }
+}
+
+impl LineSource for TailSource {
+    fn poll_next_line(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<io::Result<Vec<u8>>>> {
+        self.rx.poll_recv(cx).map(|line| {
+            line.map(|line| {
+                line.map(|(line, cursor)| {
+                    self.cursor = Some(cursor);
+                    line
+                })
+            })
+        })
+    }
+
+    fn cursor(&self) -> Option<TailCursor> {
+        self.cursor
+    }
+}
+
+/// Lines produced by the host, from a followed file or a child process.
+/// Lines are yielded without their trailing newline, and decoded lossily
+/// unless they're read raw.
+pub struct Lines {
+    source: Box<dyn LineSource>,
+}
+
+impl Lines {
+    #[must_use]
+    pub fn new(source: Box<dyn LineSource>) -> Self {
+        Self { source }
+    }
+
+    /// Next line, with invalid UTF-8 replaced, or `None` once the source
+    /// is exhausted
+    pub async fn next_line(&mut self) -> Option<io::Result<String>> {
+        let line = self.next_raw_line().await?;
+        Some(line.map(|line| decode(&line)))
+    }
+
+    /// Next line as it was read, `\r` included, e.g. to parse a format
+    /// mixing text and binary data
+    pub async fn next_raw_line(&mut self) -> Option<io::Result<Vec<u8>>> {
+        std::future::poll_fn(|cx| self.source.poll_next_line(cx)).await
+    }
+
+    /// Where the last line returned by [`Lines::next_line`] ends, if
+    /// following a file. Passed back as [`TailOptions::resume`], following
+    /// starts again right after it.
+    #[must_use]
+    pub fn cursor(&self) -> Option<TailCursor> {
+        self.source.cursor()
+    }
+}
+
+impl fmt::Debug for Lines {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Lines").finish_non_exhaustive()
+    }
+}
+
+/// Position in a followed file, which survives the file being rotated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TailCursor {
+    pub device: u64,
+    pub inode: u64,
+    pub offset: u64,
+}
+
+impl TailCursor {
+    const LEN: usize = 24;
+
+    /// Fixed-size encoding, e.g. to keep it in the plugin's storage
+    #[must_use]
+    pub fn to_bytes(self) -> [u8; Self::LEN] {
+        let mut bytes = [0; Self::LEN];
+        bytes[..8].copy_from_slice(&self.device.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.inode.to_le_bytes());
+        bytes[16..].copy_from_slice(&self.offset.to_le_bytes());
+        bytes
+    }
+
+    /// Decodes what [`TailCursor::to_bytes`] encoded
+    #[must_use]
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes: &[u8; Self::LEN] = bytes.try_into().ok()?;
+        let u64_at = |i: usize| {
+            let mut word = [0; 8];
+            word.copy_from_slice(&bytes[i..i + 8]);
+            u64::from_le_bytes(word)
+        };
+        Some(Self {
+            device: u64_at(0),
+            inode: u64_at(8),
+            offset: u64_at(16),
+        })
+    }
+
+    const fn new((device, inode): (u64, u64), offset: u64) -> Self {
+        Self {
+            device,
+            inode,
+            offset,
+        }
+    }
+
+    const fn file_id(self) -> (u64, u64) {
+        (self.device, self.inode)
+    }
+}
+
+/// How a file should be followed by `tail_file`.
+#[derive(Debug, Clone)]
+pub struct TailOptions {
+    /// Start from the beginning of the file instead of its end
+    pub from_start: bool,
+    /// Start right after a line read previously, e.g. before a restart.
+    /// If the file was rotated since, the rest of the rotated file
+    /// (`<path>.1`) is read first. Takes precedence over `from_start`.
+    pub resume: Option<TailCursor>,
+    /// How often the file is checked for new data, truncation or rotation
+    pub poll_interval: Duration,
+}
+
+impl Default for TailOptions {
+    fn default() -> Self {
+        Self {
+            from_start: false,
+            resume: None,
+            poll_interval: Duration::from_millis(250),
+        }
+    }
+}
+
+impl dyn RuntimeHandle {
+    /// Read a whole file as UTF-8
+    ///
+    /// # Errors
+    /// If the file can't be read or isn't valid UTF-8.
+    pub async fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        let bytes = self.read_file(path).await?;
+        String::from_utf8(bytes)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Buffered line capacity between the host's follower and the plugin.
+pub const LINES_BUFFER: usize = 256;
+
+/// Identity of a file on disk, used to notice rotations.
+#[cfg(unix)]
+fn file_id(meta: &std::fs::Metadata) -> (u64, u64) {
+    use std::os::unix::fs::MetadataExt;
+    (meta.dev(), meta.ino())
+}
+
+/// Rotations can't be noticed without inodes, only truncations.
+#[cfg(not(unix))]
+const fn file_id(_: &std::fs::Metadata) -> (u64, u64) {
+    (0, 0)
+}
+
+/// Where a rotated file is usually moved, e.g. `auth.log.1`
+#[must_use]
+pub fn rotated_path(path: &Path) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(".1");
+    PathBuf::from(rotated)
+}
+
+/// Where following starts.
+struct Start {
+    file: File,
+    id: Option<(u64, u64)>,
+    position: u64,
+    /// File now at the path, read once `file` is drained if that's the
+    /// rotated one
+    rotated: Option<(File, Option<(u64, u64)>)>,
+}
+
+impl Start {
+    /// Positions `file` according to `options`, or `previous`, the file at
+    /// [`rotated_path`], if the cursor to resume from points into it.
+    async fn new(
+        mut file: File,
+        previous: Option<File>,
+        options: &TailOptions,
+    ) -> io::Result<Self> {
+        let id = file.metadata().await.ok().as_ref().map(file_id);
+        let Some(cursor) = options.resume else {
+            let position = if options.from_start {
+                0
+            } else {
+                file.seek(io::SeekFrom::End(0)).await?
+            };
+            return Ok(Self {
+                file,
+                id,
+                position,
+                rotated: None,
+            });
+        };
+
+        if id == Some(cursor.file_id()) {
+            // Truncated since if it's now shorter
+            let len = file.metadata().await?.len();
+            let position = if len < cursor.offset {
+                0
+            } else {
+                cursor.offset
+            };
+            file.seek(io::SeekFrom::Start(position)).await?;
+            return Ok(Self {
+                file,
+                id,
+                position,
+                rotated: None,
+            });
+        }
+
+        // Rotated since, what's left of the previous file comes first
+        if let Some(mut previous) = previous
+            && let Ok(meta) = previous.metadata().await
+            && file_id(&meta) == cursor.file_id()
+            && meta.len() >= cursor.offset
+        {
+            previous.seek(io::SeekFrom::Start(cursor.offset)).await?;
+            return Ok(Self {
+                file: previous,
+                id: Some(cursor.file_id()),
+                position: cursor.offset,
+                rotated: Some((file, id)),
+            });
+        }
+
+        // Rotated more than once, whatever was left is gone
+        Ok(Self {
+            file,
+            id,
+            position: 0,
+            rotated: None,
+        })
+    }
+}
+
+/// Waits for `path` to be created, checking every `poll_interval`.
+/// Errors other than it not existing are reported once.
+async fn created(
+    path: &Path,
+    poll_interval: Duration,
+    tx: &mpsc::Sender<io::Result<(Vec<u8>, TailCursor)>>,
+) -> Option<File> {
+    let mut reported = false;
+    loop {
+        tokio::select! {
+            () = tx.closed() => return None,
+            () = tokio::time::sleep(poll_interval) => {}
+        }
+        match File::open(path).await {
+            Ok(file) => return Some(file),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) if !reported => {
+                reported = true;
+                if tx.send(Err(e)).await.is_err() {
+                    return None;
+                }
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+/// Follows `path` like `tail -F`, sending every complete line to `tx`,
+/// along with the cursor right after it.
+///
+/// - Resuming from a cursor reads what's left of the file it points into,
+///   which may be `previous`, the rotated file, see [`TailOptions::resume`].
+/// - Truncation (the file shrinking below what was read) restarts reading
+///   from the beginning.
+/// - Rotation (`path` now pointing at another file) drains what's left of
+///   the old file, then switches to the new one from its beginning.
+/// - A new file that can't be opened, e.g. for lack of permissions, is
+///   reported once, then retried silently.
+/// - Lines are sent as bytes, so that those that aren't valid UTF-8
+///   aren't lost, nor the cursor thrown off.
+/// - Without `file`, `path` didn't exist yet. It's read from its beginning
+///   once it's created.
+///
+/// Returns once `tx` is closed, i.e. the plugin dropped its [`Lines`].
+pub async fn follow(
+    path: PathBuf,
+    file: Option<File>,
+    previous: Option<File>,
+    mut options: TailOptions,
+    tx: mpsc::Sender<io::Result<(Vec<u8>, TailCursor)>>,
+) {
+    let file = if let Some(file) = file {
+        file
+    } else {
+        // Everything in it is new
+        options.from_start = true;
+        let Some(file) = created(&path, options.poll_interval, &tx).await
+        else {
+            return;
+        };
+        file
+    };
+    let Start {
+        file,
+        mut id,
+        mut position,
+        mut rotated,
+    } = match Start::new(file, previous, &options).await {
+        Ok(start) => start,
+        Err(e) => {
+            // Nothing more can be done with this file
+            drop(tx.send(Err(e)).await);
+            return;
+        }
+    };
+    let mut reader = BufReader::new(file);
+    let mut line = Vec::new();
+    // File at the path that couldn't be opened, reported already
+    let mut unreadable = None;
+
+    loop {
+        match reader.read_until(b'\n', &mut line).await {
+            Ok(0) => {}
+            Ok(n) => {
+                position += n as u64;
+                // Partial lines are kept until the writer finishes them
+                if line.ends_with(b"\n") {
+                    let mut complete = std::mem::take(&mut line);
+                    complete.pop();
+                    let cursor =
+                        TailCursor::new(id.unwrap_or_default(), position);
+                    if tx.send(Ok((complete, cursor))).await.is_err() {
+                        return;
+                    }
+                }
+                continue;
+            }
+            Err(e) => {
+                if tx.send(Err(e)).await.is_err() {
+                    return;
+                }
+            }
+        }
+
+        // Reached the end of a rotated file, its last line won't be finished
+        if let Some((file, new_id)) = rotated.take() {
+            if !line.is_empty() {
+                let last = std::mem::take(&mut line);
+                let cursor = TailCursor::new(id.unwrap_or_default(), position);
+                if tx.send(Ok((last, cursor))).await.is_err() {
+                    return;
+                }
+            }
+            id = new_id;
+            reader = BufReader::new(file);
+            position = 0;
+            continue;
+        }
+
+        // Wait for more while watching the path
+        tokio::select! {
+            () = tx.closed() => return,
+            () = tokio::time::sleep(options.poll_interval) => {}
+        }
+
+        let Ok(meta) = tokio::fs::metadata(&path).await else {
+            // Rotated away and not recreated yet
+            continue;
+        };
+        let new_id = file_id(&meta);
+        if Some(new_id) != id {
+            // Opened right away so it can't be rotated again under our feet,
+            // but only read once the old file is drained
+            match File::open(&path).await {
+                Ok(file) => {
+                    unreadable = None;
+                    rotated = Some((file, Some(new_id)));
+                }
+                Err(e) if unreadable != Some(new_id) => {
+                    unreadable = Some(new_id);
+                    if tx.send(Err(e)).await.is_err() {
+                        return;
+                    }
+                }
+                Err(_) => {}
+            }
+        } else if meta.len() < position {
+            match reader.seek(io::SeekFrom::Start(0)).await {
+                Ok(_) => {
+                    position = 0;
+                    line.clear();
+                }
+                Err(e) => {
+                    if tx.send(Err(e)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// `line` without a carriage return, invalid UTF-8 replaced
+fn decode(line: &[u8]) -> String {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    String::from_utf8_lossy(line).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    type Followed = mpsc::Receiver<io::Result<(Vec<u8>, TailCursor)>>;
+
+    const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+    /// Follows `path` in the background, from `options`
+    async fn follow_path(path: &Path, options: TailOptions) -> Followed {
+        let file = File::open(path).await.ok();
+        let previous = File::open(rotated_path(path)).await.ok();
+        let (tx, rx) = mpsc::channel(LINES_BUFFER);
+        let options = TailOptions {
+            poll_interval: POLL_INTERVAL,
+            ..options
+        };
+        tokio::spawn(follow(path.to_owned(), file, previous, options, tx));
+        rx
+    }
+
+    /// Next line, decoded as [`Lines::next_line`] does
+    async fn next(rx: &mut Followed) -> (String, TailCursor) {
+        let (line, cursor) =
+            tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .expect("No line in time")
+                .expect("Follower stopped")
+                .expect("Follower failed");
+        (decode(&line), cursor)
+    }
+
+    async fn lines(rx: &mut Followed, n: usize) -> Vec<String> {
+        let mut lines = Vec::new();
+        for _ in 0..n {
+            lines.push(next(rx).await.0);
+        }
+        lines
+    }
+
+    fn append(path: &Path, contents: &str) {
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap()
+            .write_all(contents.as_bytes())
+            .unwrap();
+    }
+
+    const fn from_start() -> TailOptions {
+        TailOptions {
+            from_start: true,
+            resume: None,
+            poll_interval: POLL_INTERVAL,
+        }
+    }
+
+    #[tokio::test]
+    async fn follows_appended_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("auth.log");
+        append(&path, "before\n");
+        let mut rx = follow_path(&path, TailOptions::default()).await;
+        // Once it has seeked to the end
+        tokio::time::sleep(POLL_INTERVAL * 5).await;
+
+        append(&path, "first\npart");
+        assert_eq!(next(&mut rx).await.0, "first");
+        // Partial lines wait to be finished
+        append(&path, "ial\r\n");
+        assert_eq!(next(&mut rx).await.0, "partial");
+    }
+
+    #[tokio::test]
+    async fn reads_from_the_start() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("auth.log");
+        append(&path, "a\nb\n");
+        let mut rx = follow_path(&path, from_start()).await;
+
+        let (line, cursor) = next(&mut rx).await;
+        assert_eq!(line, "a");
+        assert_eq!(cursor.offset, 2);
+        assert_eq!(next(&mut rx).await.1.offset, 4);
+    }
+
+    #[tokio::test]
+    async fn replaces_invalid_utf8() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        std::fs::write(&path, b"caf\xe9\nnext\n").unwrap();
+        let mut rx = follow_path(&path, from_start()).await;
+
+        let (line, cursor) = next(&mut rx).await;
+        assert_eq!(line, "caf\u{fffd}");
+        assert_eq!(cursor.offset, 5);
+        let (line, cursor) = next(&mut rx).await;
+        assert_eq!(line, "next");
+        assert_eq!(cursor.offset, std::fs::metadata(&path).unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn decodes_lines_unless_raw() {
+        let (tx, rx) = mpsc::channel(LINES_BUFFER);
+        for line in [&b"caf\xe9\r"[..], b"\x00\x01\r"] {
+            tx.send(Ok(line.to_vec())).await.unwrap();
+        }
+        drop(tx);
+        let mut lines = Lines::new(Box::new(rx));
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "caf\u{fffd}");
+        let raw = lines.next_raw_line().await.unwrap().unwrap();
+        assert_eq!(raw, b"\x00\x01\r");
+        assert!(lines.next_line().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn resumes_from_a_cursor() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("auth.log");
+        append(&path, "a\nb\n");
+        let mut rx = follow_path(&path, from_start()).await;
+        let (_, cursor) = next(&mut rx).await;
+        drop(rx);
+
+        append(&path, "c\n");
+        let options = TailOptions {
+            resume: Some(cursor),
+            ..TailOptions::default()
+        };
+        let mut rx = follow_path(&path, options).await;
+        assert_eq!(lines(&mut rx, 2).await, ["b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn resumes_into_the_rotated_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("auth.log");
+        append(&path, "a\nb\n");
+        let mut rx = follow_path(&path, from_start()).await;
+        let (_, cursor) = next(&mut rx).await;
+        drop(rx);
+
+        std::fs::rename(&path, rotated_path(&path)).unwrap();
+        append(&path, "c\n");
+        let options = TailOptions {
+            resume: Some(cursor),
+            ..TailOptions::default()
+        };
+        let mut rx = follow_path(&path, options).await;
+        assert_eq!(lines(&mut rx, 2).await, ["b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn restarts_from_the_start_of_a_file_it_cannot_resume() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("auth.log");
+        append(&path, "a\n");
+        let mut rx = follow_path(&path, from_start()).await;
+        let (_, cursor) = next(&mut rx).await;
+        drop(rx);
+
+        // Rotated twice, the file the cursor points into is gone
+        std::fs::remove_file(&path).unwrap();
+        append(&path, "b\n");
+        let options = TailOptions {
+            resume: Some(cursor),
+            ..TailOptions::default()
+        };
+        let mut rx = follow_path(&path, options).await;
+        assert_eq!(next(&mut rx).await.0, "b");
+    }
+
+    #[tokio::test]
+    async fn restarts_after_a_truncation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("auth.log");
+        append(&path, "first line\nsecond line\n");
+        let mut rx = follow_path(&path, from_start()).await;
+        assert_eq!(lines(&mut rx, 2).await, ["first line", "second line"]);
+
+        std::fs::write(&path, "new\n").unwrap();
+        let (line, cursor) = next(&mut rx).await;
+        assert_eq!(line, "new");
+        assert_eq!(cursor.offset, 4);
+    }
+
+    #[tokio::test]
+    async fn drains_the_old_file_on_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("auth.log");
+        append(&path, "a\n");
+        let mut rx = follow_path(&path, from_start()).await;
+        let (_, old) = next(&mut rx).await;
+
+        // Written after the rotation, and never finished
+        std::fs::rename(&path, rotated_path(&path)).unwrap();
+        append(&rotated_path(&path), "b\nunfinished");
+        append(&path, "c\n");
+        assert_eq!(lines(&mut rx, 3).await, ["b", "unfinished", "c"]);
+
+        append(&path, "d\n");
+        let (line, new) = next(&mut rx).await;
+        assert_eq!(line, "d");
+        assert_ne!(new.inode, old.inode);
+        assert_eq!(new.offset, 4);
+    }
+
+    #[tokio::test]
+    async fn waits_for_the_file_to_be_created() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("auth.log");
+        let mut rx = follow_path(&path, TailOptions::default()).await;
+
+        tokio::time::sleep(POLL_INTERVAL * 3).await;
+        append(&path, "a\nb\n");
+        assert_eq!(lines(&mut rx, 2).await, ["a", "b"]);
+    }
+
+    #[test]
+    fn encodes_cursors() {
+        let cursor = TailCursor {
+            device: 1,
+            inode: u64::MAX,
+            offset: 42,
+        };
+        assert_eq!(TailCursor::from_bytes(&cursor.to_bytes()), Some(cursor));
+        assert_eq!(TailCursor::from_bytes(&[0; 8]), None);
+    }
+}
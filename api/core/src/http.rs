@@ -0,0 +1,246 @@
+use std::{fmt, sync::Arc};
+
+use crate::{task::BoxFuture, time::Duration};
+
+/// HTTP client shared by the host, bound to a single plugin.
+///
+/// The host owns the connection pool and TLS stack, and applies the
+/// plugin's network permissions, rate limit, timeouts and retries.
+pub trait HttpClient: Send + Sync + fmt::Debug {
+    /// Send `request`, retrying on connection failures and server errors
+    fn execute(
+        &self,
+        request: HttpRequest,
+    ) -> BoxFuture<Result<HttpResponse, HttpError>>;
+}
+
+/// Type alias for the HTTP client handed to plugins
+pub type HttpClientRef = Arc<dyn HttpClient>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Patch,
+    Delete,
+}
+
+impl Method {
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Get => "GET",
+            Self::Head => "HEAD",
+            Self::Post => "POST",
+            Self::Put => "PUT",
+            Self::Patch => "PATCH",
+            Self::Delete => "DELETE",
+        }
+    }
+
+    /// Parses an uppercase method name
+    #[must_use]
+    pub fn parse(method: &str) -> Option<Self> {
+        Some(match method {
+            "GET" => Self::Get,
+            "HEAD" => Self::Head,
+            "POST" => Self::Post,
+            "PUT" => Self::Put,
+            "PATCH" => Self::Patch,
+            "DELETE" => Self::Delete,
+            _ => return None,
+        })
+    }
+
+    /// Whether the request can safely be sent more than once
+    #[must_use]
+    pub const fn is_idempotent(self) -> bool {
+        !matches!(self, Self::Post | Self::Patch)
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Request sent through the host's [`HttpClient`].
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    pub method: Method,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// Overrides the host's default timeout, for each attempt
+    pub timeout: Option<Duration>,
+    /// Overrides whether the host may retry, which defaults to
+    /// whether the method is idempotent
+    pub retry: Option<bool>,
+}
+
+impl HttpRequest {
+    #[must_use]
+    pub fn new(method: Method, url: impl Into<String>) -> Self {
+        Self {
+            method,
+            url: url.into(),
+            headers: Vec::new(),
+            body: Vec::new(),
+            timeout: None,
+            retry: None,
+        }
+    }
+
+    #[must_use]
+    pub fn get(url: impl Into<String>) -> Self {
+        Self::new(Method::Get, url)
+    }
+
+    #[must_use]
+    pub fn post(url: impl Into<String>) -> Self {
+        Self::new(Method::Post, url)
+    }
+
+    #[must_use]
+    pub fn header(
+        mut self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    #[must_use]
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Sets the body along with a JSON content type
+    #[must_use]
+    pub fn json(self, body: impl Into<Vec<u8>>) -> Self {
+        self.header("Content-Type", "application/json").body(body)
+    }
+
+    #[must_use]
+    pub const fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    #[must_use]
+    pub const fn retry(mut self, retry: bool) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Whether the host may send this request more than once
+    #[must_use]
+    pub fn retryable(&self) -> bool {
+        self.retry.unwrap_or_else(|| self.method.is_idempotent())
+    }
+}
+
+/// Response received through the host's [`HttpClient`].
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    #[must_use]
+    pub const fn new(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    /// `200 OK` with a JSON body
+    #[must_use]
+    pub fn json(body: impl Into<Vec<u8>>) -> Self {
+        Self::new(200)
+            .with_header("Content-Type", "application/json")
+            .with_body(body)
+    }
+
+    /// `200 OK` with a plain text body
+    #[must_use]
+    pub fn text_body(body: impl Into<String>) -> Self {
+        Self::new(200)
+            .with_header("Content-Type", "text/plain; charset=utf-8")
+            .with_body(body.into())
+    }
+
+    #[must_use]
+    pub fn with_header(
+        mut self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    #[must_use]
+    pub fn with_body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    #[must_use]
+    pub const fn is_success(&self) -> bool {
+        self.status >= 200 && self.status < 300
+    }
+
+    /// First value of the header `name`, compared case-insensitively
+    #[must_use]
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Body as text, replacing invalid UTF-8
+    #[must_use]
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+/// Error returned by [`HttpClient`] requests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HttpError {
+    /// The host wasn't configured with an HTTP client
+    Unavailable,
+    /// The plugin isn't allowed to reach this host
+    Denied(String),
+    /// The URL or a header was invalid
+    InvalidRequest(String),
+    /// No response arrived in time, even after retrying
+    Timeout,
+    /// The connection failed, even after retrying
+    Request(String),
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unavailable => f.write_str("HTTP client is unavailable"),
+            Self::Denied(host) => write!(f, "not allowed to reach `{host}`"),
+            Self::InvalidRequest(e) => write!(f, "invalid request: {e}"),
+            Self::Timeout => f.write_str("request timed out"),
+            Self::Request(e) => write!(f, "request failed: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for HttpError {}
@@ -1,12 +1,33 @@
+mod cancellation;
+mod fs;
+mod http;
+mod permissions;
 mod plugin;
+mod process;
+mod routes;
 mod runtime;
+mod storage;
+mod task;
+mod time;
+mod tls;
+mod unix;
 
+pub use cancellation::*;
+pub use fs::{LineSource, Lines, TailCursor, TailOptions};
+pub use http::*;
+pub use permissions::Permissions;
 pub use plugin::*;
+pub use process::{ChildProcess, ProcessCommand, ProcessOutput};
+pub use routes::*;
 pub use runtime::*;
+pub use storage::*;
+pub use task::{
+    BoxBlocking, BoxFuture, BoxedOutput, RawTask, TaskControl, TaskError,
+    TaskGroup, TaskHandle, TaskInfo, TaskSpec,
+};
+pub use time::*;
 
 pub use nexus_api_macros::plugin as r#impl;
 
 // Re-exports for macro-generated code convenience
-pub use tokio::time::{Duration, Instant, Sleep};
-pub use tokio::task;
 pub use async_trait::async_trait;
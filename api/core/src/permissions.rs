@@ -0,0 +1,333 @@
+use std::{
+    io,
+    net::Ipv6Addr,
+    path::{Path, PathBuf},
+};
+
+/// What a plugin is allowed to do through its runtime handle.
+/// Everything is denied unless the host configuration says otherwise.
+#[derive(Debug, Clone, Default)]
+pub struct Permissions {
+    /// Canonical directories (or files) the plugin may read from
+    pub read: Vec<PathBuf>,
+    /// Programs the plugin may spawn, by name or absolute path
+    pub exec: Vec<String>,
+    /// Hosts the plugin may connect to, optionally with a port
+    /// (`example.com:443`), or a `*.` wildcard for subdomains
+    pub network: Vec<String>,
+    /// Canonical Unix sockets the plugin may send HTTP requests to
+    pub sockets: Vec<PathBuf>,
+}
+
+impl Permissions {
+    /// Whether `path`, which must already be canonical, may be read
+    #[must_use]
+    pub fn can_read(&self, path: &Path) -> bool {
+        self.read.iter().any(|root| path.starts_with(root))
+    }
+
+    /// Whether the socket at `path`, which must already be canonical, may
+    /// be used
+    #[must_use]
+    pub fn can_use_socket(&self, path: &Path) -> bool {
+        self.sockets.iter().any(|socket| socket == path)
+    }
+
+    /// Whether `program` may be spawned
+    #[must_use]
+    pub fn can_exec(&self, program: &str) -> bool {
+        self.exec.iter().any(|p| p == program)
+    }
+
+    /// Whether `host` may be reached on `port`
+    #[must_use]
+    pub fn can_connect(&self, host: &str, port: u16) -> bool {
+        self.allows_host(host, Some(port))
+    }
+
+    /// Whether `host` may be resolved, i.e. reached on any port
+    #[must_use]
+    pub fn can_resolve(&self, host: &str) -> bool {
+        self.allows_host(host, None)
+    }
+
+    /// Whether `host` is allowed, on `port` unless it's `None`
+    fn allows_host(&self, host: &str, port: Option<u16>) -> bool {
+        let host = host.to_ascii_lowercase();
+        self.network.iter().any(|allowed| {
+            let allowed = allowed.to_ascii_lowercase();
+            // IPv6 addresses are bracketed, as in URLs
+            let (allowed, allowed_port) = match allowed.rsplit_once(':') {
+                Some((host, port))
+                    if !host.contains(':') || host.ends_with(']') =>
+                {
+                    (host, Some(port.parse::<u16>().ok()))
+                }
+                _ => (allowed.as_str(), None),
+            };
+            let host_matches = allowed.strip_prefix("*.").map_or_else(
+                || allowed == host,
+                |domain| {
+                    host.strip_suffix(domain)
+                        .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.'))
+                },
+            );
+            host_matches
+                && port.is_none_or(|port| {
+                    allowed_port.is_none_or(|p| p == Some(port))
+                })
+        })
+    }
+
+    /// Canonicalizes `path` and checks it may be read.
+    /// Paths that don't exist yet are resolved from their parent directory,
+    /// so that files can be followed before they are created.
+    pub(crate) async fn check_read(
+        &self,
+        plugin: &str,
+        path: &Path,
+    ) -> io::Result<PathBuf> {
+        let canonical = match tokio::fs::canonicalize(path).await {
+            Ok(canonical) => canonical,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let (Some(parent), Some(name)) =
+                    (path.parent(), path.file_name())
+                else {
+                    return Err(e);
+                };
+                let parent = if parent.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    parent
+                };
+                tokio::fs::canonicalize(parent).await?.join(name)
+            }
+            Err(e) => return Err(e),
+        };
+
+        if self.can_read(&canonical) {
+            Ok(canonical)
+        } else {
+            Err(denied(plugin, format_args!("read `{}`", path.display())))
+        }
+    }
+
+    pub(crate) fn check_connect(
+        &self,
+        plugin: &str,
+        host: &str,
+        port: u16,
+    ) -> io::Result<()> {
+        if self.can_connect(&bracketed(host), port) {
+            Ok(())
+        } else {
+            Err(denied(plugin, format_args!("connect to `{host}:{port}`")))
+        }
+    }
+
+    pub(crate) fn check_resolve(
+        &self,
+        plugin: &str,
+        host: &str,
+    ) -> io::Result<()> {
+        if self.can_resolve(host) {
+            Ok(())
+        } else {
+            Err(denied(plugin, format_args!("resolve `{host}`")))
+        }
+    }
+
+    /// Canonicalizes `path` and checks the socket may be used
+    pub(crate) async fn check_socket(
+        &self,
+        plugin: &str,
+        path: &Path,
+    ) -> io::Result<PathBuf> {
+        let canonical = tokio::fs::canonicalize(path).await?;
+        if self.can_use_socket(&canonical) {
+            Ok(canonical)
+        } else {
+            let action = format_args!("use the socket `{}`", path.display());
+            Err(denied(plugin, action))
+        }
+    }
+
+    pub(crate) fn check_exec(
+        &self,
+        plugin: &str,
+        program: &str,
+    ) -> io::Result<()> {
+        if self.can_exec(program) {
+            Ok(())
+        } else {
+            Err(denied(plugin, format_args!("execute `{program}`")))
+        }
+    }
+}
+
+/// IPv6 addresses as they're written in permissions
+fn bracketed(host: &str) -> String {
+    if host.parse::<Ipv6Addr>().is_ok() {
+        format!("[{host}]")
+    } else {
+        host.to_owned()
+    }
+}
+
+fn denied(plugin: &str, action: std::fmt::Arguments<'_>) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!("Plugin `{plugin}` is not allowed to {action}"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn network(hosts: &[&str]) -> Permissions {
+        Permissions {
+            network: hosts.iter().map(ToString::to_string).collect(),
+            ..Permissions::default()
+        }
+    }
+
+    #[test]
+    fn matches_exact_hosts() {
+        let permissions = network(&["example.com", "api.example.org:443"]);
+        assert!(permissions.can_connect("example.com", 80));
+        assert!(permissions.can_resolve("example.com"));
+        assert!(permissions.can_connect("api.example.org", 443));
+        assert!(!permissions.can_connect("api.example.org", 80));
+        // Any port of it may be reached, so it may be resolved
+        assert!(permissions.can_resolve("api.example.org"));
+
+        assert!(!permissions.can_connect("www.example.com", 80));
+        assert!(!permissions.can_connect("example.com.evil.test", 80));
+        assert!(!permissions.can_connect("evilexample.com", 80));
+        assert!(!permissions.can_resolve("example.org"));
+    }
+
+    #[test]
+    fn matches_subdomains_of_wildcards() {
+        let permissions = network(&["*.example.com", "*.example.org:8443"]);
+        assert!(permissions.can_connect("api.example.com", 443));
+        assert!(permissions.can_connect("a.b.example.com", 443));
+        assert!(permissions.can_connect("api.example.org", 8443));
+        assert!(!permissions.can_connect("api.example.org", 443));
+
+        // Only subdomains
+        assert!(!permissions.can_connect("example.com", 443));
+        assert!(!permissions.can_connect(".example.com", 443));
+        assert!(!permissions.can_connect("evilexample.com", 443));
+        assert!(!permissions.can_connect("api.example.com.evil.test", 443));
+    }
+
+    #[test]
+    fn ignores_case() {
+        let permissions = network(&["Example.COM", "*.Example.org"]);
+        assert!(permissions.can_connect("EXAMPLE.com", 443));
+        assert!(permissions.can_connect("API.example.ORG", 443));
+    }
+
+    #[test]
+    fn matches_addresses() {
+        let permissions =
+            network(&["127.0.0.1:9100", "[::1]:9100", "[2001:db8::1]"]);
+        assert!(permissions.can_connect("127.0.0.1", 9100));
+        assert!(!permissions.can_connect("127.0.0.1", 22));
+        assert!(!permissions.can_connect("127.0.0.2", 9100));
+
+        // Bracketed when checked, as they're written
+        assert!(permissions.check_connect("test", "::1", 9100).is_ok());
+        assert!(permissions.check_connect("test", "::1", 22).is_err());
+        assert!(permissions.check_connect("test", "2001:db8::1", 22).is_ok());
+        assert!(
+            permissions
+                .check_connect("test", "2001:db8::2", 22)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn denies_malformed_ports() {
+        let permissions = network(&["example.com:https"]);
+        assert!(!permissions.can_connect("example.com", 443));
+    }
+
+    #[test]
+    fn denies_everything_by_default() {
+        let permissions = Permissions::default();
+        assert!(!permissions.can_resolve("localhost"));
+        assert!(!permissions.can_exec("sh"));
+        assert!(!permissions.can_read(Path::new("/")));
+        assert!(!permissions.can_use_socket(Path::new("/run/docker.sock")));
+    }
+
+    #[test]
+    fn allows_listed_programs() {
+        let permissions = Permissions {
+            exec: vec!["journalctl".into(), "/usr/bin/systemctl".into()],
+            ..Permissions::default()
+        };
+        assert!(permissions.check_exec("test", "journalctl").is_ok());
+        assert!(permissions.check_exec("test", "/usr/bin/systemctl").is_ok());
+
+        let denied = permissions.check_exec("test", "systemctl").unwrap_err();
+        assert_eq!(denied.kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(
+            denied.to_string(),
+            "Plugin `test` is not allowed to execute `systemctl`"
+        );
+        assert!(permissions.check_exec("test", "/bin/journalctl").is_err());
+        assert!(permissions.check_exec("test", "journalctl ").is_err());
+    }
+
+    #[tokio::test]
+    async fn reads_only_under_allowed_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().canonicalize().unwrap();
+        let (logs, secrets) = (dir.join("logs"), dir.join("secrets"));
+        std::fs::create_dir(&logs).unwrap();
+        std::fs::create_dir(&secrets).unwrap();
+        std::fs::write(logs.join("auth.log"), "").unwrap();
+        std::fs::write(secrets.join("key"), "").unwrap();
+        std::os::unix::fs::symlink(secrets.join("key"), logs.join("key"))
+            .unwrap();
+        std::os::unix::fs::symlink(&secrets, logs.join("secrets")).unwrap();
+        let permissions = Permissions {
+            read: vec![logs.clone()],
+            ..Permissions::default()
+        };
+        let check = |path: PathBuf| {
+            let permissions = permissions.clone();
+            async move { permissions.check_read("test", &path).await }
+        };
+
+        let allowed = check(logs.join("auth.log")).await.unwrap();
+        assert_eq!(allowed, logs.join("auth.log"));
+        // Not created yet
+        let missing = check(logs.join("syslog")).await.unwrap();
+        assert_eq!(missing, logs.join("syslog"));
+        let within = check(logs.join("../logs/auth.log")).await.unwrap();
+        assert_eq!(within, logs.join("auth.log"));
+
+        for escaping in [
+            logs.join("../secrets/key"),
+            logs.join("../secrets/missing"),
+            logs.join("key"),
+            logs.join("secrets/key"),
+            logs.join("secrets/missing"),
+            logs.join(".."),
+        ] {
+            let denied = check(escaping.clone()).await;
+            let kind = denied.map_err(|e| e.kind());
+            assert_eq!(
+                kind,
+                Err(io::ErrorKind::PermissionDenied),
+                "{}",
+                escaping.display()
+            );
+        }
+    }
+}
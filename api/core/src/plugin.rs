@@ -14,7 +14,7 @@ pub struct Meta {
 #[async_trait]
 pub trait Plugin: Send + Sync {
     /// Initialize the plugin with a runtime handle.
-    /// Called once before main() by the loader.
+    /// Called once before `main()` by the loader.
     fn init(&mut self, runtime: RuntimeRef);
 
     /// Main plugin entry point.
@@ -0,0 +1,267 @@
+use std::{ffi::OsString, fmt, io, path::PathBuf, process::ExitStatus};
+
+use crate::{
+    cancellation::CancellationToken, fs::Lines, task::BoxFuture, time::Duration,
+};
+
+/// Process to be spawned by the host on behalf of a plugin.
+#[derive(Debug, Clone)]
+pub struct ProcessCommand {
+    pub program: String,
+    pub args: Vec<OsString>,
+    pub envs: Vec<(OsString, OsString)>,
+    pub current_dir: Option<PathBuf>,
+    /// Killed once this much time has passed
+    pub timeout: Option<Duration>,
+    /// Killed when its [`ChildProcess`] is dropped
+    pub kill_on_drop: bool,
+}
+
+impl ProcessCommand {
+    #[must_use]
+    pub fn new(program: impl Into<String>) -> Self {
+        Self {
+            program: program.into(),
+            args: Vec::new(),
+            envs: Vec::new(),
+            current_dir: None,
+            timeout: None,
+            kill_on_drop: true,
+        }
+    }
+
+    #[must_use]
+    pub fn arg(mut self, arg: impl Into<OsString>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    #[must_use]
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<OsString>, {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    #[must_use]
+    pub fn env(
+        mut self,
+        key: impl Into<OsString>,
+        value: impl Into<OsString>,
+    ) -> Self {
+        self.envs.push((key.into(), value.into()));
+        self
+    }
+
+    #[must_use]
+    pub fn current_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.current_dir = Some(dir.into());
+        self
+    }
+
+    #[must_use]
+    pub const fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    #[must_use]
+    pub const fn kill_on_drop(mut self, kill_on_drop: bool) -> Self {
+        self.kill_on_drop = kill_on_drop;
+        self
+    }
+
+    /// Builds the tokio command. Only ever called host-side.
+    pub(crate) fn to_tokio(&self) -> tokio::process::Command {
+        let mut command = tokio::process::Command::new(&self.program);
+        command
+            .args(&self.args)
+            .envs(self.envs.iter().map(|(k, v)| (k, v)))
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(dir) = &self.current_dir {
+            command.current_dir(dir);
+        }
+        command
+    }
+}
+
+/// Everything a finished process wrote, as captured by `run_process`.
+#[derive(Debug, Clone)]
+pub struct ProcessOutput {
+    pub status: ExitStatus,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+/// Process running under the host, as returned by `spawn_process`.
+pub struct ChildProcess {
+    pub(crate) id: Option<u32>,
+    pub(crate) stdout: Option<Lines>,
+    pub(crate) stderr: Option<Lines>,
+    pub(crate) status: Option<BoxFuture<io::Result<ExitStatus>>>,
+    pub(crate) kill: CancellationToken,
+    pub(crate) kill_on_drop: bool,
+}
+
+impl ChildProcess {
+    /// OS identifier of the process, if it's still running
+    #[must_use]
+    pub const fn id(&self) -> Option<u32> {
+        self.id
+    }
+
+    /// Lines the process writes to its stdout
+    pub const fn stdout(&mut self) -> Option<&mut Lines> {
+        self.stdout.as_mut()
+    }
+
+    /// Lines the process writes to its stderr
+    pub const fn stderr(&mut self) -> Option<&mut Lines> {
+        self.stderr.as_mut()
+    }
+
+    /// Takes ownership of the stdout lines
+    pub const fn take_stdout(&mut self) -> Option<Lines> {
+        self.stdout.take()
+    }
+
+    /// Takes ownership of the stderr lines
+    pub const fn take_stderr(&mut self) -> Option<Lines> {
+        self.stderr.take()
+    }
+
+    /// Asks the host to kill the process
+    pub fn kill(&self) {
+        self.kill.cancel();
+    }
+
+    /// Waits for the process to exit
+    ///
+    /// # Errors
+    /// If waiting failed, or if the status was already consumed.
+    pub async fn wait(&mut self) -> io::Result<ExitStatus> {
+        match self.status.take() {
+            Some(status) => status.await,
+            None => Err(io::Error::other("Process status already consumed")),
+        }
+    }
+}
+
+impl Drop for ChildProcess {
+    fn drop(&mut self) {
+        if self.kill_on_drop {
+            self.kill.cancel();
+        }
+    }
+}
+
+impl fmt::Debug for ChildProcess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChildProcess")
+            .field("id", &self.id)
+            .field("kill_on_drop", &self.kill_on_drop)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::{Permissions, RuntimeHandle, TokioRuntimeHandle};
+
+    fn runtime() -> TokioRuntimeHandle {
+        let handle = tokio::runtime::Handle::current();
+        TokioRuntimeHandle::new(handle, "test", CancellationToken::new())
+            .with_permissions(Permissions {
+                exec: vec!["sh".into()],
+                ..Permissions::default()
+            })
+    }
+
+    /// Creates `marker` after a second, unless it's killed first
+    fn late(marker: &Path) -> ProcessCommand {
+        ProcessCommand::new("sh")
+            .args(["-c", r#"sleep 1; touch "$1""#, "sh"])
+            .arg(marker)
+            .timeout(Duration::from_millis(100))
+    }
+
+    /// Whether `marker` was created, once it would have been
+    async fn created(marker: &Path) -> bool {
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        marker.exists()
+    }
+
+    #[tokio::test]
+    async fn captures_output() {
+        let command = ProcessCommand::new("sh")
+            .args(["-c", r#"echo "$GREETING"; echo err >&2; exit 3"#])
+            .env("GREETING", "hello");
+        let output = runtime().run_process(command).await.unwrap();
+        assert_eq!(output.status.code(), Some(3));
+        assert_eq!(output.stdout, b"hello\n");
+        assert_eq!(output.stderr, b"err\n");
+    }
+
+    #[tokio::test]
+    async fn runs_only_allowed_programs() {
+        let runtime = runtime();
+        let denied = runtime.run_process(ProcessCommand::new("ls")).await;
+        let kind = denied.map(|_| ()).map_err(|e| e.kind());
+        assert_eq!(kind, Err(io::ErrorKind::PermissionDenied));
+        let denied = runtime.spawn_process(ProcessCommand::new("/bin/sh"));
+        let kind = denied.map(|_| ()).map_err(|e| e.kind());
+        assert_eq!(kind, Err(io::ErrorKind::PermissionDenied));
+    }
+
+    #[tokio::test]
+    async fn kills_runs_that_time_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("marker");
+        let timed_out = runtime().run_process(late(&marker)).await;
+        let kind = timed_out.map(|_| ()).map_err(|e| e.kind());
+        assert_eq!(kind, Err(io::ErrorKind::TimedOut));
+        assert!(!created(&marker).await);
+    }
+
+    #[tokio::test]
+    async fn kills_processes_that_time_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("marker");
+        let mut child = runtime().spawn_process(late(&marker)).unwrap();
+        let kind = child.wait().await.map_err(|e| e.kind());
+        assert_eq!(kind, Err(io::ErrorKind::TimedOut));
+        assert!(!created(&marker).await);
+    }
+
+    #[tokio::test]
+    async fn kills_processes_on_request() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("marker");
+        let command = late(&marker).timeout(Duration::from_secs(30));
+        let mut child = runtime().spawn_process(command).unwrap();
+        child.kill();
+        let kind = child.wait().await.map_err(|e| e.kind());
+        assert_eq!(kind, Err(io::ErrorKind::Interrupted));
+        assert!(!created(&marker).await);
+    }
+
+    #[tokio::test]
+    async fn streams_output_lines() {
+        let command = ProcessCommand::new("sh").args(["-c", "printf 'a\\nb'"]);
+        let mut child = runtime().spawn_process(command).unwrap();
+        let stdout = child.stdout().unwrap();
+        assert_eq!(stdout.next_line().await.unwrap().unwrap(), "a");
+        // The last line, even without its `\n`
+        assert_eq!(stdout.next_line().await.unwrap().unwrap(), "b");
+        assert!(stdout.next_line().await.is_none());
+        assert!(child.wait().await.unwrap().success());
+    }
+}
@@ -0,0 +1,173 @@
+use std::{fmt, future::Future, sync::Arc};
+
+use crate::{
+    http::{HttpResponse, Method},
+    task::BoxFuture,
+};
+
+/// Request received by the host's HTTP server for a plugin route.
+#[derive(Debug, Clone)]
+pub struct IncomingRequest {
+    pub method: Method,
+    /// Path below `/plugins/<name>`, always starting with `/`
+    pub path: String,
+    pub query: Option<String>,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl IncomingRequest {
+    /// First value of the header `name`, compared case-insensitively
+    #[must_use]
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Value of the query parameter `name`, percent-decoded
+    #[must_use]
+    pub fn query_param(&self, name: &str) -> Option<String> {
+        self.query.as_deref()?.split('&').find_map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(key) == name).then(|| decode(value))
+        })
+    }
+}
+
+/// Decodes `%XX` escapes and `+` as used in query strings.
+/// Invalid escapes are kept as they are.
+fn decode(encoded: &str) -> String {
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut rest = encoded.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = tail;
+        match byte {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex = rest
+                    .get(..2)
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                if let Some(decoded) = hex {
+                    bytes.push(decoded);
+                    rest = &rest[2..];
+                } else {
+                    bytes.push(b'%');
+                }
+            }
+            byte => bytes.push(byte),
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Type alias for the handler of a plugin route
+pub type RouteHandler =
+    Arc<dyn Fn(IncomingRequest) -> BoxFuture<HttpResponse> + Send + Sync>;
+
+/// Route served under `/plugins/<name>`.
+///
+/// `path` is either exact (`/status`) or ends with `/*`, which matches
+/// anything below it (`/history/*` matches `/history/cpu`).
+///
+/// `GET /metrics` routes are also scraped by the host's own `/metrics`,
+/// so they should answer in the Prometheus text format.
+#[derive(Clone)]
+pub struct Route {
+    pub method: Method,
+    pub path: String,
+    pub handler: RouteHandler,
+}
+
+impl Route {
+    pub fn new<F, Fut>(
+        method: Method,
+        path: impl Into<String>,
+        handler: F,
+    ) -> Self
+    where
+        F: Fn(IncomingRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HttpResponse> + Send + 'static, {
+        Self {
+            method,
+            path: path.into(),
+            handler: Arc::new(move |request| Box::pin(handler(request))),
+        }
+    }
+
+    /// Whether `path` is served by this route
+    #[must_use]
+    pub fn matches(&self, path: &str) -> bool {
+        self.path.strip_suffix("/*").map_or_else(
+            || self.path == path,
+            |prefix| {
+                path.strip_prefix(prefix).is_some_and(|rest| {
+                    rest.is_empty() || rest.starts_with('/')
+                })
+            },
+        )
+    }
+
+    /// Checks the path is absolute and only uses a trailing wildcard
+    ///
+    /// # Errors
+    /// If it isn't.
+    pub fn validate(&self) -> Result<(), RouteError> {
+        let without_wildcard =
+            self.path.strip_suffix("/*").unwrap_or(&self.path);
+        if !self.path.starts_with('/') || without_wildcard.contains('*') {
+            return Err(RouteError::InvalidPath(self.path.clone()));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Route {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Route")
+            .field("method", &self.method)
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Routes of every plugin, served by the host.
+pub trait RouteTable: Send + Sync + fmt::Debug {
+    /// Serve `route` under `/plugins/<plugin>`
+    ///
+    /// # Errors
+    /// If the path is invalid or already registered for the same method.
+    fn register(&self, plugin: &str, route: Route) -> Result<(), RouteError>;
+
+    /// Stop serving every route of `plugin`
+    fn unregister(&self, plugin: &str);
+}
+
+/// Type alias for the route table shared by the host
+pub type RouteTableRef = Arc<dyn RouteTable>;
+
+/// Error returned when registering a [`Route`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RouteError {
+    /// The host isn't serving HTTP
+    Unavailable,
+    InvalidPath(String),
+    /// The same method and path are already registered
+    Conflict(String),
+}
+
+impl fmt::Display for RouteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unavailable => f.write_str("HTTP server is disabled"),
+            Self::InvalidPath(path) => write!(f, "invalid route path `{path}`"),
+            Self::Conflict(route) => {
+                write!(f, "route `{route}` already exists")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RouteError {}
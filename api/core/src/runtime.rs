@@ -1,52 +1,576 @@
-use std::future::Future;
-use std::pin::Pin;
-use std::sync::Arc;
-use tokio::time::Duration;
+use std::{
+    future::Future,
+    io,
+    net::{IpAddr, SocketAddr},
+    path::Path,
+    sync::Arc,
+};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, BufReader},
+    sync::{mpsc, oneshot},
+};
+
+use crate::cancellation::CancellationToken;
+use crate::fs::{self, Lines, TailOptions};
+use crate::http::{HttpClientRef, HttpError, HttpRequest, HttpResponse};
+use crate::permissions::Permissions;
+use crate::process::{ChildProcess, ProcessCommand, ProcessOutput};
+use crate::routes::{Route, RouteError, RouteTableRef};
+use crate::storage::{StorageError, StorageRef};
+use crate::task::{
+    BoxBlocking, BoxFuture, BoxedOutput, RawTask, Spawner, TaskHandle,
+    TaskInfo, TaskSpec,
+};
+use crate::time::{Duration, Elapsed, Instant, Sleep};
+use crate::{tls, unix};
 
 /// Trait for accessing the runtime from plugins.
 /// This avoids TLS issues by passing the runtime handle explicitly.
+///
+/// Everything generic lives on `dyn RuntimeHandle` itself (see `task.rs`),
+/// the trait only ever sees boxed values. Every future it returns is created
+/// and driven by the host, so plugins never touch tokio's globals: timers,
+/// IO and task handles all belong to the host's runtime.
 pub trait RuntimeHandle: Send + Sync + std::fmt::Debug {
+    /// Name of the plugin this handle was created for
+    fn plugin_name(&self) -> &str;
+
     /// Spawn a new task on the runtime
-    fn spawn(
+    fn spawn_boxed(
+        &self,
+        spec: TaskSpec,
+        future: BoxFuture<BoxedOutput>,
+    ) -> RawTask;
+
+    /// Run a blocking closure on the runtime's blocking pool
+    fn spawn_blocking_boxed(
         &self,
-        future: Pin<Box<dyn Future<Output = ()> + Send>>,
-    ) -> tokio::task::JoinHandle<()>;
+        spec: TaskSpec,
+        task: BoxBlocking,
+    ) -> RawTask;
+
+    /// Tasks spawned through this handle that are still running
+    fn tasks(&self) -> Vec<TaskInfo>;
+
+    /// Abort the tasks of `group`, or every task of the plugin if `None`.
+    /// Returns how many tasks were aborted.
+    fn abort_tasks(&self, group: Option<&str>) -> usize;
 
     /// Create a sleep future
-    fn sleep(&self, duration: Duration) -> tokio::time::Sleep;
+    fn sleep(&self, duration: Duration) -> Sleep;
+
+    /// Create a sleep future that completes at `deadline`
+    fn sleep_until(&self, deadline: Instant) -> Sleep;
+
+    /// Get the current time, as seen by the host's clock
+    fn now(&self) -> Instant;
+
+    /// Run `future` for at most `duration`
+    fn timeout_boxed(
+        &self,
+        duration: Duration,
+        future: BoxFuture<BoxedOutput>,
+    ) -> BoxFuture<Result<BoxedOutput, Elapsed>>;
+
+    /// Token that fires when the plugin should stop,
+    /// i.e. on host shutdown, unload or reload.
+    fn cancellation(&self) -> &CancellationToken;
+
+    /// Settings of the plugin, as the TOML source of its
+    /// `[plugins.<name>.settings]` table in the host's configuration
+    fn settings(&self) -> Option<&str>;
+
+    /// Read a whole file, if the plugin is allowed to
+    fn read_file(&self, path: &Path) -> BoxFuture<io::Result<Vec<u8>>>;
+
+    /// Follow a file like `tail -F`, surviving truncation and rotation.
+    /// A file that doesn't exist yet is followed once it's created.
+    fn tail_file(
+        &self,
+        path: &Path,
+        options: TailOptions,
+    ) -> BoxFuture<io::Result<Lines>>;
+
+    /// Run a process to completion, capturing its stdout and stderr
+    fn run_process(
+        &self,
+        command: ProcessCommand,
+    ) -> BoxFuture<io::Result<ProcessOutput>>;
+
+    /// Spawn a process, streaming its stdout and stderr
+    ///
+    /// # Errors
+    /// If the plugin isn't allowed to, or the process failed to start.
+    fn spawn_process(
+        &self,
+        command: ProcessCommand,
+    ) -> io::Result<ChildProcess>;
+
+    /// Key-value store of the plugin, kept across restarts
+    ///
+    /// # Errors
+    /// If the host wasn't configured with a store.
+    fn storage(&self) -> Result<StorageRef, StorageError>;
+
+    /// HTTP client shared by the host
+    ///
+    /// # Errors
+    /// If the host wasn't configured with an HTTP client.
+    fn http(&self) -> Result<HttpClientRef, HttpError>;
+
+    /// Send `request` to the HTTP server listening on the Unix socket at
+    /// `socket`, e.g. Docker's. Only the path and query of its URL are used.
+    /// It's sent once, as daemons are local.
+    fn unix_request(
+        &self,
+        socket: &Path,
+        request: HttpRequest,
+    ) -> BoxFuture<Result<HttpResponse, HttpError>>;
+
+    /// Open a TCP connection to `host` on `port`, and close it right away.
+    /// Returns the address that answered.
+    fn connect_tcp(
+        &self,
+        host: &str,
+        port: u16,
+    ) -> BoxFuture<io::Result<SocketAddr>>;
+
+    /// Resolve `host` to its addresses, through the system's resolver
+    fn resolve(&self, host: &str) -> BoxFuture<io::Result<Vec<IpAddr>>>;
+
+    /// Connect to `host` on `port` over TLS, with `server_name` as SNI
+    /// (`host` if none), and return the certificates it presented, leaf
+    /// first, DER-encoded. They aren't verified, so that expired or
+    /// self-signed ones can be inspected.
+    fn tls_certificates(
+        &self,
+        host: &str,
+        port: u16,
+        server_name: Option<&str>,
+    ) -> BoxFuture<io::Result<Vec<Vec<u8>>>>;
 
-    /// Get the current time
-    fn now(&self) -> tokio::time::Instant;
+    /// Serve `route` under `/plugins/<name>` on the host's HTTP server.
+    /// Each request is handled in a task of the plugin.
+    ///
+    /// # Errors
+    /// If the server is disabled, or the route is invalid or taken.
+    fn register_route(&self, route: Route) -> Result<(), RouteError>;
 }
 
 /// Wrapper around tokio's runtime handle
 #[derive(Debug)]
 pub struct TokioRuntimeHandle {
-    handle: tokio::runtime::Handle,
+    spawner: Spawner,
+    cancellation: CancellationToken,
+    settings: Option<String>,
+    permissions: Arc<Permissions>,
+    storage: Option<StorageRef>,
+    http: Option<HttpClientRef>,
+    routes: Option<RouteTableRef>,
 }
 
 impl TokioRuntimeHandle {
-    pub fn new(handle: tokio::runtime::Handle) -> Self {
-        Self { handle }
+    #[must_use]
+    pub fn new(
+        handle: tokio::runtime::Handle,
+        plugin_name: &str,
+        cancellation: CancellationToken,
+    ) -> Self {
+        Self {
+            spawner: Spawner::new(handle, plugin_name),
+            cancellation,
+            settings: None,
+            permissions: Arc::default(),
+            storage: None,
+            http: None,
+            routes: None,
+        }
+    }
+
+    /// Hands the plugin its settings, in TOML
+    #[must_use]
+    pub fn with_settings(mut self, settings: String) -> Self {
+        self.settings = Some(settings);
+        self
+    }
+
+    /// Replaces the default permissions, which deny everything
+    #[must_use]
+    pub fn with_permissions(mut self, permissions: Permissions) -> Self {
+        self.permissions = Arc::new(permissions);
+        self
+    }
+
+    /// Gives the plugin a key-value store
+    #[must_use]
+    pub fn with_storage(mut self, storage: StorageRef) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// Gives the plugin an HTTP client
+    #[must_use]
+    pub fn with_http(mut self, http: HttpClientRef) -> Self {
+        self.http = Some(http);
+        self
+    }
+
+    /// Lets the plugin serve routes on the host's HTTP server
+    #[must_use]
+    pub fn with_routes(mut self, routes: RouteTableRef) -> Self {
+        self.routes = Some(routes);
+        self
+    }
+
+    /// Builds `future` within the host's runtime context, so that any timer
+    /// or IO resource it creates is registered with the host's drivers.
+    fn host_future<F>(
+        &self,
+        future: impl FnOnce() -> F,
+    ) -> BoxFuture<F::Output>
+    where
+        F: Future + Send + 'static, {
+        let _guard = self.spawner.handle().enter();
+        Box::pin(future())
+    }
+
+    /// Forwards the lines of `reader` to a [`Lines`] from a host task.
+    /// Read as bytes, so that output that isn't UTF-8 can still be read.
+    fn forward_lines<R>(&self, name: &str, mut reader: R) -> Lines
+    where
+        R: AsyncBufRead + Unpin + Send + 'static, {
+        let (tx, rx) = mpsc::channel(fs::LINES_BUFFER);
+        self.spawner.spawn_detached(name, async move {
+            loop {
+                let mut line = Vec::new();
+                let read = tokio::select! {
+                    () = tx.closed() => return,
+                    read = reader.read_until(b'\n', &mut line) => read,
+                };
+                let line = match read {
+                    Ok(0) => return,
+                    Ok(_) => {
+                        if line.ends_with(b"\n") {
+                            line.pop();
+                        }
+                        Ok(line)
+                    }
+                    Err(e) => Err(e),
+                };
+                if tx.send(line).await.is_err() {
+                    return;
+                }
+            }
+        });
+        Lines::new(Box::new(rx))
     }
 }
 
 impl RuntimeHandle for TokioRuntimeHandle {
-    fn spawn(
+    fn plugin_name(&self) -> &str {
+        self.spawner.plugin_name()
+    }
+
+    fn spawn_boxed(
+        &self,
+        spec: TaskSpec,
+        future: BoxFuture<BoxedOutput>,
+    ) -> RawTask {
+        self.spawner.spawn(spec, future)
+    }
+
+    fn spawn_blocking_boxed(
         &self,
-        future: Pin<Box<dyn Future<Output = ()> + Send>>,
-    ) -> tokio::task::JoinHandle<()> {
-        self.handle.spawn(future)
+        spec: TaskSpec,
+        task: BoxBlocking,
+    ) -> RawTask {
+        self.spawner.spawn_blocking(spec, task)
     }
 
-    fn sleep(&self, duration: Duration) -> tokio::time::Sleep {
-        tokio::time::sleep(duration)
+    fn tasks(&self) -> Vec<TaskInfo> {
+        self.spawner.registry().tasks()
     }
 
-    fn now(&self) -> tokio::time::Instant {
-        tokio::time::Instant::now()
+    fn abort_tasks(&self, group: Option<&str>) -> usize {
+        self.spawner.registry().abort(group)
     }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Sleep::new(self.host_future(|| tokio::time::sleep(duration)))
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> Sleep {
+        let deadline = tokio::time::Instant::from_std(deadline);
+        Sleep::new(self.host_future(|| tokio::time::sleep_until(deadline)))
+    }
+
+    fn now(&self) -> Instant {
+        self.spawner.now()
+    }
+
+    fn timeout_boxed(
+        &self,
+        duration: Duration,
+        future: BoxFuture<BoxedOutput>,
+    ) -> BoxFuture<Result<BoxedOutput, Elapsed>> {
+        let timeout =
+            self.host_future(|| tokio::time::timeout(duration, future));
+        Box::pin(async move { timeout.await.map_err(|_| Elapsed) })
+    }
+
+    fn cancellation(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    fn settings(&self) -> Option<&str> {
+        self.settings.as_deref()
+    }
+
+    fn read_file(&self, path: &Path) -> BoxFuture<io::Result<Vec<u8>>> {
+        let permissions = Arc::clone(&self.permissions);
+        let plugin = self.plugin_name().to_owned();
+        let path = path.to_owned();
+        self.host_future(|| async move {
+            let path = permissions.check_read(&plugin, &path).await?;
+            tokio::fs::read(path).await
+        })
+    }
+
+    fn tail_file(
+        &self,
+        path: &Path,
+        options: TailOptions,
+    ) -> BoxFuture<io::Result<Lines>> {
+        let permissions = Arc::clone(&self.permissions);
+        let spawner = self.spawner.clone();
+        let path = path.to_owned();
+        self.host_future(|| async move {
+            let path =
+                permissions.check_read(spawner.plugin_name(), &path).await?;
+            let file = match tokio::fs::File::open(&path).await {
+                Ok(file) => Some(file),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+                Err(e) => return Err(e),
+            };
+            // In case the cursor points into the rotated file
+            let mut previous = None;
+            if options.resume.is_some() {
+                let rotated = fs::rotated_path(&path);
+                let plugin = spawner.plugin_name();
+                if let Ok(rotated) =
+                    permissions.check_read(plugin, &rotated).await
+                {
+                    previous = tokio::fs::File::open(rotated).await.ok();
+                }
+            }
+
+            let (tx, rx) = mpsc::channel(fs::LINES_BUFFER);
+            let name = format!("tail {}", path.display());
+            let follow = fs::follow(path, file, previous, options, tx);
+            spawner.spawn_detached(&name, follow);
+            Ok(Lines::new(Box::new(fs::TailSource::new(rx))))
+        })
+    }
+
+    fn run_process(
+        &self,
+        command: ProcessCommand,
+    ) -> BoxFuture<io::Result<ProcessOutput>> {
+        let permissions = Arc::clone(&self.permissions);
+        let plugin = self.plugin_name().to_owned();
+        self.host_future(|| async move {
+            permissions.check_exec(&plugin, &command.program)?;
+            // Dropping the child on timeout kills it
+            let output = command.to_tokio().spawn()?.wait_with_output();
+            let output = match command.timeout {
+                Some(timeout) => tokio::time::timeout(timeout, output)
+                    .await
+                    .map_err(|_| timed_out(&command.program, timeout))?,
+                None => output.await,
+            }?;
+            Ok(ProcessOutput {
+                status: output.status,
+                stdout: output.stdout,
+                stderr: output.stderr,
+            })
+        })
+    }
+
+    fn spawn_process(
+        &self,
+        command: ProcessCommand,
+    ) -> io::Result<ChildProcess> {
+        self.permissions
+            .check_exec(self.plugin_name(), &command.program)?;
+        let mut child = {
+            let _guard = self.spawner.handle().enter();
+            command.to_tokio().spawn()?
+        };
+
+        let program = &command.program;
+        let stdout = child.stdout.take().map(|out| {
+            self.forward_lines(
+                &format!("{program} stdout"),
+                BufReader::new(out),
+            )
+        });
+        let stderr = child.stderr.take().map(|err| {
+            self.forward_lines(
+                &format!("{program} stderr"),
+                BufReader::new(err),
+            )
+        });
+
+        // Supervises the child until it exits, is killed or times out
+        let kill = CancellationToken::new();
+        let (status_tx, status_rx) = oneshot::channel();
+        let id = child.id();
+        let killed = kill.clone();
+        let (timeout, name) = (command.timeout, program.clone());
+        self.spawner.spawn_detached(&format!("process {program}"), async move {
+            let deadline = async {
+                match timeout {
+                    Some(timeout) => tokio::time::sleep(timeout).await,
+                    None => std::future::pending().await,
+                }
+            };
+            let status = tokio::select! {
+                status = child.wait() => status,
+                () = killed.cancelled() => child.kill().await.and_then(|()| {
+                    Err(io::Error::new(
+                        io::ErrorKind::Interrupted,
+                        format!("`{name}` was killed"),
+                    ))
+                }),
+                () = deadline => child.kill().await.and_then(|()| {
+                    Err(timed_out(&name, timeout.unwrap_or_default()))
+                }),
+            };
+            drop(status_tx.send(status));
+        });
+        let status = self.host_future(|| async move {
+            status_rx.await.unwrap_or_else(|_| {
+                Err(io::Error::other("Process supervisor stopped"))
+            })
+        });
+
+        Ok(ChildProcess {
+            id,
+            stdout,
+            stderr,
+            status: Some(status),
+            kill,
+            kill_on_drop: command.kill_on_drop,
+        })
+    }
+
+    fn storage(&self) -> Result<StorageRef, StorageError> {
+        self.storage.clone().ok_or(StorageError::Unavailable)
+    }
+
+    fn http(&self) -> Result<HttpClientRef, HttpError> {
+        self.http.clone().ok_or(HttpError::Unavailable)
+    }
+
+    fn unix_request(
+        &self,
+        socket: &Path,
+        request: HttpRequest,
+    ) -> BoxFuture<Result<HttpResponse, HttpError>> {
+        let permissions = Arc::clone(&self.permissions);
+        let plugin = self.plugin_name().to_owned();
+        let socket = socket.to_owned();
+        self.host_future(|| async move {
+            let socket = permissions
+                .check_socket(&plugin, &socket)
+                .await
+                .map_err(|e| match e.kind() {
+                    io::ErrorKind::PermissionDenied => {
+                        HttpError::Denied(socket.display().to_string())
+                    }
+                    _ => HttpError::Request(e.to_string()),
+                })?;
+            unix::send(&socket, &request).await
+        })
+    }
+
+    fn connect_tcp(
+        &self,
+        host: &str,
+        port: u16,
+    ) -> BoxFuture<io::Result<SocketAddr>> {
+        let permissions = Arc::clone(&self.permissions);
+        let plugin = self.plugin_name().to_owned();
+        let host = host.to_owned();
+        self.host_future(|| async move {
+            permissions.check_connect(&plugin, &host, port)?;
+            let stream = tokio::net::TcpStream::connect((host, port)).await?;
+            stream.peer_addr()
+        })
+    }
+
+    fn resolve(&self, host: &str) -> BoxFuture<io::Result<Vec<IpAddr>>> {
+        let permissions = Arc::clone(&self.permissions);
+        let plugin = self.plugin_name().to_owned();
+        let host = host.to_owned();
+        self.host_future(|| async move {
+            permissions.check_resolve(&plugin, &host)?;
+            let mut ips = Vec::new();
+            for address in tokio::net::lookup_host((host, 0)).await? {
+                if !ips.contains(&address.ip()) {
+                    ips.push(address.ip());
+                }
+            }
+            Ok(ips)
+        })
+    }
+
+    fn tls_certificates(
+        &self,
+        host: &str,
+        port: u16,
+        server_name: Option<&str>,
+    ) -> BoxFuture<io::Result<Vec<Vec<u8>>>> {
+        let permissions = Arc::clone(&self.permissions);
+        let plugin = self.plugin_name().to_owned();
+        let host = host.to_owned();
+        let server_name = server_name.unwrap_or(&host).to_owned();
+        self.host_future(|| async move {
+            permissions.check_connect(&plugin, &host, port)?;
+            tls::peer_certificates(&host, port, &server_name).await
+        })
+    }
+
+    fn register_route(&self, mut route: Route) -> Result<(), RouteError> {
+        let routes = self.routes.as_ref().ok_or(RouteError::Unavailable)?;
+        route.validate()?;
+
+        // Tracked like any other task, so it's aborted if the plugin unloads
+        let spawner = self.spawner.clone();
+        let handler = route.handler;
+        let name: Arc<str> = format!("{} {}", route.method, route.path).into();
+        route.handler = Arc::new(move |request| {
+            let future = handler(request);
+            let task = TaskHandle::<HttpResponse>::new(spawner.spawn(
+                TaskSpec::named(&name),
+                Box::pin(async move { Box::new(future.await) as BoxedOutput }),
+            ));
+            Box::pin(async move {
+                task.await.unwrap_or_else(|e| {
+                    HttpResponse::new(500).with_body(e.to_string())
+                })
+            })
+        });
+        routes.register(self.plugin_name(), route)
+    }
+}
+
+fn timed_out(program: &str, timeout: Duration) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::TimedOut,
+        format!("`{program}` timed out after {timeout:?}"),
+    )
 }
 
 /// Type alias for the runtime handle used in plugins
-pub type RuntimeRef = Arc<dyn RuntimeHandle>;
\ No newline at end of file
+pub type RuntimeRef = Arc<dyn RuntimeHandle>;
@@ -0,0 +1,165 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+/// Key-value store owned by the host, namespaced to a single plugin.
+/// Whatever is stored survives restarts of both the plugin and the host.
+///
+/// Calls are synchronous and hit the disk when writing, so large batches
+/// are best applied from `spawn_blocking`.
+pub trait Storage: Send + Sync + fmt::Debug {
+    /// Value stored under `key`
+    ///
+    /// # Errors
+    /// If the underlying database failed.
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError>;
+
+    /// Store `value` under `key`, replacing any previous value
+    ///
+    /// # Errors
+    /// If the underlying database failed.
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<(), StorageError>;
+
+    /// Remove `key`, returning whether it was present
+    ///
+    /// # Errors
+    /// If the underlying database failed.
+    fn delete(&self, key: &[u8]) -> Result<bool, StorageError>;
+
+    /// Every entry whose key starts with `prefix`, ordered by key
+    ///
+    /// # Errors
+    /// If the underlying database failed.
+    fn scan_prefix(&self, prefix: &[u8]) -> Result<Vec<Entry>, StorageError>;
+
+    /// Apply every operation of `batch` at once, or none of them
+    ///
+    /// # Errors
+    /// If the underlying database failed, in which case nothing was applied.
+    fn apply(&self, batch: Batch) -> Result<(), StorageError>;
+}
+
+/// Type alias for the storage handed to plugins
+pub type StorageRef = Arc<dyn Storage>;
+
+/// Key and value, as returned by [`Storage::scan_prefix`].
+pub type Entry = (Vec<u8>, Vec<u8>);
+
+/// Operations applied atomically by [`Storage::apply`].
+#[derive(Debug, Clone, Default)]
+pub struct Batch {
+    pub ops: Vec<BatchOp>,
+}
+
+#[derive(Debug, Clone)]
+pub enum BatchOp {
+    Put { key: Vec<u8>, value: Vec<u8> },
+    Delete { key: Vec<u8> },
+}
+
+impl Batch {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { ops: Vec::new() }
+    }
+
+    #[must_use]
+    pub fn put(
+        mut self,
+        key: impl Into<Vec<u8>>,
+        value: impl Into<Vec<u8>>,
+    ) -> Self {
+        self.ops.push(BatchOp::Put {
+            key: key.into(),
+            value: value.into(),
+        });
+        self
+    }
+
+    #[must_use]
+    pub fn delete(mut self, key: impl Into<Vec<u8>>) -> Self {
+        self.ops.push(BatchOp::Delete { key: key.into() });
+        self
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+/// Storage kept in memory, lost along with the process, e.g. to test
+/// plugins.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    entries: Mutex<BTreeMap<Vec<u8>, Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    fn entries(&self) -> MutexGuard<'_, BTreeMap<Vec<u8>, Vec<u8>>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError> {
+        Ok(self.entries().get(key).cloned())
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<(), StorageError> {
+        self.entries().insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<bool, StorageError> {
+        Ok(self.entries().remove(key).is_some())
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Result<Vec<Entry>, StorageError> {
+        let entries = self
+            .entries()
+            .range(prefix.to_vec()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        Ok(entries)
+    }
+
+    fn apply(&self, batch: Batch) -> Result<(), StorageError> {
+        let mut entries = self.entries();
+        for op in batch.ops {
+            match op {
+                BatchOp::Put { key, value } => {
+                    entries.insert(key, value);
+                }
+                BatchOp::Delete { key } => {
+                    entries.remove(&key);
+                }
+            }
+        }
+        drop(entries);
+        Ok(())
+    }
+}
+
+/// Error returned by [`Storage`] operations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StorageError {
+    /// The host wasn't configured with a store
+    Unavailable,
+    /// The underlying database failed
+    Backend(String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unavailable => f.write_str("storage is unavailable"),
+            Self::Backend(e) => write!(f, "storage failed: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
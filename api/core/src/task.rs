@@ -0,0 +1,613 @@
+use std::{
+    any::Any,
+    collections::HashMap,
+    fmt,
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    sync::{
+        Arc, Mutex, MutexGuard, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+    task::{Context, Poll},
+};
+
+use tracing::Instrument;
+
+use crate::{
+    runtime::{RuntimeHandle, RuntimeRef},
+    time::Instant,
+};
+
+/// Output of a task once boxed to cross the plugin boundary.
+pub type BoxedOutput = Box<dyn Any + Send>;
+
+/// Future as handed over to the host.
+pub type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// Closure as handed over to the host by `spawn_blocking`.
+pub type BoxBlocking = Box<dyn FnOnce() -> BoxedOutput + Send>;
+
+/// How a task should be labelled by the host.
+#[derive(Debug, Clone, Default)]
+pub struct TaskSpec {
+    pub name: Option<Box<str>>,
+    pub group: Option<Box<str>>,
+}
+
+impl TaskSpec {
+    #[must_use]
+    pub fn named(name: &str) -> Self {
+        Self {
+            name: Some(name.into()),
+            group: None,
+        }
+    }
+
+    #[must_use]
+    pub fn in_group(mut self, group: &str) -> Self {
+        self.group = Some(group.into());
+        self
+    }
+}
+
+/// Diagnostics about a task that is still running.
+#[derive(Debug, Clone)]
+pub struct TaskInfo {
+    pub id: u64,
+    pub name: Option<Box<str>>,
+    pub group: Option<Box<str>>,
+    pub blocking: bool,
+    pub spawned_at: Instant,
+}
+
+impl fmt::Display for TaskInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{} `{}`", self.id, self.name.as_deref().unwrap_or("?"))?;
+        if let Some(group) = &self.group {
+            write!(f, " in `{group}`")?;
+        }
+        if self.blocking {
+            write!(f, " (blocking)")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskError {
+    /// The task was aborted, either directly or through its group.
+    Aborted,
+    /// The task panicked.
+    Panicked,
+    /// The task returned something else than what the handle expected.
+    /// Only possible when two sides disagree on the type across the boundary.
+    OutputMismatch,
+}
+
+impl fmt::Display for TaskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Aborted => f.write_str("task was aborted"),
+            Self::Panicked => f.write_str("task panicked"),
+            Self::OutputMismatch => f.write_str("task output type mismatch"),
+        }
+    }
+}
+
+impl std::error::Error for TaskError {}
+
+/// Host side of a spawned task.
+pub trait TaskControl: Send + Sync {
+    fn abort(&self);
+
+    fn is_finished(&self) -> bool;
+}
+
+impl TaskControl for tokio::task::AbortHandle {
+    fn abort(&self) {
+        Self::abort(self);
+    }
+
+    fn is_finished(&self) -> bool {
+        Self::is_finished(self)
+    }
+}
+
+/// Untyped task as returned by the host.
+/// Both the join future and the controls are driven by the host.
+pub struct RawTask {
+    join: BoxFuture<Result<BoxedOutput, TaskError>>,
+    control: Box<dyn TaskControl>,
+}
+
+impl RawTask {
+    #[must_use]
+    pub fn new(
+        join: BoxFuture<Result<BoxedOutput, TaskError>>,
+        control: Box<dyn TaskControl>,
+    ) -> Self {
+        Self { join, control }
+    }
+
+    /// Wraps a tokio task, mapping its errors host-side.
+    pub(crate) fn from_tokio(
+        join: tokio::task::JoinHandle<BoxedOutput>,
+    ) -> Self {
+        let control = Box::new(join.abort_handle());
+        let join = async move {
+            join.await.map_err(|e| {
+                if e.is_cancelled() {
+                    TaskError::Aborted
+                } else {
+                    TaskError::Panicked
+                }
+            })
+        };
+        Self::new(Box::pin(join), control)
+    }
+}
+
+impl fmt::Debug for RawTask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RawTask")
+            .field("finished", &self.control.is_finished())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Typed handle to a task spawned through the runtime.
+/// Awaiting it yields the task's output.
+#[derive(Debug)]
+pub struct TaskHandle<T> {
+    raw: RawTask,
+    output: PhantomData<fn() -> T>,
+}
+
+impl<T> TaskHandle<T> {
+    #[must_use]
+    pub const fn new(raw: RawTask) -> Self {
+        Self {
+            raw,
+            output: PhantomData,
+        }
+    }
+
+    /// Aborts the task. Blocking tasks that already started run to the end.
+    pub fn abort(&self) {
+        self.raw.control.abort();
+    }
+
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        self.raw.control.is_finished()
+    }
+}
+
+impl<T: 'static> Future for TaskHandle<T> {
+    type Output = Result<T, TaskError>;
+
+    fn poll(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Self::Output> {
+        self.raw.join.as_mut().poll(cx).map(|res| {
+            res?.downcast::<T>()
+                .map(|output| *output)
+                .map_err(|_| TaskError::OutputMismatch)
+        })
+    }
+}
+
+/// Named set of tasks that can be aborted together.
+#[derive(Debug, Clone)]
+pub struct TaskGroup {
+    name: Arc<str>,
+    runtime: RuntimeRef,
+}
+
+impl TaskGroup {
+    #[must_use]
+    pub fn new(runtime: RuntimeRef, name: &str) -> Self {
+        Self {
+            name: name.into(),
+            runtime,
+        }
+    }
+
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Spawn a named task in this group
+    pub fn spawn<F, T>(&self, name: &str, future: F) -> TaskHandle<T>
+    where
+        F: Future<Output = T> + Send + 'static,
+        T: Send + 'static, {
+        let spec = TaskSpec::named(name).in_group(&self.name);
+        self.runtime.spawn_with(spec, future)
+    }
+
+    /// Run a named blocking closure in this group
+    pub fn spawn_blocking<F, T>(&self, name: &str, f: F) -> TaskHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static, {
+        let spec = TaskSpec::named(name).in_group(&self.name);
+        self.runtime.spawn_blocking_with(spec, f)
+    }
+
+    /// Tasks of this group that are still running
+    #[must_use]
+    pub fn tasks(&self) -> Vec<TaskInfo> {
+        let mut tasks = self.runtime.tasks();
+        tasks.retain(|t| t.group.as_deref() == Some(&*self.name));
+        tasks
+    }
+
+    /// Aborts every task of this group
+    pub fn abort(&self) {
+        self.runtime.abort_tasks(Some(&self.name));
+    }
+}
+
+/// Generic helpers that box everything before handing it to the host.
+impl dyn RuntimeHandle {
+    /// Spawn a task on the runtime
+    pub fn spawn<F, T>(&self, future: F) -> TaskHandle<T>
+    where
+        F: Future<Output = T> + Send + 'static,
+        T: Send + 'static, {
+        self.spawn_with(TaskSpec::default(), future)
+    }
+
+    /// Spawn a task that shows up under `name` in diagnostics
+    pub fn spawn_named<F, T>(&self, name: &str, future: F) -> TaskHandle<T>
+    where
+        F: Future<Output = T> + Send + 'static,
+        T: Send + 'static, {
+        self.spawn_with(TaskSpec::named(name), future)
+    }
+
+    pub fn spawn_with<F, T>(&self, spec: TaskSpec, future: F) -> TaskHandle<T>
+    where
+        F: Future<Output = T> + Send + 'static,
+        T: Send + 'static, {
+        let future = async move { Box::new(future.await) as BoxedOutput };
+        TaskHandle::new(self.spawn_boxed(spec, Box::pin(future)))
+    }
+
+    /// Run blocking code on the host's blocking pool
+    pub fn spawn_blocking<F, T>(&self, name: &str, f: F) -> TaskHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static, {
+        self.spawn_blocking_with(TaskSpec::named(name), f)
+    }
+
+    pub fn spawn_blocking_with<F, T>(
+        &self,
+        spec: TaskSpec,
+        f: F,
+    ) -> TaskHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static, {
+        let f = move || Box::new(f()) as BoxedOutput;
+        TaskHandle::new(self.spawn_blocking_boxed(spec, Box::new(f)))
+    }
+}
+
+/// Spawns tasks on the host's runtime on behalf of one plugin,
+/// keeping track of them in its [`TaskRegistry`].
+#[derive(Debug, Clone)]
+pub struct Spawner {
+    handle: tokio::runtime::Handle,
+    plugin_name: Arc<str>,
+    tasks: Arc<TaskRegistry>,
+}
+
+impl Spawner {
+    pub fn new(handle: tokio::runtime::Handle, plugin_name: &str) -> Self {
+        Self {
+            handle,
+            plugin_name: plugin_name.into(),
+            tasks: Arc::default(),
+        }
+    }
+
+    pub const fn handle(&self) -> &tokio::runtime::Handle {
+        &self.handle
+    }
+
+    pub fn plugin_name(&self) -> &str {
+        &self.plugin_name
+    }
+
+    pub fn registry(&self) -> &TaskRegistry {
+        &self.tasks
+    }
+
+    pub fn now(&self) -> Instant {
+        let _guard = self.handle.enter();
+        tokio::time::Instant::now().into_std()
+    }
+
+    fn span(&self, spec: &TaskSpec) -> tracing::Span {
+        tracing::info_span!(
+            "task",
+            plugin = %self.plugin_name,
+            name = spec.name.as_deref(),
+            group = spec.group.as_deref(),
+        )
+    }
+
+    pub fn spawn(
+        &self,
+        spec: TaskSpec,
+        future: BoxFuture<BoxedOutput>,
+    ) -> RawTask {
+        let span = self.span(&spec);
+        self.tasks.register(spec, false, self.now(), |guard| {
+            self.handle.spawn(
+                async move {
+                    let _guard = guard;
+                    future.await
+                }
+                .instrument(span),
+            )
+        })
+    }
+
+    pub fn spawn_blocking(&self, spec: TaskSpec, task: BoxBlocking) -> RawTask {
+        let span = self.span(&spec);
+        self.tasks.register(spec, true, self.now(), |guard| {
+            self.handle.spawn_blocking(move || {
+                let _guard = guard;
+                span.in_scope(task)
+            })
+        })
+    }
+
+    /// Spawns a task the host runs for the plugin, e.g. following a file.
+    /// It still shows up in diagnostics and gets aborted on unload.
+    pub fn spawn_detached<F>(&self, name: &str, future: F)
+    where
+        F: Future<Output = ()> + Send + 'static, {
+        let future = async move {
+            future.await;
+            Box::new(()) as BoxedOutput
+        };
+        drop(self.spawn(TaskSpec::named(name), Box::pin(future)));
+    }
+}
+
+/// Host-side bookkeeping of the tasks spawned by one plugin.
+#[derive(Debug, Default)]
+pub struct TaskRegistry {
+    next_id: AtomicU64,
+    tasks: Mutex<HashMap<u64, TaskEntry>>,
+}
+
+#[derive(Debug)]
+struct TaskEntry {
+    info: TaskInfo,
+    abort: Option<tokio::task::AbortHandle>,
+}
+
+impl TaskRegistry {
+    fn lock(&self) -> MutexGuard<'_, HashMap<u64, TaskEntry>> {
+        self.tasks.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Registers the task returned by `spawn`. It gets deregistered by the
+    /// [`Deregister`] guard it receives, whenever it finishes or is aborted.
+    pub fn register(
+        self: &Arc<Self>,
+        spec: TaskSpec,
+        blocking: bool,
+        spawned_at: Instant,
+        spawn: impl FnOnce(Deregister) -> tokio::task::JoinHandle<BoxedOutput>,
+    ) -> RawTask {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let info = TaskInfo {
+            id,
+            name: spec.name,
+            group: spec.group,
+            blocking,
+            spawned_at,
+        };
+
+        // Inserted first so that the guard always finds something to remove
+        self.lock().insert(id, TaskEntry { info, abort: None });
+        let join = spawn(Deregister {
+            registry: Arc::clone(self),
+            id,
+        });
+        if let Some(task) = self.lock().get_mut(&id) {
+            task.abort = Some(join.abort_handle());
+        }
+        RawTask::from_tokio(join)
+    }
+
+    pub fn tasks(&self) -> Vec<TaskInfo> {
+        let mut tasks: Vec<_> =
+            self.lock().values().map(|t| t.info.clone()).collect();
+        tasks.sort_unstable_by_key(|t| t.id);
+        tasks
+    }
+
+    pub fn abort(&self, group: Option<&str>) -> usize {
+        self.lock()
+            .values()
+            .filter(|t| group.is_none() || t.info.group.as_deref() == group)
+            .filter_map(|t| t.abort.as_ref())
+            .map(tokio::task::AbortHandle::abort)
+            .count()
+    }
+}
+
+/// Removes its task from the registry when dropped.
+pub struct Deregister {
+    registry: Arc<TaskRegistry>,
+    id: u64,
+}
+
+impl Drop for Deregister {
+    fn drop(&mut self) {
+        self.registry.lock().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::timeout;
+
+    use super::*;
+    use crate::CancellationToken;
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    fn spawner() -> Spawner {
+        Spawner::new(tokio::runtime::Handle::current(), "test")
+    }
+
+    fn spawn<F, T>(
+        spawner: &Spawner,
+        spec: TaskSpec,
+        future: F,
+    ) -> TaskHandle<T>
+    where
+        F: Future<Output = T> + Send + 'static,
+        T: Send + 'static, {
+        let future = async move { Box::new(future.await) as BoxedOutput };
+        TaskHandle::new(spawner.spawn(spec, Box::pin(future)))
+    }
+
+    fn names(spawner: &Spawner) -> Vec<String> {
+        spawner
+            .registry()
+            .tasks()
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn tracks_running_tasks() {
+        let spawner = spawner();
+        let token = CancellationToken::new();
+        let waiting = |token: &CancellationToken| {
+            let token = token.clone();
+            async move { token.cancelled().await }
+        };
+        let collect =
+            spawn(&spawner, TaskSpec::named("collect"), waiting(&token));
+        let grouped = TaskSpec::named("poll").in_group("sensors");
+        let poll = spawn(&spawner, grouped, waiting(&token));
+        let anonymous = spawn(&spawner, TaskSpec::default(), waiting(&token));
+
+        assert_eq!(
+            names(&spawner),
+            ["#0 `collect`", "#1 `poll` in `sensors`", "#2 `?`"]
+        );
+
+        token.cancel();
+        for task in [collect, poll, anonymous] {
+            timeout(WAIT, task).await.unwrap().unwrap();
+        }
+        assert!(spawner.registry().tasks().is_empty());
+    }
+
+    #[tokio::test]
+    async fn forgets_finished_tasks() {
+        let spawner = spawner();
+        let task = spawn(&spawner, TaskSpec::named("answer"), async { 42 });
+        assert_eq!(timeout(WAIT, task).await.unwrap(), Ok(42));
+
+        let blocking = TaskHandle::<u8>::new(spawner.spawn_blocking(
+            TaskSpec::named("read"),
+            Box::new(|| Box::new(7u8) as BoxedOutput),
+        ));
+        assert_eq!(timeout(WAIT, blocking).await.unwrap(), Ok(7));
+
+        let panicking = spawn(&spawner, TaskSpec::named("panic"), async {
+            panic!("boom");
+        });
+        let panicked: Result<(), _> = timeout(WAIT, panicking).await.unwrap();
+        assert_eq!(panicked, Err(TaskError::Panicked));
+
+        assert!(spawner.registry().tasks().is_empty());
+    }
+
+    #[tokio::test]
+    async fn reports_blocking_tasks() {
+        let spawner = spawner();
+        let (tx, rx) = std::sync::mpsc::channel::<u8>();
+        let blocking = TaskHandle::<u8>::new(spawner.spawn_blocking(
+            TaskSpec::named("read"),
+            Box::new(move || Box::new(rx.recv().unwrap()) as BoxedOutput),
+        ));
+        assert_eq!(names(&spawner), ["#0 `read` (blocking)"]);
+
+        tx.send(7).unwrap();
+        assert_eq!(timeout(WAIT, blocking).await.unwrap(), Ok(7));
+        assert!(spawner.registry().tasks().is_empty());
+    }
+
+    #[tokio::test]
+    async fn aborts_groups() {
+        let spawner = spawner();
+        let pending = std::future::pending::<()>;
+        let grouped = TaskSpec::named("poll").in_group("sensors");
+        let poll = spawn(&spawner, grouped, pending());
+        let other = spawn(&spawner, TaskSpec::named("serve"), pending());
+
+        assert_eq!(spawner.registry().abort(Some("sensors")), 1);
+        assert_eq!(timeout(WAIT, poll).await.unwrap(), Err(TaskError::Aborted));
+        assert_eq!(names(&spawner), ["#1 `serve`"]);
+        assert!(!other.is_finished());
+        other.abort();
+    }
+
+    /// As on unload: cancelled, then aborted if still running once the
+    /// grace period is over
+    #[tokio::test]
+    async fn aborts_what_outlives_the_grace_period() {
+        let spawner = spawner();
+        let token = CancellationToken::new();
+        let watching = spawn(&spawner, TaskSpec::named("watch"), {
+            let token = token.clone();
+            async move { token.cancelled().await }
+        });
+        let stubborn = spawn(
+            &spawner,
+            TaskSpec::named("stubborn"),
+            std::future::pending::<()>(),
+        );
+
+        token.cancel();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(watching.is_finished());
+        assert_eq!(names(&spawner), ["#1 `stubborn`"]);
+
+        assert_eq!(spawner.registry().abort(None), 1);
+        let aborted = timeout(WAIT, stubborn).await.unwrap();
+        assert_eq!(aborted, Err(TaskError::Aborted));
+        assert!(spawner.registry().tasks().is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejects_mismatched_outputs() {
+        let spawner = spawner();
+        let future = async { Box::new(42u32) as BoxedOutput };
+        let task = TaskHandle::<String>::new(
+            spawner.spawn(TaskSpec::default(), Box::pin(future)),
+        );
+        let output = timeout(WAIT, task).await.unwrap();
+        assert_eq!(output, Err(TaskError::OutputMismatch));
+    }
+}
@@ -0,0 +1,102 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+pub use std::time::{Duration, Instant};
+
+use crate::{
+    runtime::RuntimeHandle,
+    task::{BoxFuture, BoxedOutput},
+};
+
+/// Sleep created and driven by the host's timer.
+/// Plugins never build a timer themselves, so they never depend on the
+/// thread-local runtime context of their own copy of tokio.
+pub struct Sleep {
+    inner: BoxFuture<()>,
+}
+
+impl Sleep {
+    #[must_use]
+    pub fn new(inner: BoxFuture<()>) -> Self {
+        Self { inner }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.as_mut().poll(cx)
+    }
+}
+
+impl fmt::Debug for Sleep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sleep").finish_non_exhaustive()
+    }
+}
+
+/// Error returned by `timeout` when the deadline passed first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed;
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("deadline has elapsed")
+    }
+}
+
+impl std::error::Error for Elapsed {}
+
+/// Error returned by `timeout`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutError {
+    /// The deadline passed first.
+    Elapsed,
+    /// The host returned something else than what the future outputs.
+    /// Only possible when two sides disagree on the type across the boundary.
+    OutputMismatch,
+}
+
+impl From<Elapsed> for TimeoutError {
+    fn from(Elapsed: Elapsed) -> Self {
+        Self::Elapsed
+    }
+}
+
+impl fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Elapsed => Elapsed.fmt(f),
+            Self::OutputMismatch => f.write_str("timeout output type mismatch"),
+        }
+    }
+}
+
+impl std::error::Error for TimeoutError {}
+
+impl dyn RuntimeHandle {
+    /// Run `future` for at most `duration`, on the host's timer
+    ///
+    /// # Errors
+    /// Returns [`TimeoutError::Elapsed`] if `future` didn't complete in time.
+    pub async fn timeout<F, T>(
+        &self,
+        duration: Duration,
+        future: F,
+    ) -> Result<T, TimeoutError>
+    where
+        F: Future<Output = T> + Send + 'static,
+        T: Send + 'static, {
+        let future = async move { Box::new(future.await) as BoxedOutput };
+        let output = self.timeout_boxed(duration, Box::pin(future)).await?;
+        output
+            .downcast::<T>()
+            .map(|output| *output)
+            .map_err(|_| TimeoutError::OutputMismatch)
+    }
+}
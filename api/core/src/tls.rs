@@ -0,0 +1,175 @@
+//! Certificates presented by TLS servers, fetched to be inspected rather
+//! than trusted: expired, self-signed or mismatched ones are returned too.
+
+use std::{io, sync::Arc};
+
+use tokio::net::TcpStream;
+use tokio_rustls::{
+    TlsConnector,
+    rustls::{
+        ClientConfig, DigitallySignedStruct, Error, SignatureScheme,
+        client::danger::{
+            HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+        },
+        crypto::{self, WebPkiSupportedAlgorithms, aws_lc_rs},
+        pki_types::{CertificateDer, ServerName, UnixTime},
+    },
+};
+
+/// Certificates `host` presents on `port` for `server_name`, leaf first,
+/// DER-encoded
+pub async fn peer_certificates(
+    host: &str,
+    port: u16,
+    server_name: &str,
+) -> io::Result<Vec<Vec<u8>>> {
+    let server_name = ServerName::try_from(server_name.to_owned())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let stream = TcpStream::connect((host, port)).await?;
+    let stream = connector()?.connect(server_name, stream).await?;
+    let (_, connection) = stream.get_ref();
+    let certificates = connection
+        .peer_certificates()
+        .filter(|certificates| !certificates.is_empty())
+        .ok_or_else(|| io::Error::other("no certificate presented"))?;
+    Ok(certificates.iter().map(|c| c.to_vec()).collect())
+}
+
+fn connector() -> io::Result<TlsConnector> {
+    let provider = Arc::new(aws_lc_rs::default_provider());
+    let verifier = Inspector(provider.signature_verification_algorithms);
+    let config = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+/// Accepts any certificate, still checking the server holds its key
+#[derive(Debug)]
+struct Inspector(WebPkiSupportedAlgorithms);
+
+impl ServerCertVerifier for Inspector {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        crypto::verify_tls12_signature(message, cert, dss, &self.0)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        crypto::verify_tls13_signature(message, cert, dss, &self.0)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rcgen::{
+        BasicConstraints, CertificateParams, DnType, IsCa, KeyPair,
+        date_time_ymd,
+    };
+    use tokio::{io::AsyncWriteExt, net::TcpListener};
+    use tokio_rustls::{
+        TlsAcceptor,
+        rustls::{ServerConfig, pki_types::PrivatePkcs8KeyDer},
+    };
+
+    use super::*;
+
+    /// A CA, and a leaf it signed for `localhost` that already expired
+    fn chain() -> (Vec<CertificateDer<'static>>, KeyPair) {
+        let mut ca = CertificateParams::new(Vec::new()).unwrap();
+        ca.distinguished_name.push(DnType::CommonName, "Nexus CA");
+        ca.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca_key = KeyPair::generate().unwrap();
+        let ca = ca.self_signed(&ca_key).unwrap();
+
+        let mut leaf =
+            CertificateParams::new(vec!["localhost".into()]).unwrap();
+        leaf.not_before = date_time_ymd(2019, 1, 1);
+        leaf.not_after = date_time_ymd(2020, 1, 1);
+        let key = KeyPair::generate().unwrap();
+        let leaf = leaf.signed_by(&key, &ca, &ca_key).unwrap();
+        (vec![leaf.der().clone(), ca.der().clone()], key)
+    }
+
+    /// Port of a TLS server presenting `chain` to a single client
+    async fn serve(chain: Vec<CertificateDer<'static>>, key: &KeyPair) -> u16 {
+        let key = PrivatePkcs8KeyDer::from(key.serialize_der()).into();
+        let config = ServerConfig::builder_with_provider(Arc::new(
+            aws_lc_rs::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(chain, key)
+        .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            // The client may leave as soon as it has the certificates
+            if let Ok(mut stream) = acceptor.accept(stream).await {
+                stream.shutdown().await.ok();
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn fetches_the_chain_leaf_first() {
+        let (chain, key) = chain();
+        let port = serve(chain.clone(), &key).await;
+        // Expired, and not for this name, but returned all the same
+        let fetched = peer_certificates("127.0.0.1", port, "other.example")
+            .await
+            .unwrap();
+        let expected = chain.iter().map(|c| c.to_vec()).collect::<Vec<_>>();
+        assert_eq!(fetched, expected);
+    }
+
+    #[tokio::test]
+    async fn fails_without_tls() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream.write_all(b"SSH-2.0-OpenSSH_9.6\r\n").await.unwrap();
+        });
+        let fetched = peer_certificates("127.0.0.1", port, "localhost").await;
+        assert!(fetched.is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_server_names() {
+        let error = peer_certificates("127.0.0.1", 443, "not a name!")
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+}
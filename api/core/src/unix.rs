@@ -0,0 +1,280 @@
+//! HTTP/1.1 over Unix sockets, e.g. Docker's `/var/run/docker.sock`.
+//! Only what local daemons need: one request per connection, answered
+//! with a `Content-Length`, a chunked or a close-delimited body.
+
+use std::{io, path::Path};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixStream,
+};
+
+use crate::{
+    http::{HttpError, HttpRequest, HttpResponse},
+    time::Duration,
+};
+
+/// Timeout of requests that don't set their own
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Largest response read, as a daemon could otherwise stream forever
+const MAX_RESPONSE: u64 = 16 * 1_024 * 1_024;
+
+/// Sends `request` to the server listening on `socket`, without retrying
+pub async fn send(
+    socket: &Path,
+    request: &HttpRequest,
+) -> Result<HttpResponse, HttpError> {
+    let timeout = request.timeout.unwrap_or(DEFAULT_TIMEOUT);
+    tokio::time::timeout(timeout, exchange(socket, request))
+        .await
+        .map_err(|_| HttpError::Timeout)?
+        .map_err(|e| HttpError::Request(e.to_string()))
+}
+
+async fn exchange(
+    socket: &Path,
+    request: &HttpRequest,
+) -> io::Result<HttpResponse> {
+    let mut stream = UnixStream::connect(socket).await?;
+    stream.write_all(&head(request)?).await?;
+    stream.write_all(&request.body).await?;
+
+    let mut raw = Vec::new();
+    stream.take(MAX_RESPONSE).read_to_end(&mut raw).await?;
+    parse(&raw).ok_or_else(|| invalid("malformed HTTP response"))
+}
+
+/// Request line and headers, asking the server to close the connection
+fn head(request: &HttpRequest) -> io::Result<Vec<u8>> {
+    let mut lines = vec![
+        format!("{} {} HTTP/1.1", request.method, path(&request.url)),
+        "Host: localhost".to_owned(),
+        "Connection: close".to_owned(),
+        format!("Content-Length: {}", request.body.len()),
+    ];
+    for (name, value) in &request.headers {
+        if [name, value].iter().any(|s| s.contains(['\r', '\n'])) {
+            return Err(invalid("header with a line break"));
+        }
+        lines.push(format!("{name}: {value}"));
+    }
+    Ok(format!("{}\r\n\r\n", lines.join("\r\n")).into_bytes())
+}
+
+/// Path and query of `url`, which may also be given alone, e.g.
+/// `/containers/json` or `http://localhost/containers/json`
+fn path(url: &str) -> &str {
+    let Some((_scheme, rest)) = url.split_once("://") else {
+        return url;
+    };
+    rest.find('/').map_or("/", |start| &rest[start..])
+}
+
+fn parse(raw: &[u8]) -> Option<HttpResponse> {
+    let end = raw.windows(4).position(|w| w == b"\r\n\r\n")?;
+    let head = std::str::from_utf8(&raw[..end]).ok()?;
+    let body = &raw[end + 4..];
+
+    let mut lines = head.split("\r\n");
+    let status = lines.next()?.split(' ').nth(1)?.parse().ok()?;
+    let mut response = HttpResponse::new(status);
+    for line in lines {
+        let (name, value) = line.split_once(':')?;
+        response = response.with_header(name.trim(), value.trim());
+    }
+
+    let chunked = response
+        .header("Transfer-Encoding")
+        .is_some_and(|encoding| encoding.eq_ignore_ascii_case("chunked"));
+    let length = response
+        .header("Content-Length")
+        .and_then(|length| length.parse::<usize>().ok());
+    let body = if chunked {
+        dechunk(body)?
+    } else if let Some(length) = length {
+        body.get(..length)?.to_vec()
+    } else {
+        body.to_vec()
+    };
+    Some(response.with_body(body))
+}
+
+/// Joins the chunks of a `Transfer-Encoding: chunked` body
+fn dechunk(mut body: &[u8]) -> Option<Vec<u8>> {
+    let mut joined = Vec::new();
+    loop {
+        let end = body.windows(2).position(|w| w == b"\r\n")?;
+        let size = std::str::from_utf8(&body[..end]).ok()?;
+        // Extensions follow a `;`
+        let size = size.split(';').next()?.trim();
+        let size = usize::from_str_radix(size, 16).ok()?;
+        let chunk = body.get(end + 2..end + 2 + size)?;
+        if size == 0 {
+            return Some(joined);
+        }
+        joined.extend_from_slice(chunk);
+        body = body.get(end + 2 + size + 2..)?;
+    }
+}
+
+fn invalid(e: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use tempfile::TempDir;
+    use tokio::{net::UnixListener, task::JoinHandle};
+
+    use super::*;
+    use crate::http::Method;
+
+    /// Answers a single connection to a socket in `dir` with `answer`,
+    /// or never if it's `None`, returning the socket and the request as
+    /// it was received
+    fn serve(
+        dir: &TempDir,
+        answer: Option<&'static [u8]>,
+    ) -> (PathBuf, JoinHandle<String>) {
+        let socket = dir.path().join("daemon.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        let received = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 1_024];
+            while !complete(&request) {
+                let read = stream.read(&mut buffer).await.unwrap();
+                if read == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buffer[..read]);
+            }
+            let Some(answer) = answer else {
+                std::future::pending::<()>().await;
+                unreachable!();
+            };
+            stream.write_all(answer).await.unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        (socket, received)
+    }
+
+    /// Whether `request` holds its head and its whole body
+    fn complete(request: &[u8]) -> bool {
+        let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n")
+        else {
+            return false;
+        };
+        let head = String::from_utf8_lossy(&request[..end]);
+        let length = head
+            .lines()
+            .find_map(|line| line.strip_prefix("Content-Length: "))
+            .map_or(0, |length| length.parse().unwrap());
+        request.len() >= end + 4 + length
+    }
+
+    async fn get(answer: &'static [u8]) -> Result<HttpResponse, HttpError> {
+        let dir = tempfile::tempdir().unwrap();
+        let (socket, _) = serve(&dir, Some(answer));
+        send(&socket, &HttpRequest::get("/_ping")).await
+    }
+
+    #[tokio::test]
+    async fn sends_the_request() {
+        let dir = tempfile::tempdir().unwrap();
+        let answer = b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n";
+        let (socket, received) = serve(&dir, Some(answer));
+        let request =
+            HttpRequest::new(Method::Post, "http://localhost/exec?id=1")
+                .header("X-Test", "1")
+                .body("{}");
+        let response = send(&socket, &request).await.unwrap();
+        assert_eq!(response.status, 204);
+        assert_eq!(
+            received.await.unwrap(),
+            "POST /exec?id=1 HTTP/1.1\r\n\
+             Host: localhost\r\n\
+             Connection: close\r\n\
+             Content-Length: 2\r\n\
+             X-Test: 1\r\n\
+             \r\n\
+             {}"
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_bodies_of_a_given_length() {
+        let response =
+            get(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello world")
+                .await
+                .unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.text(), "hello");
+    }
+
+    #[tokio::test]
+    async fn reads_chunked_bodies() {
+        let response = get(b"HTTP/1.1 200 OK\r\n\
+            Transfer-Encoding: chunked\r\n\r\n\
+            5\r\nhello\r\n\
+            7;name=value\r\n, world\r\n\
+            0\r\n\r\n")
+        .await
+        .unwrap();
+        assert_eq!(response.text(), "hello, world");
+    }
+
+    #[tokio::test]
+    async fn reads_bodies_until_closed() {
+        let response = get(b"HTTP/1.1 404 Not Found\r\n\
+            Content-Type: text/plain\r\n\r\n\
+            no such container")
+        .await
+        .unwrap();
+        assert_eq!(response.status, 404);
+        assert_eq!(response.header("content-type"), Some("text/plain"));
+        assert_eq!(response.text(), "no such container");
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_responses() {
+        for answer in [
+            &b"garbage"[..],
+            b"HTTP/1.1 OK\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nshort",
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhel",
+        ] {
+            let error = get(answer).await.unwrap_err();
+            assert!(matches!(error, HttpError::Request(_)), "{error}");
+        }
+    }
+
+    #[tokio::test]
+    async fn times_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let (socket, _) = serve(&dir, None);
+        let request =
+            HttpRequest::get("/_ping").timeout(Duration::from_millis(50));
+        let error = send(&socket, &request).await.unwrap_err();
+        assert_eq!(error, HttpError::Timeout);
+    }
+
+    #[tokio::test]
+    async fn rejects_line_breaks_in_headers() {
+        let dir = tempfile::tempdir().unwrap();
+        let (socket, _) = serve(&dir, None);
+        let request = HttpRequest::get("/_ping").header("X-Test", "1\r\nX: 2");
+        let error = send(&socket, &request).await.unwrap_err();
+        assert!(matches!(error, HttpError::Request(_)), "{error}");
+    }
+
+    #[test]
+    fn takes_the_path_of_urls() {
+        assert_eq!(path("/containers/json"), "/containers/json");
+        assert_eq!(path("http://localhost/v1.43/info?a=1"), "/v1.43/info?a=1");
+        assert_eq!(path("http://localhost"), "/");
+    }
+}
@@ -1,4 +1,4 @@
-use macros_lib::{c_struct, quote};
+use macros_lib::{c_struct, proc_macro2, quote};
 use proc_macro::TokenStream;
 use quote::quote;
 
@@ -9,6 +9,8 @@ pub fn plugin(input: TokenStream) -> TokenStream {
         Err(e) => return e.into(),
     };
 
+    let helpers = instance_helpers();
+    let io_helpers = io_helpers();
     let patched = quote! {
         use nexus_api::{RuntimeRef, Plugin};
         use std::sync::OnceLock;
@@ -21,17 +23,69 @@ pub fn plugin(input: TokenStream) -> TokenStream {
             runtime: OnceLock<RuntimeRef>,
         }
 
+        #helpers
+        #io_helpers
+
+        #[nexus_api::async_trait]
+        impl Plugin for Instance {
+            fn init(&mut self, runtime: RuntimeRef) {
+                self.runtime.set(runtime).expect("Runtime already initialized");
+            }
+
+            #input
+        }
+
+        #[expect(clippy::no_mangle_with_rust_abi)]
+        #[expect(unsafe_code)]
+        #[unsafe(no_mangle)]
+        pub extern "Rust" fn _new_rust_impl() -> Box<dyn Plugin> {
+            Box::new(Instance {
+                runtime: OnceLock::new(),
+            })
+        }
+    };
+
+    patched.into()
+}
+
+/// Convenience methods of the generated `Instance`, forwarding to its runtime.
+fn instance_helpers() -> proc_macro2::TokenStream {
+    quote! {
         impl Instance {
             fn runtime(&self) -> &RuntimeRef {
                 self.runtime.get().expect("Plugin runtime not initialized. Call init() before main().")
             }
 
             /// Spawn a task on the runtime
-            pub fn spawn<F>(&self, future: F) -> nexus_api::task::JoinHandle<()>
+            pub fn spawn<F, T>(&self, future: F) -> nexus_api::TaskHandle<T>
             where
-                F: std::future::Future<Output = ()> + Send + 'static,
+                F: std::future::Future<Output = T> + Send + 'static,
+                T: Send + 'static,
             {
-                self.runtime().spawn(Box::pin(future))
+                self.runtime().spawn(future)
+            }
+
+            /// Spawn a task that shows up under `name` in diagnostics
+            pub fn spawn_named<F, T>(&self, name: &str, future: F) -> nexus_api::TaskHandle<T>
+            where
+                F: std::future::Future<Output = T> + Send + 'static,
+                T: Send + 'static,
+            {
+                self.runtime().spawn_named(name, future)
+            }
+
+            /// Run blocking code without stalling the async executor
+            pub fn spawn_blocking<F, T>(&self, name: &str, f: F) -> nexus_api::TaskHandle<T>
+            where
+                F: FnOnce() -> T + Send + 'static,
+                T: Send + 'static,
+            {
+                self.runtime().spawn_blocking(name, f)
+            }
+
+            /// Group of tasks that can be aborted together
+            pub fn task_group(&self, name: &str) -> nexus_api::TaskGroup {
+                nexus_api::TaskGroup::new(std::sync::Arc::clone(self.runtime()), name)
             }
 
             /// Sleep for a duration
@@ -39,30 +93,188 @@ pub fn plugin(input: TokenStream) -> TokenStream {
                 self.runtime().sleep(duration)
             }
 
+            /// Sleep until a deadline
+            pub fn sleep_until(&self, deadline: nexus_api::Instant) -> nexus_api::Sleep {
+                self.runtime().sleep_until(deadline)
+            }
+
             /// Get current instant
             pub fn now(&self) -> nexus_api::Instant {
                 self.runtime().now()
             }
-        }
 
-        #[nexus_api::async_trait]
-        impl Plugin for Instance {
-            fn init(&mut self, runtime: RuntimeRef) {
-                self.runtime.set(runtime).expect("Runtime already initialized");
+            /// Run a future for at most a duration
+            pub async fn timeout<F, T>(
+                &self,
+                duration: nexus_api::Duration,
+                future: F,
+            ) -> Result<T, nexus_api::TimeoutError>
+            where
+                F: std::future::Future<Output = T> + Send + 'static,
+                T: Send + 'static,
+            {
+                self.runtime().timeout(duration, future).await
             }
 
-            #input
-        }
+            /// Token that fires on shutdown, unload or reload
+            pub fn cancellation(&self) -> &nexus_api::CancellationToken {
+                self.runtime().cancellation()
+            }
 
-        #[expect(clippy::no_mangle_with_rust_abi)]
-        #[expect(unsafe_code)]
-        #[unsafe(no_mangle)]
-        pub extern "Rust" fn _new_rust_impl() -> Box<dyn Plugin> {
-            Box::new(Instance {
-                runtime: OnceLock::new(),
-            })
+            /// Whether the plugin was asked to stop
+            pub fn is_cancelled(&self) -> bool {
+                self.cancellation().is_cancelled()
+            }
+
+            /// Spawn a task that is dropped as soon as the plugin is asked to stop
+            pub fn spawn_cancellable<F, T>(
+                &self,
+                name: &str,
+                future: F,
+            ) -> nexus_api::TaskHandle<Result<T, nexus_api::Cancelled>>
+            where
+                F: std::future::Future<Output = T> + Send + 'static,
+                T: Send + 'static,
+            {
+                let cancellation = self.cancellation().clone();
+                self.spawn_named(name, async move {
+                    cancellation.run_until_cancelled(future).await
+                })
+            }
+
+            /// Sleep for a duration, waking up early if the plugin is asked to stop
+            pub async fn sleep_cancellable(
+                &self,
+                duration: nexus_api::Duration,
+            ) -> Result<(), nexus_api::Cancelled> {
+                self.cancellation()
+                    .run_until_cancelled(self.sleep(duration))
+                    .await
+            }
         }
-    };
+    }
+}
 
-    patched.into()
+/// Filesystem and process methods of the generated `Instance`.
+fn io_helpers() -> proc_macro2::TokenStream {
+    quote! {
+        impl Instance {
+            /// Read a whole file through the host
+            pub async fn read_file(
+                &self,
+                path: impl AsRef<std::path::Path>,
+            ) -> std::io::Result<Vec<u8>> {
+                self.runtime().read_file(path.as_ref()).await
+            }
+
+            /// Read a whole UTF-8 file through the host
+            pub async fn read_to_string(
+                &self,
+                path: impl AsRef<std::path::Path>,
+            ) -> std::io::Result<String> {
+                self.runtime().read_to_string(path.as_ref()).await
+            }
+
+            /// Follow a file like `tail -F`
+            pub async fn tail_file(
+                &self,
+                path: impl AsRef<std::path::Path>,
+                options: nexus_api::TailOptions,
+            ) -> std::io::Result<nexus_api::Lines> {
+                self.runtime().tail_file(path.as_ref(), options).await
+            }
+
+            /// Run a process to completion through the host
+            pub async fn run_process(
+                &self,
+                command: nexus_api::ProcessCommand,
+            ) -> std::io::Result<nexus_api::ProcessOutput> {
+                self.runtime().run_process(command).await
+            }
+
+            /// Spawn a process through the host, streaming its output
+            pub fn spawn_process(
+                &self,
+                command: nexus_api::ProcessCommand,
+            ) -> std::io::Result<nexus_api::ChildProcess> {
+                self.runtime().spawn_process(command)
+            }
+
+            /// Settings of the plugin, in TOML, if the host has any
+            pub fn settings(&self) -> Option<&str> {
+                self.runtime().settings()
+            }
+
+            /// Key-value store of the plugin, kept across restarts
+            pub fn storage(&self) -> Result<nexus_api::StorageRef, nexus_api::StorageError> {
+                self.runtime().storage()
+            }
+
+            /// HTTP client shared by the host
+            pub fn http(&self) -> Result<nexus_api::HttpClientRef, nexus_api::HttpError> {
+                self.runtime().http()
+            }
+
+            /// Send a request through the host's HTTP client
+            pub async fn http_request(
+                &self,
+                request: nexus_api::HttpRequest,
+            ) -> Result<nexus_api::HttpResponse, nexus_api::HttpError> {
+                self.runtime().http()?.execute(request).await
+            }
+
+            /// Send a request to the HTTP server on a Unix socket, through the host
+            pub async fn unix_request(
+                &self,
+                socket: impl AsRef<std::path::Path>,
+                request: nexus_api::HttpRequest,
+            ) -> Result<nexus_api::HttpResponse, nexus_api::HttpError> {
+                self.runtime().unix_request(socket.as_ref(), request).await
+            }
+
+            /// Check `host` is reachable over TCP on `port`, through the host
+            pub async fn connect_tcp(
+                &self,
+                host: &str,
+                port: u16,
+            ) -> std::io::Result<std::net::SocketAddr> {
+                self.runtime().connect_tcp(host, port).await
+            }
+
+            /// Resolve `host` to its addresses, through the host
+            pub async fn resolve(
+                &self,
+                host: &str,
+            ) -> std::io::Result<Vec<std::net::IpAddr>> {
+                self.runtime().resolve(host).await
+            }
+
+            /// Fetch the certificates a TLS server presents, through the host
+            pub async fn tls_certificates(
+                &self,
+                host: &str,
+                port: u16,
+                server_name: Option<&str>,
+            ) -> std::io::Result<Vec<Vec<u8>>> {
+                self.runtime()
+                    .tls_certificates(host, port, server_name)
+                    .await
+            }
+
+            /// Serve a route under `/plugins/<name>` on the host's HTTP server
+            pub fn route<F, Fut>(
+                &self,
+                method: nexus_api::Method,
+                path: &str,
+                handler: F,
+            ) -> Result<(), nexus_api::RouteError>
+            where
+                F: Fn(nexus_api::IncomingRequest) -> Fut + Send + Sync + 'static,
+                Fut: std::future::Future<Output = nexus_api::HttpResponse> + Send + 'static,
+            {
+                self.runtime()
+                    .register_route(nexus_api::Route::new(method, path, handler))
+            }
+        }
+    }
 }
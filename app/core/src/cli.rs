@@ -0,0 +1,94 @@
+use clap::{Parser, Subcommand};
+
+use crate::{config::Config, storage::Store};
+
+/// Plugin host for server management.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Inspect or reset the key-value store of plugins
+    Storage {
+        #[command(subcommand)]
+        action: StorageAction,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum StorageAction {
+    /// Print every entry stored by a plugin
+    Dump { plugin: String },
+    /// Delete everything stored by a plugin
+    Clear { plugin: String },
+}
+
+impl Command {
+    /// Runs the command instead of the host
+    pub fn run(self, config: &Config) -> Result<(), String> {
+        match self {
+            Self::Storage { action } => {
+                let store =
+                    Store::open(&config.data_dir).map_err(|e| e.to_string())?;
+                action.run(&store)
+            }
+        }
+    }
+}
+
+impl StorageAction {
+    fn run(self, store: &Store) -> Result<(), String> {
+        match self {
+            Self::Dump { plugin } => {
+                let entries = store
+                    .namespace(&plugin)
+                    .scan_prefix(&[])
+                    .map_err(|e| e.to_string())?;
+                for (key, value) in entries {
+                    println!("{} = {}", display(&key), display(&value));
+                }
+            }
+            Self::Clear { plugin } => {
+                if store.clear(&plugin).map_err(|e| e.to_string())? {
+                    println!("Cleared the storage of {plugin}");
+                } else {
+                    println!("{plugin} has nothing stored");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Bytes as a quoted string if they're UTF-8, in hex otherwise
+fn display(bytes: &[u8]) -> String {
+    std::str::from_utf8(bytes).map_or_else(
+        |_| {
+            const DIGITS: &[u8; 16] = b"0123456789abcdef";
+            let hex = String::with_capacity(bytes.len() * 2);
+            bytes.iter().fold(hex, |mut hex, b| {
+                hex.push(char::from(DIGITS[usize::from(b >> 4)]));
+                hex.push(char::from(DIGITS[usize::from(b & 0x0f)]));
+                hex
+            })
+        },
+        |s| format!("{s:?}"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn displays_text_quoted_and_binary_in_hex() {
+        assert_eq!(display(b"last_seen"), r#""last_seen""#);
+        assert_eq!(display(b"say \"hi\"\n"), r#""say \"hi\"\n""#);
+        assert_eq!(display(b"\x00\xff\x10"), "00ff10");
+        assert_eq!(display(b""), r#""""#);
+    }
+}
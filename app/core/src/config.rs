@@ -0,0 +1,125 @@
+use std::{collections::HashMap, io, path::Path, path::PathBuf};
+
+use nexus_utils::api::Permissions;
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::http_client::{HttpConfig, RateLimitConfig};
+use crate::server::ServerConfig;
+
+/// Host configuration, read from `nexus.toml`.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Where plugins' key-value stores are kept
+    pub data_dir: PathBuf,
+    /// HTTP client shared by plugins
+    pub http: HttpConfig,
+    /// Embedded HTTP server, disabled if unset
+    pub server: Option<ServerConfig>,
+    /// Per plugin settings, keyed by the plugin's name
+    pub plugins: HashMap<String, PluginConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            data_dir: PathBuf::from("./data"),
+            http: HttpConfig::default(),
+            server: None,
+            plugins: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PluginConfig {
+    pub permissions: PermissionsConfig,
+    pub http: RateLimitConfig,
+    /// Handed as is to the plugin, which parses it
+    pub settings: Option<toml::Table>,
+}
+
+/// What a plugin may access, nothing by default.
+///
+/// ```toml
+/// [plugins.nexus-metrics.permissions]
+/// read = ["/var/log"]
+/// exec = ["journalctl"]
+/// network = ["discord.com", "*.example.com:443"]
+/// sockets = ["/var/run/docker.sock"]
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PermissionsConfig {
+    /// Directories (or files) the plugin may read from
+    pub read: Vec<PathBuf>,
+    /// Programs the plugin may spawn
+    pub exec: Vec<String>,
+    /// Hosts the plugin may reach, optionally with a port
+    pub network: Vec<String>,
+    /// Unix sockets the plugin may send HTTP requests to
+    pub sockets: Vec<PathBuf>,
+}
+
+impl Config {
+    /// Loads the configuration, falling back to defaults if there's none
+    pub async fn load(path: &Path) -> Result<Self, String> {
+        let contents = match tokio::fs::read_to_string(path).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(Self::default());
+            }
+            Err(e) => {
+                return Err(format!("Failed to read {}: {e}", path.display()));
+            }
+        };
+        toml::from_str(&contents)
+            .map_err(|e| format!("Failed to parse {}: {e}", path.display()))
+    }
+
+    /// Permissions granted to `plugin`
+    pub fn permissions(&self, plugin: &str) -> Permissions {
+        let Some(config) = self.plugins.get(plugin) else {
+            return Permissions::default();
+        };
+        let canonical = |paths: &[PathBuf], permission: &str| {
+            paths
+                .iter()
+                .filter_map(|path| match std::fs::canonicalize(path) {
+                    Ok(path) => Some(path),
+                    Err(e) => {
+                        warn!(
+                            "Ignoring {permission} permission of {plugin} for {}: {e}",
+                            path.display()
+                        );
+                        None
+                    }
+                })
+                .collect()
+        };
+        Permissions {
+            read: canonical(&config.permissions.read, "read"),
+            exec: config.permissions.exec.clone(),
+            network: config.permissions.network.clone(),
+            sockets: canonical(&config.permissions.sockets, "socket"),
+        }
+    }
+
+    /// Settings of `plugin`, serialized back to TOML
+    pub fn settings(&self, plugin: &str) -> Option<String> {
+        let settings = self.plugins.get(plugin)?.settings.as_ref()?;
+        toml::to_string(settings)
+            .inspect_err(|e| warn!("Failed to pass {plugin} its settings: {e}"))
+            .ok()
+    }
+
+    /// Rate limit of the HTTP requests made by `plugin`
+    pub fn rate_limit(&self, plugin: &str) -> RateLimitConfig {
+        self.plugins
+            .get(plugin)
+            .map(|config| config.http.clone())
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,474 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use nexus_utils::api::{
+    BoxFuture, HttpClient, HttpClientRef, HttpError, HttpRequest, HttpResponse,
+    Permissions,
+};
+use reqwest::{StatusCode, Url, header::RETRY_AFTER, redirect};
+use serde::Deserialize;
+use tracing::debug;
+
+/// Longest wait between two attempts, whatever the server asks for
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Settings of the HTTP client shared by plugins, `[http]` in `nexus.toml`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    /// Timeout of each attempt, unless the request sets its own
+    pub timeout_secs: u64,
+    pub connect_timeout_secs: u64,
+    /// Attempts made after the first one fails
+    pub retries: u32,
+    /// Wait before the first retry, doubled after each attempt
+    pub backoff_ms: u64,
+    /// Proxy used for every request, e.g. `socks5://127.0.0.1:1080`
+    pub proxy: Option<String>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 30,
+            connect_timeout_secs: 10,
+            retries: 3,
+            backoff_ms: 500,
+            proxy: None,
+        }
+    }
+}
+
+/// Per plugin rate limit, `[plugins.<name>.http]` in `nexus.toml`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    /// Sustained requests per second, unlimited if unset
+    pub rate_limit: Option<f64>,
+    /// Requests that may be sent at once after being idle
+    pub burst: Option<u32>,
+}
+
+/// Connection pool and TLS stack shared by every plugin.
+#[derive(Clone)]
+pub struct HttpService {
+    client: reqwest::Client,
+    config: Arc<HttpConfig>,
+}
+
+impl HttpService {
+    pub fn new(config: HttpConfig) -> Result<Self, reqwest::Error> {
+        let mut builder = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+            // Following redirects would get around network permissions
+            .redirect(redirect::Policy::none())
+            .user_agent(concat!("nexus/", env!("CARGO_PKG_VERSION")));
+        if let Some(proxy) = &config.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+        }
+        Ok(Self {
+            client: builder.build()?,
+            config: Arc::new(config),
+        })
+    }
+
+    /// Client of a single plugin
+    pub fn for_plugin(
+        &self,
+        plugin: &str,
+        permissions: Permissions,
+        rate_limit: &RateLimitConfig,
+    ) -> HttpClientRef {
+        let limiter =
+            rate_limit
+                .rate_limit
+                .filter(|rate| *rate > 0.0)
+                .map(|rate| {
+                    let burst = rate_limit
+                        .burst
+                        .map_or_else(|| rate.max(1.0), f64::from);
+                    RateLimiter::new(rate, burst)
+                });
+        Arc::new(PluginHttpClient {
+            service: self.clone(),
+            plugin: plugin.into(),
+            permissions: Arc::new(permissions),
+            limiter: limiter.map(Arc::new),
+        })
+    }
+}
+
+impl fmt::Debug for HttpService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpService")
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+struct PluginHttpClient {
+    service: HttpService,
+    plugin: Arc<str>,
+    permissions: Arc<Permissions>,
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+impl HttpClient for PluginHttpClient {
+    fn execute(
+        &self,
+        request: HttpRequest,
+    ) -> BoxFuture<Result<HttpResponse, HttpError>> {
+        let service = self.service.clone();
+        let plugin = Arc::clone(&self.plugin);
+        let permissions = Arc::clone(&self.permissions);
+        let limiter = self.limiter.clone();
+        Box::pin(async move {
+            let url = Url::parse(&request.url)
+                .map_err(|e| HttpError::InvalidRequest(e.to_string()))?;
+            check_url(&permissions, &url)?;
+
+            let attempts = if request.retryable() {
+                service.config.retries + 1
+            } else {
+                1
+            };
+            let mut backoff = Duration::from_millis(service.config.backoff_ms);
+            for attempt in 1..=attempts {
+                if let Some(limiter) = &limiter
+                    && let Some(delay) = limiter.reserve()
+                {
+                    tokio::time::sleep(delay).await;
+                }
+
+                let result = service.send(&request, url.clone()).await;
+                let wait = match &result {
+                    Ok(response) if should_retry(response.status()) => {
+                        retry_after(response).unwrap_or(backoff)
+                    }
+                    Err(e) if e.is_connect() || e.is_timeout() => backoff,
+                    _ => return finish(result).await,
+                };
+                if attempt == attempts {
+                    return finish(result).await;
+                }
+
+                debug!(
+                    "Retrying {} {} for {plugin} in {wait:?} (attempt {attempt})",
+                    request.method, url
+                );
+                tokio::time::sleep(wait.min(MAX_BACKOFF)).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+            unreachable!("At least one attempt is always made")
+        })
+    }
+}
+
+impl HttpService {
+    async fn send(
+        &self,
+        request: &HttpRequest,
+        url: Url,
+    ) -> Result<reqwest::Response, reqwest::Error> {
+        let method =
+            reqwest::Method::from_bytes(request.method.as_str().as_bytes())
+                .expect("Every method is valid");
+        let timeout = request
+            .timeout
+            .unwrap_or_else(|| Duration::from_secs(self.config.timeout_secs));
+        let mut builder = self
+            .client
+            .request(method, url)
+            .timeout(timeout)
+            .body(request.body.clone());
+        for (name, value) in &request.headers {
+            builder = builder.header(name, value);
+        }
+        builder.send().await
+    }
+}
+
+fn check_url(permissions: &Permissions, url: &Url) -> Result<(), HttpError> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(HttpError::InvalidRequest(format!(
+            "unsupported scheme `{}`",
+            url.scheme()
+        )));
+    }
+    let (Some(host), Some(port)) =
+        (url.host_str(), url.port_or_known_default())
+    else {
+        return Err(HttpError::InvalidRequest(format!("no host in `{url}`")));
+    };
+    if permissions.can_connect(host, port) {
+        Ok(())
+    } else {
+        Err(HttpError::Denied(format!("{host}:{port}")))
+    }
+}
+
+/// Server errors and throttling are worth trying again
+fn should_retry(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+/// Wait asked for by the server, in seconds
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    let secs = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    secs.trim().parse().ok().map(Duration::from_secs)
+}
+
+async fn finish(
+    result: Result<reqwest::Response, reqwest::Error>,
+) -> Result<HttpResponse, HttpError> {
+    let response = result.map_err(|e| convert_error(&e))?;
+    let status = response.status().as_u16();
+    let headers = response
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+            (name.to_string(), value)
+        })
+        .collect();
+    let body = response
+        .bytes()
+        .await
+        .map_err(|e| convert_error(&e))?
+        .to_vec();
+    Ok(HttpResponse {
+        status,
+        headers,
+        body,
+    })
+}
+
+fn convert_error(e: &reqwest::Error) -> HttpError {
+    if e.is_timeout() {
+        HttpError::Timeout
+    } else if e.is_builder() {
+        HttpError::InvalidRequest(e.to_string())
+    } else {
+        HttpError::Request(e.to_string())
+    }
+}
+
+/// Token bucket, refilled continuously at `rate` tokens per second.
+#[derive(Debug)]
+struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    /// Available tokens, negative when requests are waiting for some
+    state: Mutex<(f64, Instant)>,
+}
+
+impl RateLimiter {
+    fn new(rate: f64, burst: f64) -> Self {
+        Self {
+            rate,
+            burst,
+            state: Mutex::new((burst, Instant::now())),
+        }
+    }
+
+    /// Takes a token, returning how long to wait before it's really there
+    fn reserve(&self) -> Option<Duration> {
+        let mut state =
+            self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let (tokens, last) = &mut *state;
+        let now = Instant::now();
+        *tokens = (now.duration_since(*last).as_secs_f64())
+            .mul_add(self.rate, *tokens)
+            .min(self.burst);
+        *last = now;
+        *tokens -= 1.0;
+        let missing = -*tokens;
+        drop(state);
+        (missing > 0.0).then(|| Duration::from_secs_f64(missing / self.rate))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::{
+        Router,
+        extract::State,
+        http::HeaderValue,
+        response::{IntoResponse, Response},
+        routing::any,
+    };
+    use nexus_utils::api::HttpRequest;
+
+    use super::*;
+
+    /// Answers with `statuses` in turn, then repeats the last one
+    struct Mock {
+        statuses: Vec<u16>,
+        hits: AtomicUsize,
+    }
+
+    impl Mock {
+        fn hits(&self) -> usize {
+            self.hits.load(Ordering::SeqCst)
+        }
+    }
+
+    async fn answer(State(mock): State<Arc<Mock>>) -> Response {
+        let hit = mock.hits.fetch_add(1, Ordering::SeqCst);
+        let status = mock.statuses[hit.min(mock.statuses.len() - 1)];
+        let mut response =
+            StatusCode::from_u16(status).unwrap().into_response();
+        if status == 429 {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from_static("0"));
+        }
+        response
+    }
+
+    async fn serve(statuses: &[u16]) -> (String, Arc<Mock>) {
+        let mock = Arc::new(Mock {
+            statuses: statuses.to_vec(),
+            hits: AtomicUsize::new(0),
+        });
+        let app = Router::new()
+            .route("/", any(answer))
+            .with_state(Arc::clone(&mock));
+        let listener =
+            tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr: SocketAddr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (addr.to_string(), mock)
+    }
+
+    /// Client allowed to reach `network`, retrying twice without waiting
+    /// long
+    fn client(network: &[&str], rate_limit: &RateLimitConfig) -> HttpClientRef {
+        let service = HttpService::new(HttpConfig {
+            retries: 2,
+            backoff_ms: 1,
+            ..HttpConfig::default()
+        })
+        .unwrap();
+        let permissions = Permissions {
+            network: network.iter().map(ToString::to_string).collect(),
+            ..Permissions::default()
+        };
+        service.for_plugin("test", permissions, rate_limit)
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors() {
+        let (addr, mock) = serve(&[500, 503, 200]).await;
+        let client = client(&[&addr], &RateLimitConfig::default());
+        let response = client
+            .execute(HttpRequest::get(format!("http://{addr}/")))
+            .await
+            .unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(mock.hits(), 3);
+    }
+
+    #[tokio::test]
+    async fn retries_throttled_requests() {
+        let (addr, mock) = serve(&[429, 200]).await;
+        let client = client(&[&addr], &RateLimitConfig::default());
+        let response = client
+            .execute(HttpRequest::get(format!("http://{addr}/")))
+            .await
+            .unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(mock.hits(), 2);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_last_retry() {
+        let (addr, mock) = serve(&[502]).await;
+        let client = client(&[&addr], &RateLimitConfig::default());
+        let response = client
+            .execute(HttpRequest::get(format!("http://{addr}/")))
+            .await
+            .unwrap();
+        assert_eq!(response.status, 502);
+        assert_eq!(mock.hits(), 3);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_when_disabled() {
+        let (addr, mock) = serve(&[503, 200]).await;
+        let client = client(&[&addr], &RateLimitConfig::default());
+        let url = format!("http://{addr}/");
+        let response = client
+            .execute(HttpRequest::get(&url).retry(false))
+            .await
+            .unwrap();
+        assert_eq!(response.status, 503);
+        assert_eq!(mock.hits(), 1);
+
+        // Nor by default for methods that aren't idempotent
+        let response = client.execute(HttpRequest::post(&url)).await.unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(mock.hits(), 2);
+    }
+
+    #[tokio::test]
+    async fn denies_hosts_not_allowed() {
+        let (addr, mock) = serve(&[200]).await;
+        let client = client(&["example.com"], &RateLimitConfig::default());
+        let result = client
+            .execute(HttpRequest::get(format!("http://{addr}/")))
+            .await;
+        assert_eq!(result.unwrap_err(), HttpError::Denied(addr));
+        assert_eq!(mock.hits(), 0);
+    }
+
+    #[tokio::test]
+    async fn paces_requests_to_the_rate_limit() {
+        let (addr, mock) = serve(&[200]).await;
+        let rate_limit = RateLimitConfig {
+            rate_limit: Some(20.0),
+            burst: Some(1),
+        };
+        let client = client(&[&addr], &rate_limit);
+        let started = Instant::now();
+        for _ in 0..4 {
+            let request = HttpRequest::get(format!("http://{addr}/"));
+            client.execute(request).await.unwrap();
+        }
+        // The first request uses the burst, the others wait 50ms each
+        assert!(started.elapsed() >= Duration::from_millis(140));
+        assert_eq!(mock.hits(), 4);
+    }
+
+    #[test]
+    fn rate_limiter_allows_a_burst() {
+        let limiter = RateLimiter::new(10.0, 2.0);
+        assert_eq!(limiter.reserve(), None);
+        assert_eq!(limiter.reserve(), None);
+        let delay = limiter.reserve().unwrap();
+        assert!(delay > Duration::from_millis(90));
+        assert!(delay <= Duration::from_millis(100));
+        // Requests queue up behind the ones already waiting
+        assert!(limiter.reserve().unwrap() > Duration::from_millis(190));
+    }
+
+    #[test]
+    fn rate_limiter_survives_a_poisoned_lock() {
+        let limiter = Arc::new(RateLimiter::new(10.0, 1.0));
+        let poisoner = Arc::clone(&limiter);
+        std::thread::spawn(move || {
+            let _state = poisoner.state.lock();
+            panic!("Poisoning the rate limiter");
+        })
+        .join()
+        .unwrap_err();
+        assert_eq!(limiter.reserve(), None);
+    }
+}
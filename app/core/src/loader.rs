@@ -1,6 +1,6 @@
 #![expect(unsafe_code)]
 
-use std::{ops::Deref, path::Path};
+use std::{ops::Deref, path::Path, sync::Arc};
 
 use libloading::{Error, Library};
 use nexus_utils::api::{Meta, Plugin, RuntimeRef};
@@ -9,11 +9,16 @@ use tracing::{info, warn};
 pub struct PluginInstance {
     pub(crate) meta: &'static Meta,
     pub(crate) plugin: Box<dyn Plugin>,
+    runtime: RuntimeRef,
     lib: LibWrapper,
 }
 
 impl PluginInstance {
-    pub(crate) fn new<P: AsRef<Path>>(path: P, runtime: RuntimeRef) -> Result<Self, Error> {
+    /// Loads the library at `path`, creating its runtime from its [`Meta`].
+    pub(crate) fn new<P: AsRef<Path>>(
+        path: P,
+        runtime: impl FnOnce(&Meta) -> RuntimeRef,
+    ) -> Result<Self, Error> {
         let path = path.as_ref();
         unsafe {
             info!(
@@ -39,9 +44,38 @@ impl PluginInstance {
                 })?;
             let mut plugin = new();
 
-            plugin.init(runtime);
+            let runtime = runtime(meta);
+            plugin.init(Arc::clone(&runtime));
 
-            Ok(Self { meta, plugin, lib })
+            Ok(Self {
+                meta,
+                plugin,
+                runtime,
+                lib,
+            })
+        }
+    }
+}
+
+/// Unloading (or reloading) a plugin always fires its token first,
+/// then aborts whatever it left running before the library goes away.
+impl Drop for PluginInstance {
+    fn drop(&mut self) {
+        self.runtime.cancellation().cancel();
+
+        let leftover = self.runtime.tasks();
+        if !leftover.is_empty() {
+            let names = leftover
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+            warn!(
+                "Aborting {} task(s) left behind by `{}`: {names}",
+                leftover.len(),
+                self.runtime.plugin_name()
+            );
+            self.runtime.abort_tasks(None);
         }
     }
 }
@@ -1,15 +1,28 @@
+mod cli;
+mod config;
+mod http_client;
 mod loader;
 mod on_shutdown;
+mod registry;
+mod server;
+mod services;
+mod storage;
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::fs;
 use tokio::io::AsyncReadExt;
+use tokio::task::JoinSet;
 
+use clap::Parser;
+use cli::Cli;
+use config::Config;
 use loader::PluginInstance;
-use nexus_utils::api::TokioRuntimeHandle;
+use nexus_utils::api::{CancellationToken, Meta};
 use tracing::info;
 use on_shutdown::with_graceful_shutdown;
+use registry::Registry;
+use services::Services;
 
 // #[cfg(not(target_env = "msvc"))]
 // #[global_allocator]
@@ -17,22 +30,41 @@ use on_shutdown::with_graceful_shutdown;
 
 #[tokio::main]
 async fn main() {
+    let cli = Cli::parse();
+    let config = match Config::load(Path::new("./nexus.toml")).await {
+        Ok(config) => Arc::new(config),
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+    if let Some(command) = cli.command {
+        if let Err(e) = command.run(&config) {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let discord_worker = {
         let path = PathBuf::from("./Logs");
         let discord_hook = Some("https://discord.com/api/webhooks/1302403792595320932/uRKBpnrO0QHW2av73HubxxOgIsOAUPNyrcRFvwwrvBYpxRZMvy-Ycj5I2jZ1AFbr2-OI".to_string());
         nexus_utils::init_logging(&path, "INFO".to_string(), discord_hook).await
     };
 
-    // TODO
-    // Create runtime handle for plugins
-    let runtime_handle = Arc::new(TokioRuntimeHandle::new(tokio::runtime::Handle::current()));
+    let registry = Arc::new(Registry::new());
+
+    // Fired on shutdown, every plugin gets a child of it
+    let cancellation = CancellationToken::new();
+    let services = Services::start(config, &registry, &cancellation);
+    let mut plugins = JoinSet::new();
     // Scan and load all plugins from ./plugins/ directory
     let plugin_dir = PathBuf::from("./plugins");
     let mut entries = match fs::read_dir(&plugin_dir).await {
         Ok(e) => e,
         Err(_) => {
             info!("No plugins directory found at {:?}", plugin_dir);
-            return with_graceful_shutdown(discord_worker).await;
+            return with_graceful_shutdown(discord_worker, cancellation, plugins).await;
         }
     };
 
@@ -66,19 +98,54 @@ async fn main() {
             continue;
         };
 
+        // Create runtime handle for the plugin
+        let handle = tokio::runtime::Handle::current();
+        let runtime = services.runtime(handle, cancellation.child_token());
         let path_for_spawn = path.clone();
-        let runtime_clone = Arc::clone(&runtime_handle);
-        tokio::spawn(async move {
-            match PluginInstance::new(&path_for_spawn, runtime_clone) {
+        let registry = Arc::clone(&registry);
+        let services = services.clone();
+        registry.loading(&plugin_name);
+        plugins.spawn(async move {
+            match PluginInstance::new(&path_for_spawn, runtime) {
                 Ok(instance) => {
+                    registry.running(&plugin_name, instance.meta);
+                    let _stopped = Stopped {
+                        registry,
+                        services,
+                        file: plugin_name,
+                        meta: instance.meta,
+                    };
                     instance.plugin.main().await;
                 }
                 Err(e) => {
                     tracing::error!("Failed to load plugin {}: {}", plugin_name, e);
+                    registry.failed(&plugin_name, e.to_string());
                 }
             }
         });
     }
 
-    with_graceful_shutdown(discord_worker).await;
+    with_graceful_shutdown(discord_worker, cancellation, plugins).await;
+}
+
+/// Marks a plugin as stopped, or failed if it panicked, and forgets its
+/// routes, however its task ends, aborted on shutdown included. Dropped
+/// before its [`PluginInstance`], so that nothing calls into its library
+/// once it's unloaded.
+struct Stopped {
+    registry: Arc<Registry>,
+    services: Services,
+    file: String,
+    meta: &'static Meta,
+}
+
+impl Drop for Stopped {
+    fn drop(&mut self) {
+        self.services.unload(self.meta);
+        if std::thread::panicking() {
+            self.registry.failed(&self.file, "panicked".to_string());
+        } else {
+            self.registry.stopped(&self.file);
+        }
+    }
 }
@@ -1,12 +1,35 @@
-use nexus_utils::BackgroundWorker;
-use tokio::signal;
+use std::time::Duration;
+
+use nexus_utils::{BackgroundWorker, api::CancellationToken};
+use tokio::{signal, task::JoinSet};
 use tracing::{info, warn};
 
+/// How long plugins get to return from `main` after being cancelled.
+const PLUGIN_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
 /// Shutdown routines before exit.
-/// Currently only gracefully disconnects from the database.
-async fn before_shutdown(discord_worker: Option<BackgroundWorker>) {
+/// Asks every plugin to stop, then detaches the Discord worker.
+async fn before_shutdown(
+    discord_worker: Option<BackgroundWorker>,
+    cancellation: CancellationToken,
+    mut plugins: JoinSet<()>,
+) {
     warn!("Shutting down! Running routines...");
 
+    info!("Cancelling {} plugin(s)...", plugins.len());
+    cancellation.cancel();
+    let stopped = tokio::time::timeout(PLUGIN_GRACE_PERIOD, async {
+        while plugins.join_next().await.is_some() {}
+    })
+    .await;
+    if stopped.is_err() {
+        warn!(
+            "{} plugin(s) did not stop within {PLUGIN_GRACE_PERIOD:?}, aborting them",
+            plugins.len()
+        );
+        plugins.shutdown().await;
+    }
+
     // Necessary because Rust will change on ver. 2024
     #[allow(clippy::single_match)]
     match discord_worker {
@@ -19,9 +42,13 @@ async fn before_shutdown(discord_worker: Option<BackgroundWorker>) {
 }
 
 /// Routine for gracefully handling the shutdown.
-pub async fn with_graceful_shutdown(discord_worker: Option<BackgroundWorker>) {
+pub async fn with_graceful_shutdown(
+    discord_worker: Option<BackgroundWorker>,
+    cancellation: CancellationToken,
+    plugins: JoinSet<()>,
+) {
     shutdown_signal().await;
-    before_shutdown(discord_worker).await;
+    before_shutdown(discord_worker, cancellation, plugins).await;
 }
 
 /// Installs signal handlers for SIGTERM/SIGINT.
@@ -0,0 +1,109 @@
+use std::{
+    collections::BTreeMap,
+    sync::{PoisonError, RwLock},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use nexus_utils::api::Meta;
+use serde::Serialize;
+
+/// Where a plugin is in its lifecycle.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", content = "error", rename_all = "lowercase")]
+pub enum PluginState {
+    Loading,
+    Running,
+    Stopped,
+    Failed(String),
+}
+
+/// What the host knows about a plugin, as shown by `/plugins`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PluginEntry {
+    /// File the plugin was loaded from
+    pub file: String,
+    /// From the plugin's [`Meta`], once loaded
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub authors: Option<String>,
+    #[serde(flatten)]
+    pub state: PluginState,
+    /// Unix time of the last state change
+    pub since: u64,
+}
+
+/// Every plugin the host has tried to load, keyed by file name.
+#[derive(Debug)]
+pub struct Registry {
+    started: Instant,
+    plugins: RwLock<BTreeMap<String, PluginEntry>>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            plugins: RwLock::default(),
+        }
+    }
+
+    /// How long the host has been running
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn loading(&self, file: &str) {
+        self.plugins
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(
+                file.to_owned(),
+                PluginEntry {
+                    file: file.to_owned(),
+                    name: None,
+                    version: None,
+                    authors: None,
+                    state: PluginState::Loading,
+                    since: unix_now(),
+                },
+            );
+    }
+
+    pub fn running(&self, file: &str, meta: &Meta) {
+        self.update(file, |entry| {
+            entry.name = Some(meta.name.to_string_lossy().into_owned());
+            entry.version = Some(meta.version.to_string_lossy().into_owned());
+            entry.authors = Some(meta.authors.to_string_lossy().into_owned());
+            entry.state = PluginState::Running;
+        });
+    }
+
+    pub fn stopped(&self, file: &str) {
+        self.update(file, |entry| entry.state = PluginState::Stopped);
+    }
+
+    pub fn failed(&self, file: &str, error: String) {
+        self.update(file, |entry| entry.state = PluginState::Failed(error));
+    }
+
+    pub fn plugins(&self) -> Vec<PluginEntry> {
+        let plugins =
+            self.plugins.read().unwrap_or_else(PoisonError::into_inner);
+        plugins.values().cloned().collect()
+    }
+
+    fn update(&self, file: &str, update: impl FnOnce(&mut PluginEntry)) {
+        let mut plugins =
+            self.plugins.write().unwrap_or_else(PoisonError::into_inner);
+        if let Some(entry) = plugins.get_mut(file) {
+            update(entry);
+            entry.since = unix_now();
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
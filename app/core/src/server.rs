@@ -0,0 +1,285 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use axum::{
+    Json, Router,
+    body::{Body, Bytes},
+    extract::{Path, State},
+    http::{
+        HeaderMap, HeaderName, HeaderValue, StatusCode, Uri,
+        header::CONTENT_TYPE,
+    },
+    response::{IntoResponse, Response},
+    routing::{any, get},
+};
+use nexus_utils::api::{
+    CancellationToken, HttpResponse, IncomingRequest, Method, Route,
+    RouteError, RouteTable,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::{error, info};
+
+use crate::registry::{PluginState, Registry};
+
+/// Path aggregated by the host, and served by plugins exposing metrics
+const METRICS_PATH: &str = "/metrics";
+
+/// Settings of the embedded HTTP server, `[server]` in `nexus.toml`.
+/// The server is disabled unless this section is present.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub bind: SocketAddr,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: SocketAddr::from(([127, 0, 0, 1], 8080)),
+        }
+    }
+}
+
+/// Routes registered by plugins, keyed by plugin name.
+#[derive(Debug, Default)]
+pub struct Routes {
+    routes: RwLock<HashMap<String, Vec<Route>>>,
+}
+
+impl Routes {
+    /// Every plugin's `GET /metrics` route, sorted by plugin
+    fn exporters(&self) -> Vec<Route> {
+        let routes = self.routes.read().unwrap_or_else(PoisonError::into_inner);
+        let mut exporters = routes
+            .iter()
+            .flat_map(|(plugin, routes)| {
+                routes.iter().map(move |r| (plugin, r))
+            })
+            .filter(|(_, r)| r.method == Method::Get && r.path == METRICS_PATH)
+            .map(|(plugin, r)| (plugin.clone(), r.clone()))
+            .collect::<Vec<_>>();
+        drop(routes);
+        exporters.sort_by(|(a, _), (b, _)| a.cmp(b));
+        exporters.into_iter().map(|(_, route)| route).collect()
+    }
+
+    /// Handler for `path` of `plugin`, or the status to answer with
+    fn find(
+        &self,
+        plugin: &str,
+        method: Method,
+        path: &str,
+    ) -> Result<Route, StatusCode> {
+        let routes = self.routes.read().unwrap_or_else(PoisonError::into_inner);
+        let matching = routes
+            .get(plugin)
+            .into_iter()
+            .flatten()
+            .filter(|route| route.matches(path));
+
+        let mut allowed = false;
+        for route in matching {
+            if route.method == method {
+                return Ok(route.clone());
+            }
+            allowed = true;
+        }
+        drop(routes);
+        Err(if allowed {
+            StatusCode::METHOD_NOT_ALLOWED
+        } else {
+            StatusCode::NOT_FOUND
+        })
+    }
+}
+
+impl RouteTable for Routes {
+    fn register(&self, plugin: &str, route: Route) -> Result<(), RouteError> {
+        route.validate()?;
+        let name = format!("{} /plugins/{plugin}{}", route.method, route.path);
+        let mut routes =
+            self.routes.write().unwrap_or_else(PoisonError::into_inner);
+        let plugin_routes = routes.entry(plugin.to_owned()).or_default();
+        let taken = plugin_routes
+            .iter()
+            .any(|r| r.method == route.method && r.path == route.path);
+        if !taken {
+            plugin_routes.push(route);
+        }
+        drop(routes);
+
+        if taken {
+            return Err(RouteError::Conflict(name));
+        }
+        info!("Serving {name}");
+        Ok(())
+    }
+
+    fn unregister(&self, plugin: &str) {
+        self.routes
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(plugin);
+    }
+}
+
+#[derive(Debug, Clone)]
+struct AppState {
+    registry: Arc<Registry>,
+    routes: Arc<Routes>,
+}
+
+/// Serves `/health`, `/plugins` and plugin routes until `cancellation` fires
+pub async fn serve(
+    config: ServerConfig,
+    registry: Arc<Registry>,
+    routes: Arc<Routes>,
+    cancellation: CancellationToken,
+) {
+    let app = Router::new()
+        .route("/health", get(health))
+        .route("/plugins", get(plugins))
+        .route(METRICS_PATH, get(metrics))
+        .route("/plugins/{name}", any(plugin_route_root))
+        .route("/plugins/{name}/{*path}", any(plugin_route))
+        .with_state(AppState { registry, routes });
+
+    let listener = match tokio::net::TcpListener::bind(config.bind).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Failed to bind the HTTP server to {}: {e}", config.bind);
+            return;
+        }
+    };
+    info!("Serving HTTP on {}", config.bind);
+    if let Err(e) = axum::serve(listener, app)
+        .with_graceful_shutdown(async move { cancellation.cancelled().await })
+        .await
+    {
+        error!("HTTP server failed: {e}");
+    }
+}
+
+/// Host and per plugin status.
+/// Answers `503` if any plugin failed, so load balancers can tell.
+async fn health(State(state): State<AppState>) -> Response {
+    let plugins = state.registry.plugins();
+    let healthy = plugins
+        .iter()
+        .all(|p| !matches!(p.state, PluginState::Failed(_)));
+    let statuses = plugins
+        .iter()
+        .map(|p| (p.name.as_ref().unwrap_or(&p.file), &p.state))
+        .collect::<HashMap<_, _>>();
+
+    let status = if healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = json!({
+        "status": if healthy { "ok" } else { "degraded" },
+        "uptime_secs": state.registry.uptime().as_secs(),
+        "plugins": statuses,
+    });
+    (status, Json(body)).into_response()
+}
+
+async fn plugins(State(state): State<AppState>) -> Response {
+    Json(state.registry.plugins()).into_response()
+}
+
+/// Prometheus exposition of every plugin serving `GET /metrics`,
+/// so that one scrape covers the whole host.
+async fn metrics(State(state): State<AppState>) -> Response {
+    let mut body = String::new();
+    for route in state.routes.exporters() {
+        let request = IncomingRequest {
+            method: Method::Get,
+            path: METRICS_PATH.to_owned(),
+            query: None,
+            headers: Vec::new(),
+            body: Vec::new(),
+        };
+        let response = (route.handler)(request).await;
+        if response.is_success() {
+            body.push_str(&response.text());
+        }
+    }
+    let content_type = "text/plain; version=0.0.4; charset=utf-8";
+    ([(CONTENT_TYPE, content_type)], body).into_response()
+}
+
+async fn plugin_route_root(
+    state: State<AppState>,
+    Path(name): Path<String>,
+    method: axum::http::Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    dispatch(&state.routes, &name, "/", method, &uri, &headers, body).await
+}
+
+async fn plugin_route(
+    state: State<AppState>,
+    Path((name, path)): Path<(String, String)>,
+    method: axum::http::Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let path = format!("/{path}");
+    dispatch(&state.routes, &name, &path, method, &uri, &headers, body).await
+}
+
+async fn dispatch(
+    routes: &Routes,
+    plugin: &str,
+    path: &str,
+    method: axum::http::Method,
+    uri: &Uri,
+    headers: &HeaderMap,
+    body: Bytes,
+) -> Response {
+    let Some(method) = Method::parse(method.as_str()) else {
+        return StatusCode::METHOD_NOT_ALLOWED.into_response();
+    };
+    let route = match routes.find(plugin, method, path) {
+        Ok(route) => route,
+        Err(status) => return status.into_response(),
+    };
+
+    let request = IncomingRequest {
+        method,
+        path: path.to_owned(),
+        query: uri.query().map(ToOwned::to_owned),
+        headers: headers
+            .iter()
+            .map(|(name, value)| {
+                let value = String::from_utf8_lossy(value.as_bytes());
+                (name.to_string(), value.into_owned())
+            })
+            .collect(),
+        body: body.to_vec(),
+    };
+    into_response((route.handler)(request).await)
+}
+
+fn into_response(response: HttpResponse) -> Response {
+    let mut reply = Response::new(Body::from(response.body));
+    *reply.status_mut() = StatusCode::from_u16(response.status)
+        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    for (name, value) in response.headers {
+        if let (Ok(name), Ok(value)) =
+            (HeaderName::try_from(name), HeaderValue::try_from(value))
+        {
+            reply.headers_mut().append(name, value);
+        }
+    }
+    reply
+}
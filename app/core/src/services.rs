@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use nexus_utils::api::{
+    CancellationToken, Meta, RouteTable, RuntimeRef, TokioRuntimeHandle,
+};
+use tracing::error;
+
+use crate::{
+    config::Config,
+    http_client::HttpService,
+    registry::Registry,
+    server::{self, Routes},
+    storage::Store,
+};
+
+/// Everything the host shares with plugins, set up once at startup.
+/// A service that fails to start is logged, and plugins run without it.
+#[derive(Debug, Clone)]
+pub struct Services {
+    config: Arc<Config>,
+    store: Option<Store>,
+    http: Option<HttpService>,
+    routes: Option<Arc<Routes>>,
+}
+
+impl Services {
+    /// Starts the services, the HTTP server stopping with `cancellation`
+    pub fn start(
+        config: Arc<Config>,
+        registry: &Arc<Registry>,
+        cancellation: &CancellationToken,
+    ) -> Self {
+        let store = Store::open(&config.data_dir)
+            .inspect_err(|e| error!("Plugins will run without storage: {e}"))
+            .ok();
+        let http = HttpService::new(config.http.clone())
+            .inspect_err(|e| error!("Plugins will run without HTTP: {e}"))
+            .ok();
+        let routes = config.server.clone().map(|server| {
+            let routes = Arc::new(Routes::default());
+            tokio::spawn(server::serve(
+                server,
+                Arc::clone(registry),
+                Arc::clone(&routes),
+                cancellation.child_token(),
+            ));
+            routes
+        });
+
+        Self {
+            config,
+            store,
+            http,
+            routes,
+        }
+    }
+
+    /// Builds the runtime of a plugin, once its [`Meta`] is known
+    pub fn runtime(
+        &self,
+        handle: tokio::runtime::Handle,
+        cancellation: CancellationToken,
+    ) -> impl FnOnce(&Meta) -> RuntimeRef + use<> {
+        let services = self.clone();
+        move |meta: &Meta| -> RuntimeRef {
+            let name = meta.name.to_string_lossy();
+            let permissions = services.config.permissions(&name);
+            let mut runtime =
+                TokioRuntimeHandle::new(handle, &name, cancellation)
+                    .with_permissions(permissions.clone());
+            if let Some(settings) = services.config.settings(&name) {
+                runtime = runtime.with_settings(settings);
+            }
+            if let Some(store) = &services.store {
+                runtime = runtime.with_storage(store.namespace(&name));
+            }
+            if let Some(http) = &services.http {
+                let rate_limit = services.config.rate_limit(&name);
+                runtime = runtime.with_http(http.for_plugin(
+                    &name,
+                    permissions,
+                    &rate_limit,
+                ));
+            }
+            if let Some(routes) = services.routes {
+                runtime = runtime.with_routes(routes);
+            }
+            Arc::new(runtime)
+        }
+    }
+
+    /// Forgets whatever a stopped plugin registered
+    pub fn unload(&self, meta: &Meta) {
+        if let Some(routes) = &self.routes {
+            routes.unregister(&meta.name.to_string_lossy());
+        }
+    }
+}
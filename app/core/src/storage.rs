@@ -0,0 +1,247 @@
+use std::{fmt, path::Path, sync::Arc};
+
+use nexus_utils::api::{
+    Batch, BatchOp, Entry, Storage, StorageError, StorageRef,
+};
+use redb::{Database, ReadableDatabase, TableDefinition, TableError};
+
+/// Name of the database file within the data directory
+const DATABASE_FILE: &str = "storage.redb";
+
+/// Embedded database holding every plugin's key-value store,
+/// one table per plugin.
+#[derive(Clone)]
+pub struct Store {
+    db: Arc<Database>,
+}
+
+impl Store {
+    /// Opens the store in `data_dir`, creating it if needed
+    pub fn open(data_dir: &Path) -> Result<Self, StorageError> {
+        std::fs::create_dir_all(data_dir).map_err(backend)?;
+        let db =
+            Database::create(data_dir.join(DATABASE_FILE)).map_err(backend)?;
+        Ok(Self { db: Arc::new(db) })
+    }
+
+    /// Store of a single plugin
+    pub fn namespace(&self, plugin: &str) -> StorageRef {
+        Arc::new(PluginStorage {
+            db: Arc::clone(&self.db),
+            table: plugin.to_owned(),
+        })
+    }
+
+    /// Removes everything stored by `plugin`, returning whether it had a store
+    pub fn clear(&self, plugin: &str) -> Result<bool, StorageError> {
+        let tx = self.db.begin_write().map_err(backend)?;
+        let existed = tx.delete_table(definition(plugin)).map_err(backend)?;
+        tx.commit().map_err(backend)?;
+        Ok(existed)
+    }
+}
+
+impl fmt::Debug for Store {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Store").finish_non_exhaustive()
+    }
+}
+
+/// Key-value store of a single plugin.
+struct PluginStorage {
+    db: Arc<Database>,
+    table: String,
+}
+
+impl PluginStorage {
+    fn definition(&self) -> TableDefinition<'_, &'static [u8], &'static [u8]> {
+        definition(&self.table)
+    }
+}
+
+impl Storage for PluginStorage {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError> {
+        let tx = self.db.begin_read().map_err(backend)?;
+        let table = match tx.open_table(self.definition()) {
+            Ok(table) => table,
+            Err(TableError::TableDoesNotExist(_)) => return Ok(None),
+            Err(e) => return Err(backend(e)),
+        };
+        let value = table.get(key).map_err(backend)?;
+        Ok(value.map(|value| value.value().to_vec()))
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<(), StorageError> {
+        let tx = self.db.begin_write().map_err(backend)?;
+        tx.open_table(self.definition())
+            .map_err(backend)?
+            .insert(key, value)
+            .map_err(backend)?;
+        tx.commit().map_err(backend)
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<bool, StorageError> {
+        let tx = self.db.begin_write().map_err(backend)?;
+        let existed = tx
+            .open_table(self.definition())
+            .map_err(backend)?
+            .remove(key)
+            .map_err(backend)?
+            .is_some();
+        tx.commit().map_err(backend)?;
+        Ok(existed)
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Result<Vec<Entry>, StorageError> {
+        let tx = self.db.begin_read().map_err(backend)?;
+        let table = match tx.open_table(self.definition()) {
+            Ok(table) => table,
+            Err(TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(backend(e)),
+        };
+
+        let mut entries = Vec::new();
+        for entry in table.range(prefix..).map_err(backend)? {
+            let (key, value) = entry.map_err(backend)?;
+            if !key.value().starts_with(prefix) {
+                break;
+            }
+            entries.push((key.value().to_vec(), value.value().to_vec()));
+        }
+        Ok(entries)
+    }
+
+    fn apply(&self, batch: Batch) -> Result<(), StorageError> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let tx = self.db.begin_write().map_err(backend)?;
+        {
+            let mut table =
+                tx.open_table(self.definition()).map_err(backend)?;
+            for op in batch.ops {
+                match op {
+                    BatchOp::Put { key, value } => {
+                        table.insert(key.as_slice(), value.as_slice())
+                    }
+                    BatchOp::Delete { key } => table.remove(key.as_slice()),
+                }
+                .map_err(backend)?;
+            }
+        }
+        // Dropping an uncommitted transaction aborts it
+        tx.commit().map_err(backend)
+    }
+}
+
+impl fmt::Debug for PluginStorage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PluginStorage")
+            .field("table", &self.table)
+            .finish_non_exhaustive()
+    }
+}
+
+const fn definition(
+    plugin: &str,
+) -> TableDefinition<'_, &'static [u8], &'static [u8]> {
+    TableDefinition::new(plugin)
+}
+
+fn backend(e: impl Into<redb::Error>) -> StorageError {
+    StorageError::Backend(e.into().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> (tempfile::TempDir, Store) {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::open(&dir.path().join("data")).unwrap();
+        (dir, store)
+    }
+
+    fn entries(pairs: &[(&str, &str)]) -> Vec<Entry> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.as_bytes().to_vec(), v.as_bytes().to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn stores_values() {
+        let (_dir, store) = store();
+        let storage = store.namespace("metrics");
+        assert_eq!(storage.get(b"cursor").unwrap(), None);
+        assert!(!storage.delete(b"cursor").unwrap());
+
+        storage.put(b"cursor", b"s=1;i=1").unwrap();
+        storage.put(b"cursor", b"s=1;i=2").unwrap();
+        assert_eq!(storage.get(b"cursor").unwrap(), Some(b"s=1;i=2".to_vec()));
+
+        assert!(storage.delete(b"cursor").unwrap());
+        assert_eq!(storage.get(b"cursor").unwrap(), None);
+    }
+
+    #[test]
+    fn scans_prefixes_in_order() {
+        let (_dir, store) = store();
+        let storage = store.namespace("metrics");
+        assert!(storage.scan_prefix(b"").unwrap().is_empty());
+        for key in ["seen:b", "seen:a", "seem", "seen;", "cursor"] {
+            storage.put(key.as_bytes(), b"1").unwrap();
+        }
+
+        assert_eq!(
+            storage.scan_prefix(b"seen:").unwrap(),
+            entries(&[("seen:a", "1"), ("seen:b", "1")])
+        );
+        assert_eq!(storage.scan_prefix(b"unknown").unwrap(), []);
+        assert_eq!(storage.scan_prefix(b"").unwrap().len(), 5);
+    }
+
+    #[test]
+    fn applies_batches_at_once() {
+        let (_dir, store) = store();
+        let storage = store.namespace("metrics");
+        storage.put(b"old", b"1").unwrap();
+
+        let batch = Batch::new().put(b"new", b"2").delete(b"old");
+        storage.apply(batch).unwrap();
+        assert_eq!(storage.scan_prefix(b"").unwrap(), entries(&[("new", "2")]));
+    }
+
+    #[test]
+    fn isolates_plugins() {
+        let (_dir, store) = store();
+        let metrics = store.namespace("metrics");
+        let logwatch = store.namespace("logwatch");
+        metrics.put(b"cursor", b"metrics").unwrap();
+        logwatch.put(b"cursor", b"logwatch").unwrap();
+
+        assert_eq!(metrics.get(b"cursor").unwrap(), Some(b"metrics".to_vec()));
+        assert!(logwatch.delete(b"cursor").unwrap());
+        assert_eq!(metrics.scan_prefix(b"").unwrap().len(), 1);
+
+        assert!(store.clear("metrics").unwrap());
+        assert!(!store.clear("metrics").unwrap());
+        assert!(!store.clear("healthcheck").unwrap());
+        assert_eq!(metrics.get(b"cursor").unwrap(), None);
+    }
+
+    #[test]
+    fn keeps_values_across_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let store = Store::open(dir.path()).unwrap();
+            store
+                .namespace("metrics")
+                .put(b"cursor", b"s=1;i=5")
+                .unwrap();
+        }
+        let store = Store::open(dir.path()).unwrap();
+        let cursor = store.namespace("metrics").get(b"cursor").unwrap();
+        assert_eq!(cursor, Some(b"s=1;i=5".to_vec()));
+    }
+}
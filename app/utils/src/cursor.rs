@@ -0,0 +1,128 @@
+//! Position in a followed log, kept in the plugin's storage so that
+//! following resumes there after a restart, neither missing nor repeating
+//! lines.
+
+use std::time::{Duration, Instant};
+
+use nexus_api::StorageRef;
+use tracing::warn;
+
+/// How often the cursor is saved, unless a line was reported
+pub const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Cursor of a single log, e.g. a [`nexus_api::TailCursor`] or a journal
+/// cursor, saved under a key of its own so that another log doesn't
+/// resume at a random offset.
+#[derive(Debug)]
+pub struct SavedCursor {
+    storage: Option<StorageRef>,
+    key: Vec<u8>,
+    /// What's followed, as logged, e.g. `the SSH log`
+    log: String,
+    saved: Instant,
+}
+
+impl SavedCursor {
+    /// Kept under `key`, nowhere if there's no storage
+    #[must_use]
+    pub fn new(
+        storage: Option<StorageRef>,
+        key: impl Into<Vec<u8>>,
+        log: impl Into<String>,
+    ) -> Self {
+        Self {
+            storage,
+            key: key.into(),
+            log: log.into(),
+            saved: Instant::now(),
+        }
+    }
+
+    /// Where following stopped before a restart, if it was saved
+    #[must_use]
+    pub fn load(&self) -> Option<Vec<u8>> {
+        let storage = self.storage.as_ref()?;
+        storage
+            .get(&self.key)
+            .inspect_err(|e| {
+                warn!("Failed to read the position in {}: {e}", self.log);
+            })
+            .ok()
+            .flatten()
+    }
+
+    /// Saves `cursor`, read at `now`: right away after a report, so that
+    /// it's not reported again after a restart, else at most every
+    /// [`SAVE_INTERVAL`]
+    pub fn save(
+        &mut self,
+        cursor: Option<&[u8]>,
+        reported: bool,
+        now: Instant,
+    ) {
+        let (Some(storage), Some(cursor)) = (&self.storage, cursor) else {
+            return;
+        };
+        if !reported && now.duration_since(self.saved) < SAVE_INTERVAL {
+            return;
+        }
+        if let Err(e) = storage.put(&self.key, cursor) {
+            warn!("Failed to save the position in {}: {e}", self.log);
+        }
+        self.saved = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use nexus_api::{MemoryStorage, Storage};
+
+    use super::*;
+
+    fn cursor(storage: &Arc<MemoryStorage>) -> SavedCursor {
+        let storage = Arc::clone(storage) as StorageRef;
+        SavedCursor::new(Some(storage), "cursor.auth.log", "`auth.log`")
+    }
+
+    fn saved(storage: &MemoryStorage) -> Option<Vec<u8>> {
+        storage.get(b"cursor.auth.log").unwrap()
+    }
+
+    #[test]
+    fn saves_right_away_after_a_report() {
+        let storage = Arc::new(MemoryStorage::default());
+        let mut cursor = cursor(&storage);
+        let now = Instant::now();
+        cursor.save(Some(b"1"), false, now);
+        assert_eq!(saved(&storage), None);
+        cursor.save(Some(b"2"), true, now);
+        assert_eq!(saved(&storage).as_deref(), Some(&b"2"[..]));
+    }
+
+    #[test]
+    fn saves_every_interval() {
+        let storage = Arc::new(MemoryStorage::default());
+        let mut cursor = cursor(&storage);
+        let start = Instant::now();
+        cursor.save(Some(b"1"), false, start + SAVE_INTERVAL);
+        assert_eq!(saved(&storage).as_deref(), Some(&b"1"[..]));
+        cursor.save(Some(b"2"), false, start + SAVE_INTERVAL * 3 / 2);
+        assert_eq!(saved(&storage).as_deref(), Some(&b"1"[..]));
+        cursor.save(Some(b"3"), false, start + SAVE_INTERVAL * 2);
+        assert_eq!(saved(&storage).as_deref(), Some(&b"3"[..]));
+    }
+
+    #[test]
+    fn loads_what_was_saved() {
+        let storage = Arc::new(MemoryStorage::default());
+        assert_eq!(cursor(&storage).load(), None);
+        cursor(&storage).save(Some(b"42"), true, Instant::now());
+        assert_eq!(cursor(&storage).load(), Some(b"42".to_vec()));
+
+        let mut unsaved = SavedCursor::new(None, "cursor", "the SSH log");
+        unsaved.save(Some(b"42"), true, Instant::now());
+        assert_eq!(unsaved.load(), None);
+    }
+}
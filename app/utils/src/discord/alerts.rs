@@ -0,0 +1,64 @@
+use nexus_api::RuntimeRef;
+use serde_json::{Value, json};
+use tracing::warn;
+
+use super::{Webhook, WebhookError};
+
+/// Where a plugin posts its alerts: the webhook of its settings, if set,
+/// along with the text sent with each alert, e.g. mentions.
+#[derive(Debug, Clone, Default)]
+pub struct Alerts {
+    /// Only logged if none
+    webhook: Option<Webhook>,
+    content: Option<String>,
+}
+
+impl Alerts {
+    /// Posting to `webhook_url` through the plugin's HTTP client, with
+    /// `user_tags` (e.g. `<@1234>` or `@here`) in each alert
+    #[must_use]
+    pub fn new(
+        runtime: &RuntimeRef,
+        webhook_url: Option<String>,
+        user_tags: &[String],
+    ) -> Self {
+        let webhook = webhook_url.and_then(|url| {
+            let http = runtime
+                .http()
+                .inspect_err(|e| {
+                    let plugin = runtime.plugin_name();
+                    warn!("Not posting the alerts of {plugin}: {e}");
+                })
+                .ok()?;
+            Some(Webhook::new(url, http))
+        });
+        Self {
+            webhook,
+            content: (!user_tags.is_empty()).then(|| user_tags.join(" ")),
+        }
+    }
+
+    /// Posts `embed`, unless there's no webhook
+    ///
+    /// # Errors
+    /// If the request fails, or Discord rejects the message.
+    pub async fn send(&self, embed: Value) -> Result<(), WebhookError> {
+        let Some(webhook) = &self.webhook else {
+            return Ok(());
+        };
+        webhook
+            .send(self.content.clone(), vec![embed])
+            .await
+            .map(drop)
+    }
+}
+
+/// Field of an embed, e.g. `field("User", "root", true)`
+#[must_use]
+pub fn field(name: &str, value: &str, inline: bool) -> Value {
+    json!({
+        "name": name,
+        "value": value,
+        "inline": inline,
+    })
+}
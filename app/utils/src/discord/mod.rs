@@ -1,9 +1,15 @@
+mod alerts;
+pub use alerts::{Alerts, field};
+
 mod config;
 pub use config::Config;
 
 mod payload;
 use payload::Payload;
 
+mod webhook;
+pub use webhook::{Webhook, WebhookError};
+
 use tracing::Level;
 pub use tracing_layer_core::filters::EventFilters;
 pub use tracing_layer_core::layer::WebhookLayer;
@@ -16,6 +22,9 @@ use tracing_layer_core::{
 const MAX_FIELD_VALUE_CHARS: usize = 1024 - 15;
 const MAX_ERROR_MESSAGE_CHARS: usize = 2048 - 15;
 
+/// Events of this target are alert transitions, see [`Layer::alert`]
+pub const ALERT_TARGET: &str = "alert";
+
 pub struct Layer {
     config: Config,
 }
@@ -30,6 +39,52 @@ impl Layer {
     }
 }
 
+impl Layer {
+    /// Embed of an alert firing, resolving or triggering once, built from
+    /// the event's `state`, `rule`, `series`, `value` and `threshold` fields.
+    /// Only resolved alerts don't ping.
+    fn alert(
+        &self,
+        app_name: &str,
+        message: &str,
+        metadata: &str,
+    ) -> Box<dyn WebhookMessage> {
+        let fields = serde_json::from_str::<serde_json::Value>(metadata)
+            .unwrap_or_default();
+        let field = |name: &str| match &fields[name] {
+            serde_json::Value::String(s) => s.clone(),
+            serde_json::Value::Null => "?".to_string(),
+            value => value.to_string(),
+        };
+        let (emoji, state, color) = match field("state").as_str() {
+            "firing" => (":rotating_light:", "Firing", 15_548_997),
+            "resolved" => (":white_check_mark:", "Resolved", 5_763_719),
+            _ => (":arrows_counterclockwise:", "Triggered", 15_105_570),
+        };
+        let mut embed = serde_json::json!({
+            "title": format!("{app_name} - {emoji} {state}: {}", field("rule")),
+            "description": message,
+            "fields": [
+                { "name": "Series", "value": format!("`{}`", field("series")), "inline": true },
+                { "name": "Value", "value": field("value"), "inline": true },
+            ],
+            "color": color,
+        });
+        // One-off events have no threshold
+        if !fields["threshold"].is_null() {
+            embed["fields"].as_array_mut().unwrap().push(serde_json::json!(
+                { "name": "Threshold", "value": field("threshold"), "inline": true }
+            ));
+        }
+
+        Box::new(Payload {
+            content: (state != "Resolved").then(|| "@here".to_string()),
+            embeds: Some(vec![embed]),
+            webhook_url: self.config.webhook_url.clone(),
+        })
+    }
+}
+
 impl WebhookMessageFactory for Layer {
     fn create(
         &self,
@@ -50,6 +105,9 @@ impl WebhookMessageFactory for Layer {
             );
             message = message.chars().take(MAX_ERROR_MESSAGE_CHARS).collect();
         }
+        if target == ALERT_TARGET {
+            return self.alert(&app_name, &message, &metadata);
+        }
 
         let emoji = emoji_from_level(event_level);
         let color = color_from_level(event_level);
@@ -0,0 +1,143 @@
+use std::fmt;
+
+use nexus_api::{HttpClientRef, HttpError, HttpRequest, HttpResponse, Method};
+use serde_json::Value;
+use tracing_layer_core::WebhookMessage;
+
+use super::payload::Payload;
+
+/// Sends messages to a Discord webhook through the host's HTTP client,
+/// for plugins posting their own embeds rather than log events.
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    url: String,
+    http: HttpClientRef,
+}
+
+/// Error returned by [`Webhook`] calls.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebhookError {
+    /// The request itself failed
+    Http(HttpError),
+    /// Discord answered with an error status
+    Rejected { status: u16, body: String },
+    /// Discord's answer wasn't the expected message
+    InvalidResponse(String),
+}
+
+impl WebhookError {
+    /// Whether the message doesn't exist (anymore)
+    #[must_use]
+    pub const fn is_not_found(&self) -> bool {
+        matches!(self, Self::Rejected { status: 404, .. })
+    }
+}
+
+impl fmt::Display for WebhookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Http(e) => e.fmt(f),
+            Self::Rejected { status, body } => {
+                write!(f, "Discord answered {status}: {body}")
+            }
+            Self::InvalidResponse(e) => {
+                write!(f, "unexpected answer from Discord: {e}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for WebhookError {}
+
+impl From<HttpError> for WebhookError {
+    fn from(e: HttpError) -> Self {
+        Self::Http(e)
+    }
+}
+
+impl Webhook {
+    #[must_use]
+    pub const fn new(url: String, http: HttpClientRef) -> Self {
+        Self { url, http }
+    }
+
+    /// Posts a new message, returning its ID
+    ///
+    /// # Errors
+    /// If the request fails, or Discord rejects the message.
+    pub async fn send(
+        &self,
+        content: Option<String>,
+        embeds: Vec<Value>,
+    ) -> Result<String, WebhookError> {
+        // Without `wait`, Discord doesn't answer with the message
+        let url = self.endpoint("", "wait=true");
+        let request =
+            HttpRequest::post(url).json(self.payload(content, embeds));
+        let response = self.execute(request).await?;
+        serde_json::from_slice::<Value>(&response.body)
+            .ok()
+            .and_then(|message| message["id"].as_str().map(str::to_string))
+            .ok_or_else(|| WebhookError::InvalidResponse(response.text()))
+    }
+
+    /// Replaces the content and embeds of a message sent by this webhook
+    ///
+    /// # Errors
+    /// If the request fails, or Discord rejects the edit, e.g. because the
+    /// message was deleted, see [`WebhookError::is_not_found`].
+    pub async fn edit(
+        &self,
+        message_id: &str,
+        content: Option<String>,
+        embeds: Vec<Value>,
+    ) -> Result<(), WebhookError> {
+        let url = self.endpoint(&format!("/messages/{message_id}"), "");
+        let request = HttpRequest::new(Method::Patch, url)
+            .json(self.payload(content, embeds))
+            .retry(true);
+        self.execute(request).await.map(drop)
+    }
+
+    fn payload(&self, content: Option<String>, embeds: Vec<Value>) -> String {
+        Payload {
+            content,
+            embeds: Some(embeds),
+            webhook_url: self.url.clone(),
+        }
+        .serialize()
+    }
+
+    /// The webhook's URL with `path` appended, keeping its query string
+    /// (e.g. `thread_id`) along with `query`
+    fn endpoint(&self, path: &str, query: &str) -> String {
+        let (base, existing) =
+            self.url.split_once('?').unwrap_or((self.url.as_str(), ""));
+        let query = [existing, query]
+            .into_iter()
+            .filter(|q| !q.is_empty())
+            .collect::<Vec<_>>()
+            .join("&");
+        let base = base.trim_end_matches('/');
+        if query.is_empty() {
+            format!("{base}{path}")
+        } else {
+            format!("{base}{path}?{query}")
+        }
+    }
+
+    async fn execute(
+        &self,
+        request: HttpRequest,
+    ) -> Result<HttpResponse, WebhookError> {
+        let response = self.http.execute(request).await?;
+        if response.is_success() {
+            Ok(response)
+        } else {
+            Err(WebhookError::Rejected {
+                status: response.status,
+                body: response.text(),
+            })
+        }
+    }
+}
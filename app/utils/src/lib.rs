@@ -1,5 +1,7 @@
 mod canonicalize_unexistent;
+pub mod cursor;
 pub mod discord;
+pub mod prometheus;
 use canonicalize_unexistent::canonicalize_unexistent;
 
 mod init_logging;
@@ -0,0 +1,113 @@
+//! Writing of the Prometheus text exposition format, served by plugins on
+//! their `/metrics` route.
+
+use std::fmt::{self, Write};
+
+/// Content type of the text exposition format
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// What `write` writes
+pub fn render(write: impl FnOnce(&mut String) -> fmt::Result) -> String {
+    let mut out = String::new();
+    // Writing to a `String` never fails
+    let _ = write(&mut out);
+    out
+}
+
+/// Help and type of the gauge `name`, before its samples
+///
+/// # Errors
+/// If writing to `out` fails.
+pub fn gauge(out: &mut impl Write, name: &str, help: &str) -> fmt::Result {
+    writeln!(out, "# HELP {name} {help}")?;
+    writeln!(out, "# TYPE {name} gauge")
+}
+
+/// Help and type of the counter `name`, before its samples
+///
+/// # Errors
+/// If writing to `out` fails.
+pub fn counter(out: &mut impl Write, name: &str, help: &str) -> fmt::Result {
+    writeln!(out, "# HELP {name} {help}")?;
+    writeln!(out, "# TYPE {name} counter")
+}
+
+/// Sample of `name`, e.g. `name{key="value"} 1`. Infinities are written
+/// `+Inf` and `-Inf`, and NaN `NaN`, as the format spells them.
+///
+/// # Errors
+/// If writing to `out` fails.
+pub fn sample(
+    out: &mut impl Write,
+    name: &str,
+    labels: &[(&str, &str)],
+    value: f64,
+) -> fmt::Result {
+    out.write_str(name)?;
+    if !labels.is_empty() {
+        out.write_char('{')?;
+        for (i, (key, value)) in labels.iter().enumerate() {
+            if i > 0 {
+                out.write_char(',')?;
+            }
+            write!(out, "{key}=\"{}\"", escape(value))?;
+        }
+        out.write_char('}')?;
+    }
+    match value {
+        f64::INFINITY => writeln!(out, " +Inf"),
+        f64::NEG_INFINITY => writeln!(out, " -Inf"),
+        value => writeln!(out, " {value}"),
+    }
+}
+
+/// Escapes a label value as required by the exposition format
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_series() {
+        let out = render(|out| {
+            gauge(out, "nexus_up", "Whether it's up.")?;
+            sample(out, "nexus_up", &[], 1.0)?;
+            counter(out, "nexus_hits_total", "Hits.")?;
+            sample(out, "nexus_hits_total", &[("a", "1"), ("b", "2")], 3.5)
+        });
+        assert_eq!(
+            out,
+            "# HELP nexus_up Whether it's up.\n\
+             # TYPE nexus_up gauge\n\
+             nexus_up 1\n\
+             # HELP nexus_hits_total Hits.\n\
+             # TYPE nexus_hits_total counter\n\
+             nexus_hits_total{a=\"1\",b=\"2\"} 3.5\n"
+        );
+    }
+
+    #[test]
+    fn escapes_label_values() {
+        let out = render(|out| {
+            sample(out, "nexus_up", &[("path", "C:\\a \"b\"\nc")], 0.0)
+        });
+        assert_eq!(out, "nexus_up{path=\"C:\\\\a \\\"b\\\"\\nc\"} 0\n");
+    }
+
+    #[test]
+    fn spells_out_special_values() {
+        let out = render(|out| {
+            sample(out, "a", &[], f64::INFINITY)?;
+            sample(out, "b", &[], f64::NEG_INFINITY)?;
+            sample(out, "c", &[], f64::NAN)?;
+            sample(out, "d", &[], -0.5)
+        });
+        assert_eq!(out, "a +Inf\nb -Inf\nc NaN\nd -0.5\n");
+    }
+}
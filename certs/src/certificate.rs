@@ -0,0 +1,143 @@
+use std::net::IpAddr;
+
+use x509_parser::extensions::GeneralName;
+use x509_parser::pem::Pem;
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+/// What's shown of a certificate.
+#[derive(Debug, Clone)]
+pub struct Certificate {
+    pub subject: String,
+    pub issuer: String,
+    /// Subject alternative names, DNS names and IPs
+    pub names: Vec<String>,
+    /// Hexadecimal
+    pub serial: String,
+    /// Unix timestamps
+    pub not_before: i64,
+    pub not_after: i64,
+}
+
+impl Certificate {
+    /// From a single DER-encoded certificate
+    pub fn from_der(der: &[u8]) -> Result<Self, String> {
+        let (_, certificate) = X509Certificate::from_der(der)
+            .map_err(|e| format!("invalid certificate: {e}"))?;
+        let names = match certificate.subject_alternative_name() {
+            Ok(Some(names)) => {
+                names.value.general_names.iter().filter_map(name).collect()
+            }
+            Ok(None) => Vec::new(),
+            Err(e) => return Err(format!("invalid alternative names: {e}")),
+        };
+        let validity = certificate.validity();
+        Ok(Self {
+            subject: certificate.subject().to_string(),
+            issuer: certificate.issuer().to_string(),
+            names,
+            serial: certificate.raw_serial_as_string(),
+            not_before: validity.not_before.timestamp(),
+            not_after: validity.not_after.timestamp(),
+        })
+    }
+
+    /// Every certificate of a PEM file, or the file itself if it's DER
+    pub fn parse_file(contents: &[u8]) -> Result<Vec<Self>, String> {
+        let mut certificates = Vec::new();
+        for pem in Pem::iter_from_buffer(contents) {
+            let pem = match pem {
+                Ok(pem) => pem,
+                // Not PEM at all, so maybe DER
+                Err(_) if certificates.is_empty() => break,
+                Err(e) => return Err(format!("invalid PEM: {e}")),
+            };
+            // Keys and parameters may be in the same file
+            if pem.label == "CERTIFICATE" {
+                certificates.push(Self::from_der(&pem.contents)?);
+            }
+        }
+        if certificates.is_empty() {
+            let der = Self::from_der(contents)
+                .map_err(|_| "no certificate found".to_string())?;
+            certificates.push(der);
+        }
+        Ok(certificates)
+    }
+}
+
+/// DNS name or IP, others aren't used to reach servers
+fn name(name: &GeneralName<'_>) -> Option<String> {
+    match name {
+        GeneralName::DNSName(name) => Some((*name).to_string()),
+        GeneralName::IPAddress(ip) => <[u8; 4]>::try_from(*ip)
+            .map(IpAddr::from)
+            .or_else(|_| <[u8; 16]>::try_from(*ip).map(IpAddr::from))
+            .ok()
+            .map(|ip| ip.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rcgen::{CertificateParams, DnType, KeyPair, date_time_ymd};
+
+    use super::*;
+
+    /// Self-signed, for `names`, expiring at the start of `year`
+    fn generate(name: &str, year: i32) -> (rcgen::Certificate, KeyPair) {
+        let names = vec![format!("{name}.example"), "192.0.2.1".to_string()];
+        let mut params = CertificateParams::new(names).unwrap();
+        params.distinguished_name.push(DnType::CommonName, name);
+        params.not_before = date_time_ymd(2025, 1, 1);
+        params.not_after = date_time_ymd(year, 1, 1);
+        let key = KeyPair::generate().unwrap();
+        (params.self_signed(&key).unwrap(), key)
+    }
+
+    /// `2030-01-01T00:00:00Z`
+    const EXPIRY_2030: i64 = 1_893_456_000;
+
+    #[test]
+    fn parses_der() {
+        let (generated, _) = generate("mail", 2030);
+        let certificate = Certificate::from_der(generated.der()).unwrap();
+        assert_eq!(certificate.subject, "CN=mail");
+        assert_eq!(certificate.issuer, "CN=mail");
+        assert_eq!(certificate.names, ["mail.example", "192.0.2.1"]);
+        assert_eq!(certificate.not_before, 1_735_689_600);
+        assert_eq!(certificate.not_after, EXPIRY_2030);
+        assert!(!certificate.serial.is_empty());
+
+        let file = Certificate::parse_file(generated.der()).unwrap();
+        assert_eq!(file.len(), 1);
+        assert_eq!(file[0].serial, certificate.serial);
+    }
+
+    #[test]
+    fn parses_every_certificate_of_a_pem_file() {
+        let (leaf, key) = generate("leaf", 2030);
+        let (intermediate, _) = generate("intermediate", 2028);
+        let pem = [key.serialize_pem(), leaf.pem(), intermediate.pem()];
+        let certificates = Certificate::parse_file(pem.concat().as_bytes())
+            .unwrap()
+            .into_iter()
+            .map(|certificate| certificate.subject)
+            .collect::<Vec<_>>();
+        assert_eq!(certificates, ["CN=leaf", "CN=intermediate"]);
+    }
+
+    #[test]
+    fn rejects_files_without_certificates() {
+        let key = KeyPair::generate().unwrap();
+        for contents in
+            [&b"not a certificate"[..], key.serialize_pem().as_bytes()]
+        {
+            let error = Certificate::parse_file(contents).unwrap_err();
+            assert_eq!(error, "no certificate found");
+        }
+        let (generated, _) = generate("mail", 2030);
+        let truncated = &generated.der()[..100];
+        assert!(Certificate::from_der(truncated).is_err());
+    }
+}
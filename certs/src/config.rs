@@ -0,0 +1,229 @@
+use serde::{Deserialize, Deserializer};
+use std::collections::HashSet;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Settings of the plugin, from `[plugins.nexus-certs.settings]` in the
+/// host's `nexus.toml`.
+///
+/// ```toml
+/// [plugins.nexus-certs.settings]
+/// webhook_url = "https://discord.com/api/webhooks/..."
+///
+/// [[plugins.nexus-certs.settings.certificates]]
+/// name = "website"
+/// type = "endpoint"
+/// host = "example.com"
+///
+/// [[plugins.nexus-certs.settings.certificates]]
+/// name = "mail"
+/// type = "file"
+/// path = "/etc/ssl/mail/fullchain.pem"
+/// thresholds = [60, 30, 7]
+/// ```
+///
+/// Endpoints need a network permission, and files a read one.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Discord webhook alerts are posted to, none if unset
+    pub webhook_url: Option<String>,
+    /// Mentions added to the alerts, e.g. `<@1234>` or `@here`
+    pub user_tags: Vec<String>,
+    /// How often every certificate is checked
+    #[serde(deserialize_with = "duration")]
+    pub interval: Duration,
+    /// How long fetching a certificate may take
+    #[serde(deserialize_with = "duration")]
+    pub timeout: Duration,
+    /// Days before expiry alerted on, for the certificates that don't set
+    /// their own
+    pub thresholds: Vec<u32>,
+    pub certificates: Vec<CertificateConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            webhook_url: None,
+            user_tags: Vec::new(),
+            interval: Duration::from_hours(6),
+            timeout: Duration::from_secs(10),
+            thresholds: vec![30, 14, 7, 1],
+            certificates: Vec::new(),
+        }
+    }
+}
+
+/// Certificate watched for its expiry.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CertificateConfig {
+    /// Unique, shown in alerts and results
+    pub name: String,
+    pub thresholds: Option<Vec<u32>>,
+    #[serde(flatten)]
+    pub source: Source,
+}
+
+/// Where a certificate is read from, by its `type`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Source {
+    /// Presented by a TLS server, the leaf of the chain it sends
+    Endpoint {
+        host: String,
+        #[serde(default = "https_port")]
+        port: u16,
+        /// Sent as SNI, `host` if unset
+        #[serde(default)]
+        server_name: Option<String>,
+    },
+    /// PEM file, or a single DER-encoded certificate. If there are several,
+    /// e.g. a chain, the first to expire is watched.
+    File { path: PathBuf },
+}
+
+const fn https_port() -> u16 {
+    443
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Endpoint {
+                host,
+                port,
+                server_name,
+            } => {
+                let host = if host.contains(':') {
+                    format!("[{host}]")
+                } else {
+                    host.clone()
+                };
+                write!(f, "{host}:{port}")?;
+                match server_name {
+                    Some(name) if *name != host => write!(f, " ({name})"),
+                    _ => Ok(()),
+                }
+            }
+            Self::File { path } => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// A certificate along with the defaults it didn't override
+#[derive(Debug, Clone)]
+pub struct Watched {
+    pub name: String,
+    /// Days before expiry, largest first
+    pub thresholds: Vec<u32>,
+    pub source: Source,
+}
+
+/// Why the settings were rejected.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// Not valid TOML, or not matching [`Config`]
+    Parse(toml::de::Error),
+    /// Well-formed, but unusable
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse(e) => write!(f, "failed to parse settings: {e}"),
+            Self::Invalid(e) => write!(f, "invalid settings: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Parses and validates the plugin's settings, defaults if there's none
+    pub fn load(settings: Option<&str>) -> Result<Self, ConfigError> {
+        let config = match settings {
+            Some(settings) => {
+                toml::from_str::<Self>(settings).map_err(ConfigError::Parse)?
+            }
+            None => Self::default(),
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |e: String| Err(ConfigError::Invalid(e));
+        if let Some(url) = &self.webhook_url
+            && !url.starts_with("https://")
+            && !url.starts_with("http://")
+        {
+            return invalid(format!("`webhook_url` isn't a URL: {url}"));
+        }
+        if self.interval.is_zero() || self.timeout.is_zero() {
+            return invalid("`interval` and `timeout` must not be zero".into());
+        }
+        let mut names = HashSet::new();
+        for watched in self.certificates() {
+            let name = &watched.name;
+            if name.is_empty() || name.contains('/') {
+                return invalid(format!("invalid certificate name `{name}`"));
+            }
+            if !names.insert(name.clone()) {
+                return invalid(format!(
+                    "certificate `{name}` is defined twice"
+                ));
+            }
+            if watched.thresholds.contains(&0) {
+                return invalid(format!(
+                    "certificate `{name}` has a zero-day threshold, expiry is \
+                     always alerted on"
+                ));
+            }
+            match &watched.source {
+                Source::Endpoint { host, .. } if host.is_empty() => {
+                    return invalid(format!(
+                        "certificate `{name}` has no host"
+                    ));
+                }
+                Source::File { path } if path.as_os_str().is_empty() => {
+                    return invalid(format!(
+                        "certificate `{name}` has no path"
+                    ));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Configured certificates, with the defaults applied
+    pub fn certificates(&self) -> Vec<Watched> {
+        self.certificates
+            .iter()
+            .map(|certificate| {
+                let mut thresholds = certificate
+                    .thresholds
+                    .clone()
+                    .unwrap_or_else(|| self.thresholds.clone());
+                thresholds.sort_unstable_by(|a, b| b.cmp(a));
+                thresholds.dedup();
+                Watched {
+                    name: certificate.name.clone(),
+                    thresholds,
+                    source: certificate.source.clone(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Human-readable duration, e.g. `1h 30m`
+fn duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Duration, D::Error> {
+    let duration = String::deserialize(deserializer)?;
+    humantime::parse_duration(&duration).map_err(serde::de::Error::custom)
+}
@@ -0,0 +1,114 @@
+mod certificate;
+mod config;
+mod monitor;
+mod prometheus;
+mod state;
+
+use std::sync::Arc;
+
+use config::Config;
+use monitor::Monitor;
+use nexus_api::{HttpResponse, Meta, Method, RouteError, r#impl};
+use nexus_utils::discord::Alerts;
+use state::Results;
+use tracing::{error, info, warn};
+
+r#impl! {
+    pub static META: Meta = Meta {
+        name: env!("CARGO_PKG_NAME"),
+        authors: env!("CARGO_PKG_AUTHORS"),
+        version: env!("CARGO_PKG_VERSION"),
+    };
+
+    async fn main(&self) {
+        let config = match Config::load(self.settings()) {
+            Ok(config) => config,
+            Err(e) => {
+                error!("Not checking certificates, {e}");
+                return;
+            }
+        };
+        let certificates = config.certificates();
+        if certificates.is_empty() {
+            info!("No certificates configured");
+            return;
+        }
+
+        let storage = self
+            .storage()
+            .inspect_err(|e| warn!("Alerts on certificates won't be kept: {e}"))
+            .ok();
+        let results = Arc::new(Results::new(&certificates, storage));
+        self.serve(&results);
+
+        let monitor = Arc::new(Monitor::new(
+            Arc::clone(self.runtime()),
+            results,
+            Alerts::new(
+                self.runtime(),
+                config.webhook_url.clone(),
+                &config.user_tags,
+            ),
+            config.interval,
+            config.timeout,
+        ));
+        for watched in certificates.iter().cloned() {
+            let monitor = Arc::clone(&monitor);
+            let name = format!("certificate {}", watched.name);
+            self.spawn_cancellable(&name, async move {
+                monitor.watch(watched).await;
+            });
+        }
+        info!("Now checking {} certificates", certificates.len());
+
+        self.cancellation().cancelled().await;
+        info!("Stopped checking certificates");
+    }
+}
+
+impl Instance {
+    /// Serves the certificates as JSON on `/certificates` and
+    /// `/certificates/<name>`, and their expiry in Prometheus format on
+    /// `/metrics`
+    fn serve(&self, results: &Arc<Results>) {
+        let queried = Arc::clone(results);
+        let route =
+            self.route(Method::Get, "/certificates/*", move |request| {
+                let response = certificates(&queried, &request.path);
+                async move { response }
+            });
+        match route {
+            Ok(()) | Err(RouteError::Unavailable) => {}
+            Err(e) => warn!("Failed to serve certificates: {e}"),
+        }
+
+        let scraped = Arc::clone(results);
+        let route = self.route(Method::Get, "/metrics", move |_| {
+            let body = prometheus::render(&scraped);
+            async move {
+                HttpResponse::new(200)
+                    .with_header("Content-Type", prometheus::CONTENT_TYPE)
+                    .with_body(body)
+            }
+        });
+        match route {
+            Ok(()) | Err(RouteError::Unavailable) => {}
+            Err(e) => warn!("Failed to serve certificate metrics: {e}"),
+        }
+    }
+}
+
+/// Answers `GET /certificates` with every certificate, and
+/// `GET /certificates/<name>` with a single one
+fn certificates(results: &Results, path: &str) -> HttpResponse {
+    let name = path
+        .strip_prefix("/certificates/")
+        .filter(|name| !name.is_empty());
+    let Some(name) = name else {
+        return HttpResponse::json(results.to_json().to_string());
+    };
+    results.certificate_json(name).map_or_else(
+        || HttpResponse::new(404).with_body(format!("No certificate `{name}`")),
+        |certificate| HttpResponse::json(certificate.to_string()),
+    )
+}
@@ -0,0 +1,290 @@
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use nexus_api::RuntimeRef;
+use nexus_utils::discord::Alerts;
+use serde_json::json;
+use tracing::{info, warn};
+
+use crate::certificate::Certificate;
+use crate::config::{Source, Watched};
+use crate::state::{self, Event, Results, Stage};
+
+/// Most alternative names shown in an alert
+const MAX_NAMES: usize = 10;
+
+/// Checks the certificates, and alerts as they get close to expiry.
+#[derive(Debug)]
+pub struct Monitor {
+    runtime: RuntimeRef,
+    results: Arc<Results>,
+    alerts: Alerts,
+    interval: Duration,
+    timeout: Duration,
+}
+
+impl Monitor {
+    pub const fn new(
+        runtime: RuntimeRef,
+        results: Arc<Results>,
+        alerts: Alerts,
+        interval: Duration,
+        timeout: Duration,
+    ) -> Self {
+        Self {
+            runtime,
+            results,
+            alerts,
+            interval,
+            timeout,
+        }
+    }
+
+    /// Checks `watched` every `interval`, until the task is dropped
+    pub async fn watch(&self, watched: Watched) {
+        loop {
+            let started = self.runtime.now();
+            let result = self.fetch(&watched.source).await;
+            if let Err(e) = &result {
+                warn!(
+                    "Failed to check the certificate `{}`: {e}",
+                    watched.name
+                );
+            }
+            let event = self.results.record(
+                &watched,
+                SystemTime::now(),
+                result.clone(),
+            );
+            if let (Some(event), Ok(certificate)) = (event, &result) {
+                self.notify(&watched, event, certificate).await;
+            }
+            self.runtime.sleep_until(started + self.interval).await;
+        }
+    }
+
+    /// The certificate watched at `source`
+    async fn fetch(&self, source: &Source) -> Result<Certificate, String> {
+        self.runtime
+            .timeout(
+                self.timeout,
+                read(RuntimeRef::clone(&self.runtime), source.clone()),
+            )
+            .await
+            .unwrap_or_else(|_| {
+                Err(format!(
+                    "timed out after {}",
+                    humantime::format_duration(self.timeout)
+                ))
+            })
+    }
+
+    async fn notify(
+        &self,
+        watched: &Watched,
+        event: Event,
+        certificate: &Certificate,
+    ) {
+        let name = &watched.name;
+        let expiry = state::rfc3339(certificate.not_after);
+        let (title, color) = match event {
+            Event::Reached(Stage::Expired) => {
+                warn!("Certificate `{name}` expired on {expiry}");
+                (format!(":x: Certificate {name} expired"), 15_548_997)
+            }
+            Event::Reached(Stage::Expiring(days)) => {
+                warn!("Certificate `{name}` expires within {days} days");
+                let color = if days <= 7 { 15_105_570 } else { 16_705_372 };
+                let within = if days == 1 {
+                    "a day".to_string()
+                } else {
+                    format!("{days} days")
+                };
+                (
+                    format!(
+                        ":warning: Certificate {name} expires within {within}"
+                    ),
+                    color,
+                )
+            }
+            Event::Reached(Stage::Valid) => return,
+            Event::Renewed => {
+                info!("Certificate `{name}` was renewed, expires on {expiry}");
+                (
+                    format!(
+                        ":white_check_mark: Certificate {name} was renewed"
+                    ),
+                    5_763_719,
+                )
+            }
+        };
+        let mut names = certificate
+            .names
+            .iter()
+            .take(MAX_NAMES)
+            .map(|name| format!("`{name}`"))
+            .collect::<Vec<_>>();
+        if certificate.names.len() > MAX_NAMES {
+            names.push(format!(
+                "and {} more",
+                certificate.names.len() - MAX_NAMES
+            ));
+        }
+        let mut fields = vec![
+            json!({
+                "name": "Source",
+                "value": format!("`{}`", watched.source),
+            }),
+            json!({ "name": "Subject", "value": certificate.subject }),
+            json!({ "name": "Issuer", "value": certificate.issuer }),
+            json!({ "name": "Expires", "value": expiry, "inline": true }),
+            json!({
+                "name": "Serial",
+                "value": format!("`{}`", certificate.serial),
+                "inline": true,
+            }),
+        ];
+        if !names.is_empty() {
+            fields.push(json!({ "name": "Names", "value": names.join(", ") }));
+        }
+        let embed = json!({
+            "title": title,
+            "color": color,
+            "fields": fields,
+        });
+        if let Err(e) = self.alerts.send(embed).await {
+            warn!("Failed to post the expiry of `{name}`: {e}");
+        }
+    }
+}
+
+/// The certificate watched at `source`: the one the endpoint presented,
+/// not the intermediates sent along, which may expire first without
+/// affecting it, else the first of the file's to expire
+async fn read(
+    runtime: RuntimeRef,
+    source: Source,
+) -> Result<Certificate, String> {
+    match source {
+        Source::Endpoint {
+            host,
+            port,
+            server_name,
+        } => {
+            let chain = runtime
+                .tls_certificates(&host, port, server_name.as_deref())
+                .await
+                .map_err(|e| e.to_string())?;
+            let leaf = chain.first().ok_or("no certificate presented")?;
+            Certificate::from_der(leaf)
+        }
+        Source::File { path } => {
+            let contents =
+                runtime.read_file(&path).await.map_err(|e| e.to_string())?;
+            Certificate::parse_file(&contents)?
+                .into_iter()
+                .min_by_key(|certificate| certificate.not_after)
+                .ok_or_else(|| "no certificate found".to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nexus_api::{CancellationToken, Permissions, TokioRuntimeHandle};
+    use rcgen::{
+        BasicConstraints, CertificateParams, DnType, IsCa, KeyPair,
+        date_time_ymd,
+    };
+    use tokio::{io::AsyncWriteExt, net::TcpListener};
+    use tokio_rustls::{
+        TlsAcceptor,
+        rustls::{
+            ServerConfig,
+            pki_types::{CertificateDer, PrivatePkcs8KeyDer},
+        },
+    };
+
+    use super::*;
+
+    /// `2030-01-01T00:00:00Z`
+    const EXPIRY_2030: i64 = 1_893_456_000;
+
+    /// Port of a TLS server presenting a leaf expiring in 2030, along with
+    /// the CA that signed it, expiring first
+    async fn serve() -> u16 {
+        let mut ca = CertificateParams::new(Vec::new()).unwrap();
+        ca.distinguished_name.push(DnType::CommonName, "Nexus CA");
+        ca.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        ca.not_after = date_time_ymd(2028, 1, 1);
+        let ca_key = KeyPair::generate().unwrap();
+        let ca = ca.self_signed(&ca_key).unwrap();
+        let mut leaf =
+            CertificateParams::new(vec!["localhost".into()]).unwrap();
+        leaf.distinguished_name
+            .push(DnType::CommonName, "localhost");
+        leaf.not_after = date_time_ymd(2030, 1, 1);
+        let key = KeyPair::generate().unwrap();
+        let leaf = leaf.signed_by(&key, &ca, &ca_key).unwrap();
+
+        let chain: Vec<CertificateDer<'static>> =
+            vec![leaf.der().clone(), ca.der().clone()];
+        let key = PrivatePkcs8KeyDer::from(key.serialize_der()).into();
+        let config = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(chain, key)
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            if let Ok(mut stream) = acceptor.accept(stream).await {
+                stream.shutdown().await.ok();
+            }
+        });
+        port
+    }
+
+    fn monitor() -> Monitor {
+        let permissions = Permissions {
+            network: vec!["127.0.0.1".into()],
+            ..Permissions::default()
+        };
+        let runtime = TokioRuntimeHandle::new(
+            tokio::runtime::Handle::current(),
+            "nexus-certs",
+            CancellationToken::new(),
+        )
+        .with_permissions(permissions);
+        Monitor::new(
+            Arc::new(runtime),
+            Arc::new(Results::new(&[], None)),
+            Alerts::default(),
+            Duration::from_hours(6),
+            Duration::from_secs(5),
+        )
+    }
+
+    #[tokio::test]
+    async fn watches_the_leaf_of_endpoints() {
+        let source = Source::Endpoint {
+            host: "127.0.0.1".into(),
+            port: serve().await,
+            server_name: Some("localhost".into()),
+        };
+        let certificate = monitor().fetch(&source).await.unwrap();
+        assert_eq!(certificate.subject, "CN=localhost");
+        assert_eq!(certificate.not_after, EXPIRY_2030);
+    }
+
+    #[tokio::test]
+    async fn fails_on_endpoints_not_allowed() {
+        let source = Source::Endpoint {
+            host: "192.0.2.1".into(),
+            port: 443,
+            server_name: None,
+        };
+        assert!(monitor().fetch(&source).await.is_err());
+    }
+}
@@ -0,0 +1,27 @@
+use std::fmt;
+
+use nexus_utils::prometheus::{self, gauge, sample};
+
+use crate::state::Results;
+
+pub use prometheus::CONTENT_TYPE;
+
+/// Renders the certificates read in the Prometheus text exposition format.
+pub fn render(results: &Results) -> String {
+    prometheus::render(|out| write_results(out, results))
+}
+
+fn write_results(out: &mut String, results: &Results) -> fmt::Result {
+    let name = "nexus_certificate_expiry_timestamp_seconds";
+    gauge(
+        out,
+        name,
+        "When a certificate expires, as a Unix timestamp.",
+    )?;
+    for (certificate, source, not_after) in results.samples() {
+        let labels = [("name", certificate.as_str()), ("source", &source)];
+        #[allow(clippy::cast_precision_loss)]
+        sample(out, name, &labels, not_after as f64)?;
+    }
+    Ok(())
+}
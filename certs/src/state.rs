@@ -0,0 +1,468 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use nexus_api::StorageRef;
+use serde_json::{Value, json};
+use tracing::warn;
+
+use crate::certificate::Certificate;
+use crate::config::Watched;
+
+const DAY: i64 = 24 * 60 * 60;
+
+/// How close a certificate is to its expiry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Further from it than every threshold
+    Valid,
+    /// Within the threshold of this many days
+    Expiring(u32),
+    Expired,
+}
+
+impl Stage {
+    /// Where a certificate expiring at `not_after` is at `now`, given
+    /// `thresholds` sorted largest first
+    fn at(not_after: i64, now: i64, thresholds: &[u32]) -> Self {
+        let left = not_after - now;
+        if left <= 0 {
+            return Self::Expired;
+        }
+        thresholds
+            .iter()
+            .rev()
+            .find(|&&days| left <= i64::from(days) * DAY)
+            .map_or(Self::Valid, |&days| Self::Expiring(days))
+    }
+
+    /// Higher as expiry gets closer
+    const fn urgency(self) -> u64 {
+        match self {
+            Self::Valid => 0,
+            Self::Expiring(days) => u64::MAX - 1 - days as u64,
+            Self::Expired => u64::MAX,
+        }
+    }
+
+    fn parse(stage: &str) -> Option<Self> {
+        match stage {
+            "valid" => Some(Self::Valid),
+            "expired" => Some(Self::Expired),
+            days => days.strip_suffix('d')?.parse().ok().map(Self::Expiring),
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Valid => f.write_str("valid"),
+            Self::Expiring(days) => write!(f, "{days}d"),
+            Self::Expired => f.write_str("expired"),
+        }
+    }
+}
+
+/// What's alerted on after a check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// A threshold was crossed, or the certificate expired
+    Reached(Stage),
+    /// Replaced by one expiring later, after alerts on the previous one
+    Renewed,
+}
+
+/// Latest stage alerted on, for the certificate expiring at `not_after`,
+/// kept across restarts so that each threshold is alerted on once.
+#[derive(Debug, Clone, Copy)]
+struct Alerted {
+    not_after: i64,
+    stage: Stage,
+}
+
+#[derive(Debug)]
+struct CertificateState {
+    source: String,
+    alerted: Option<Alerted>,
+    /// Latest check, none before the first one
+    latest: Option<(SystemTime, Result<Certificate, String>)>,
+}
+
+/// Latest results of every certificate, queried by the routes.
+#[derive(Debug)]
+pub struct Results {
+    certificates: Mutex<BTreeMap<String, CertificateState>>,
+    storage: Option<StorageRef>,
+}
+
+impl Results {
+    pub fn new(watched: &[Watched], storage: Option<StorageRef>) -> Self {
+        let certificates = watched
+            .iter()
+            .map(|watched| {
+                let alerted = storage
+                    .as_ref()
+                    .and_then(|storage| saved(storage, &watched.name));
+                let state = CertificateState {
+                    source: watched.source.to_string(),
+                    alerted,
+                    latest: None,
+                };
+                (watched.name.clone(), state)
+            })
+            .collect();
+        Self {
+            certificates: Mutex::new(certificates),
+            storage,
+        }
+    }
+
+    /// Records a check of `watched`, and what should be alerted on
+    pub fn record(
+        &self,
+        watched: &Watched,
+        at: SystemTime,
+        result: Result<Certificate, String>,
+    ) -> Option<Event> {
+        let event = self.update(watched, at, result);
+        let alerted = self.lock().get(&watched.name)?.alerted;
+        if let (Some(storage), Some(alerted), Some(_)) =
+            (&self.storage, alerted, event)
+        {
+            let value = format!("{} {}", alerted.not_after, alerted.stage);
+            if let Err(e) = storage.put(&key(&watched.name), value.as_bytes()) {
+                warn!("Failed to save the alerts on `{}`: {e}", watched.name);
+            }
+        }
+        event
+    }
+
+    fn update(
+        &self,
+        watched: &Watched,
+        at: SystemTime,
+        result: Result<Certificate, String>,
+    ) -> Option<Event> {
+        let not_after = result.as_ref().ok().map(|c| c.not_after);
+        let mut certificates = self.lock();
+        let entry = certificates.get_mut(&watched.name)?;
+        entry.latest = Some((at, result));
+        let not_after = not_after?;
+
+        let stage = Stage::at(not_after, unix(at), &watched.thresholds);
+        let (alerted, event) = next(entry.alerted, not_after, stage);
+        entry.alerted = Some(alerted);
+        drop(certificates);
+        event
+    }
+
+    /// Every certificate by name, as served on `/certificates`
+    pub fn to_json(&self) -> Value {
+        let certificates = self
+            .lock()
+            .iter()
+            .map(|(name, state)| (name.clone(), state.to_json()))
+            .collect::<serde_json::Map<_, _>>();
+        Value::Object(certificates)
+    }
+
+    /// A single certificate, if it's watched
+    pub fn certificate_json(&self, name: &str) -> Option<Value> {
+        self.lock().get(name).map(CertificateState::to_json)
+    }
+
+    /// Name, source and expiry of each certificate read successfully
+    pub fn samples(&self) -> Vec<(String, String, i64)> {
+        self.lock()
+            .iter()
+            .filter_map(|(name, state)| {
+                let (_, latest) = state.latest.as_ref()?;
+                let certificate = latest.as_ref().ok()?;
+                Some((
+                    name.clone(),
+                    state.source.clone(),
+                    certificate.not_after,
+                ))
+            })
+            .collect()
+    }
+
+    fn lock(
+        &self,
+    ) -> std::sync::MutexGuard<'_, BTreeMap<String, CertificateState>> {
+        self.certificates.lock().expect("Results poisoned")
+    }
+}
+
+impl CertificateState {
+    fn to_json(&self) -> Value {
+        let mut value = json!({
+            "source": self.source,
+            "alerted": self.alerted.map(|alerted| alerted.stage.to_string()),
+        });
+        let Some((checked, latest)) = &self.latest else {
+            return value;
+        };
+        value["checked_at"] = humantime::format_rfc3339_seconds(*checked)
+            .to_string()
+            .into();
+        match latest {
+            Ok(certificate) => {
+                value["subject"] = certificate.subject.clone().into();
+                value["issuer"] = certificate.issuer.clone().into();
+                value["names"] = certificate.names.clone().into();
+                value["serial"] = certificate.serial.clone().into();
+                value["not_before"] = rfc3339(certificate.not_before).into();
+                value["not_after"] = rfc3339(certificate.not_after).into();
+            }
+            Err(e) => value["error"] = e.clone().into(),
+        }
+        value
+    }
+}
+
+/// What's alerted on once the certificate expiring at `not_after` is at
+/// `stage`, given what was alerted on before, and what to keep
+fn next(
+    previous: Option<Alerted>,
+    not_after: i64,
+    stage: Stage,
+) -> (Alerted, Option<Event>) {
+    let current = Alerted { not_after, stage };
+    let reached = (stage != Stage::Valid).then_some(Event::Reached(stage));
+    let Some(previous) = previous else {
+        // Nothing alerted on yet
+        return (current, reached);
+    };
+    if not_after > previous.not_after {
+        let renewed =
+            (previous.stage != Stage::Valid).then_some(Event::Renewed);
+        return (current, reached.or(renewed));
+    }
+    if stage.urgency() > previous.stage.urgency() {
+        return (current, reached);
+    }
+    // Alerted on already, or moved away from expiry without being renewed,
+    // e.g. if the thresholds changed
+    let least = if stage.urgency() < previous.stage.urgency() {
+        current
+    } else {
+        previous
+    };
+    (least, None)
+}
+
+/// Seconds since the Unix epoch
+pub fn unix(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs().try_into().unwrap_or(i64::MAX))
+}
+
+/// e.g. `2026-01-01T00:00:00Z`, from seconds since the Unix epoch
+pub fn rfc3339(timestamp: i64) -> String {
+    let time = u64::try_from(timestamp).map_or(UNIX_EPOCH, |secs| {
+        UNIX_EPOCH + std::time::Duration::from_secs(secs)
+    });
+    humantime::format_rfc3339_seconds(time).to_string()
+}
+
+fn key(name: &str) -> Vec<u8> {
+    format!("alerted.{name}").into_bytes()
+}
+
+/// Latest alert on `name` before a restart, if any
+fn saved(storage: &StorageRef, name: &str) -> Option<Alerted> {
+    let saved = storage
+        .get(&key(name))
+        .inspect_err(|e| warn!("Failed to read the alerts on `{name}`: {e}"))
+        .ok()??;
+    let saved = String::from_utf8(saved).ok()?;
+    let (not_after, stage) = saved.split_once(' ')?;
+    Some(Alerted {
+        not_after: not_after.parse().ok()?,
+        stage: Stage::parse(stage)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use nexus_api::{MemoryStorage, Storage};
+
+    use super::*;
+    use crate::config::Source;
+
+    const THRESHOLDS: [u32; 4] = [30, 14, 7, 1];
+    const NOW: i64 = 1_800_000_000;
+
+    fn stage(left: i64) -> Stage {
+        Stage::at(NOW + left, NOW, &THRESHOLDS)
+    }
+
+    #[test]
+    fn stages_by_threshold() {
+        assert_eq!(stage(31 * DAY), Stage::Valid);
+        assert_eq!(stage(30 * DAY + 1), Stage::Valid);
+        assert_eq!(stage(30 * DAY), Stage::Expiring(30));
+        assert_eq!(stage(15 * DAY), Stage::Expiring(30));
+        assert_eq!(stage(14 * DAY), Stage::Expiring(14));
+        assert_eq!(stage(7 * DAY), Stage::Expiring(7));
+        assert_eq!(stage(DAY / 2), Stage::Expiring(1));
+        assert_eq!(stage(1), Stage::Expiring(1));
+        assert_eq!(stage(0), Stage::Expired);
+        assert_eq!(stage(-DAY), Stage::Expired);
+        assert_eq!(Stage::at(NOW + DAY, NOW, &[]), Stage::Valid);
+    }
+
+    #[test]
+    fn stages_round_trip() {
+        for stage in [Stage::Valid, Stage::Expiring(14), Stage::Expired] {
+            assert_eq!(Stage::parse(&stage.to_string()), Some(stage));
+        }
+        assert_eq!(Stage::parse("14"), None);
+        assert_eq!(Stage::parse("soon"), None);
+    }
+
+    /// Events of a certificate going through `stages`, each expiring at
+    /// the given timestamp
+    fn events(stages: &[(i64, Stage)]) -> Vec<Option<Event>> {
+        let mut alerted = None;
+        stages
+            .iter()
+            .map(|&(not_after, stage)| {
+                let (next, event) = next(alerted, not_after, stage);
+                alerted = Some(next);
+                event
+            })
+            .collect()
+    }
+
+    #[test]
+    fn alerts_on_each_threshold_once() {
+        let events = events(&[
+            (NOW, Stage::Valid),
+            (NOW, Stage::Expiring(30)),
+            (NOW, Stage::Expiring(30)),
+            (NOW, Stage::Expiring(7)),
+            (NOW, Stage::Expiring(14)),
+            (NOW, Stage::Expiring(7)),
+            (NOW, Stage::Expired),
+            (NOW, Stage::Expired),
+        ]);
+        assert_eq!(
+            events,
+            [
+                None,
+                Some(Event::Reached(Stage::Expiring(30))),
+                None,
+                Some(Event::Reached(Stage::Expiring(7))),
+                None,
+                Some(Event::Reached(Stage::Expiring(7))),
+                Some(Event::Reached(Stage::Expired)),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn alerts_on_renewals_after_alerts() {
+        let events = events(&[
+            (NOW, Stage::Expiring(7)),
+            (NOW + 90 * DAY, Stage::Valid),
+            (NOW + 180 * DAY, Stage::Valid),
+            (NOW + 190 * DAY, Stage::Expiring(14)),
+        ]);
+        assert_eq!(
+            events,
+            [
+                Some(Event::Reached(Stage::Expiring(7))),
+                Some(Event::Renewed),
+                None,
+                Some(Event::Reached(Stage::Expiring(14))),
+            ]
+        );
+    }
+
+    fn watched() -> Watched {
+        Watched {
+            name: "mail".to_string(),
+            thresholds: THRESHOLDS.to_vec(),
+            source: Source::File {
+                path: "/etc/ssl/mail.pem".into(),
+            },
+        }
+    }
+
+    fn certificate(not_after: i64) -> Certificate {
+        Certificate {
+            subject: "CN=mail".to_string(),
+            issuer: "CN=ca".to_string(),
+            names: vec!["mail.example".to_string()],
+            serial: "01".to_string(),
+            not_before: not_after - 90 * DAY,
+            not_after,
+        }
+    }
+
+    fn at(timestamp: i64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(timestamp.try_into().unwrap())
+    }
+
+    #[test]
+    fn keeps_the_alerts_across_restarts() {
+        let storage = Arc::new(MemoryStorage::default());
+        let watched = watched();
+        let results = || {
+            let storage = Arc::clone(&storage) as StorageRef;
+            Results::new(std::slice::from_ref(&watched), Some(storage))
+        };
+
+        let expiring = certificate(NOW + 10 * DAY);
+        let event = results().record(&watched, at(NOW), Ok(expiring.clone()));
+        assert_eq!(event, Some(Event::Reached(Stage::Expiring(14))));
+        let saved = storage.get(b"alerted.mail").unwrap().unwrap();
+        assert_eq!(saved, format!("{} 14d", NOW + 10 * DAY).as_bytes());
+
+        let restarted = results();
+        let alerted =
+            restarted.certificate_json("mail").unwrap()["alerted"].clone();
+        assert_eq!(alerted, "14d");
+        let event = restarted.record(&watched, at(NOW), Ok(expiring));
+        assert_eq!(event, None);
+        let renewed = certificate(NOW + 100 * DAY);
+        let event = restarted.record(&watched, at(NOW), Ok(renewed));
+        assert_eq!(event, Some(Event::Renewed));
+        let saved = storage.get(b"alerted.mail").unwrap().unwrap();
+        assert_eq!(saved, format!("{} valid", NOW + 100 * DAY).as_bytes());
+    }
+
+    #[test]
+    fn ignores_unreadable_saved_alerts() {
+        let storage = Arc::new(MemoryStorage::default());
+        storage.put(b"alerted.mail", b"soon").unwrap();
+        let watched = watched();
+        let results = Results::new(
+            std::slice::from_ref(&watched),
+            Some(storage as StorageRef),
+        );
+        let expiring = certificate(NOW + 10 * DAY);
+        let event = results.record(&watched, at(NOW), Ok(expiring));
+        assert_eq!(event, Some(Event::Reached(Stage::Expiring(14))));
+    }
+
+    #[test]
+    fn keeps_failures_without_alerting() {
+        let watched = watched();
+        let results = Results::new(std::slice::from_ref(&watched), None);
+        let error = Err("no certificate found".to_string());
+        assert_eq!(results.record(&watched, at(NOW), error), None);
+        let json = results.certificate_json("mail").unwrap();
+        assert_eq!(json["error"], "no certificate found");
+        assert_eq!(json["alerted"], Value::Null);
+        assert!(results.samples().is_empty());
+    }
+}
@@ -0,0 +1,252 @@
+use serde::{Deserialize, Deserializer};
+use std::collections::HashSet;
+use std::fmt;
+use std::net::IpAddr;
+use std::time::Duration;
+
+/// Settings of the plugin, from `[plugins.nexus-healthcheck.settings]` in
+/// the host's `nexus.toml`.
+///
+/// ```toml
+/// [plugins.nexus-healthcheck.settings]
+/// webhook_url = "https://discord.com/api/webhooks/..."
+///
+/// [[plugins.nexus-healthcheck.settings.checks]]
+/// name = "website"
+/// type = "http"
+/// url = "https://example.com/health"
+/// body_contains = "ok"
+///
+/// [[plugins.nexus-healthcheck.settings.checks]]
+/// name = "database"
+/// type = "tcp"
+/// host = "127.0.0.1"
+/// port = 5432
+/// interval = "30s"
+/// ```
+///
+/// Hosts need a network permission, and commands an exec one.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Discord webhook state changes are posted to, none if unset
+    pub webhook_url: Option<String>,
+    /// Mentions added to the alerts, e.g. `<@1234>` or `@here`
+    pub user_tags: Vec<String>,
+    /// Defaults of the checks that don't set their own
+    #[serde(deserialize_with = "duration")]
+    pub interval: Duration,
+    #[serde(deserialize_with = "duration")]
+    pub timeout: Duration,
+    /// Consecutive failures before a check is down
+    pub down_after: u32,
+    /// Consecutive successes before a down check is back up
+    pub up_after: u32,
+    pub checks: Vec<CheckConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            webhook_url: None,
+            user_tags: Vec::new(),
+            interval: Duration::from_mins(1),
+            timeout: Duration::from_secs(10),
+            down_after: 2,
+            up_after: 1,
+            checks: Vec::new(),
+        }
+    }
+}
+
+/// Probe run on a schedule, e.g. every minute.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CheckConfig {
+    /// Unique, shown in alerts and results
+    pub name: String,
+    #[serde(default, deserialize_with = "optional_duration")]
+    pub interval: Option<Duration>,
+    /// How long a single run may take before it fails
+    #[serde(default, deserialize_with = "optional_duration")]
+    pub timeout: Option<Duration>,
+    pub down_after: Option<u32>,
+    pub up_after: Option<u32>,
+    #[serde(flatten)]
+    pub probe: Probe,
+}
+
+/// What a check does, by its `type`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Probe {
+    /// Connects to `host` on `port`
+    Tcp { host: String, port: u16 },
+    /// Sends a request to `url`, which fails if the status isn't expected
+    /// or the body doesn't contain `body_contains`
+    Http {
+        url: String,
+        /// e.g. `HEAD`, `GET` by default
+        #[serde(default)]
+        method: Option<String>,
+        /// Any `2xx` if empty. Redirects aren't followed.
+        #[serde(default)]
+        expected_status: Vec<u16>,
+        #[serde(default)]
+        body_contains: Option<String>,
+    },
+    /// Resolves `host`, which fails without any address, or without one
+    /// of `expected` if it's set
+    Dns {
+        host: String,
+        #[serde(default)]
+        expected: Vec<IpAddr>,
+    },
+    /// Runs `command`, which fails unless it exits with `expected_code`
+    Command {
+        command: Vec<String>,
+        #[serde(default)]
+        expected_code: i32,
+    },
+}
+
+impl Probe {
+    /// What's checked, e.g. `tcp 127.0.0.1:5432`
+    pub fn target(&self) -> String {
+        match self {
+            Self::Tcp { host, port } => format!("tcp {host}:{port}"),
+            Self::Http { url, method, .. } => {
+                format!("{} {url}", method.as_deref().unwrap_or("GET"))
+            }
+            Self::Dns { host, .. } => format!("dns {host}"),
+            Self::Command { command, .. } => {
+                format!("command {}", command.join(" "))
+            }
+        }
+    }
+}
+
+/// A check along with the defaults it didn't override
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: String,
+    pub interval: Duration,
+    pub timeout: Duration,
+    pub down_after: u32,
+    pub up_after: u32,
+    pub probe: Probe,
+}
+
+/// Why the settings were rejected.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// Not valid TOML, or not matching [`Config`]
+    Parse(toml::de::Error),
+    /// Well-formed, but unusable
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse(e) => write!(f, "failed to parse settings: {e}"),
+            Self::Invalid(e) => write!(f, "invalid settings: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Parses and validates the plugin's settings, defaults if there's none
+    pub fn load(settings: Option<&str>) -> Result<Self, ConfigError> {
+        let config = match settings {
+            Some(settings) => {
+                toml::from_str::<Self>(settings).map_err(ConfigError::Parse)?
+            }
+            None => Self::default(),
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |e: String| Err(ConfigError::Invalid(e));
+        if let Some(url) = &self.webhook_url
+            && !url.starts_with("https://")
+            && !url.starts_with("http://")
+        {
+            return invalid(format!("`webhook_url` isn't a URL: {url}"));
+        }
+        let mut names = HashSet::new();
+        for check in self.checks() {
+            let name = &check.name;
+            if name.is_empty() || name.contains('/') {
+                return invalid(format!("invalid check name `{name}`"));
+            }
+            if !names.insert(name.clone()) {
+                return invalid(format!("check `{name}` is defined twice"));
+            }
+            if check.interval.is_zero() || check.timeout.is_zero() {
+                return invalid(format!(
+                    "check `{name}` needs a non-zero interval and timeout"
+                ));
+            }
+            if check.down_after == 0 || check.up_after == 0 {
+                return invalid(format!(
+                    "check `{name}` needs non-zero `down_after` and `up_after`"
+                ));
+            }
+            match &check.probe {
+                Probe::Http { url, method, .. } => {
+                    if !url.starts_with("https://")
+                        && !url.starts_with("http://")
+                    {
+                        return invalid(format!(
+                            "check `{name}` has an invalid URL: {url}"
+                        ));
+                    }
+                    let method = method.as_deref().unwrap_or("GET");
+                    if nexus_api::Method::parse(method).is_none() {
+                        return invalid(format!(
+                            "check `{name}` has an unknown method `{method}`"
+                        ));
+                    }
+                }
+                Probe::Command { command, .. } if command.is_empty() => {
+                    return invalid(format!("check `{name}` has no command"));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Configured checks, with the defaults applied
+    pub fn checks(&self) -> Vec<Check> {
+        self.checks
+            .iter()
+            .map(|check| Check {
+                name: check.name.clone(),
+                interval: check.interval.unwrap_or(self.interval),
+                timeout: check.timeout.unwrap_or(self.timeout),
+                down_after: check.down_after.unwrap_or(self.down_after),
+                up_after: check.up_after.unwrap_or(self.up_after),
+                probe: check.probe.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Human-readable duration, e.g. `1h 30m`
+fn duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Duration, D::Error> {
+    let duration = String::deserialize(deserializer)?;
+    humantime::parse_duration(&duration).map_err(serde::de::Error::custom)
+}
+
+fn optional_duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    duration(deserializer).map(Some)
+}
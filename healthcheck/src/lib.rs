@@ -0,0 +1,110 @@
+mod config;
+mod monitor;
+mod probe;
+mod prometheus;
+mod state;
+
+use std::sync::Arc;
+
+use config::Config;
+use monitor::Monitor;
+use nexus_api::{HttpResponse, Meta, Method, RouteError, r#impl};
+use nexus_utils::discord::Alerts;
+use state::Results;
+use tracing::{error, info, warn};
+
+r#impl! {
+    pub static META: Meta = Meta {
+        name: env!("CARGO_PKG_NAME"),
+        authors: env!("CARGO_PKG_AUTHORS"),
+        version: env!("CARGO_PKG_VERSION"),
+    };
+
+    async fn main(&self) {
+        let config = match Config::load(self.settings()) {
+            Ok(config) => config,
+            Err(e) => {
+                error!("Not running health checks, {e}");
+                return;
+            }
+        };
+        let checks = config.checks();
+        if checks.is_empty() {
+            info!("No health checks configured");
+            return;
+        }
+
+        let storage = self
+            .storage()
+            .inspect_err(|e| warn!("Health of checks won't be kept: {e}"))
+            .ok();
+        let results = Arc::new(Results::new(&checks, storage));
+        self.serve(&results);
+
+        let monitor = Arc::new(Monitor::new(
+            Arc::clone(self.runtime()),
+            results,
+            Alerts::new(
+                self.runtime(),
+                config.webhook_url.clone(),
+                &config.user_tags,
+            ),
+        ));
+        for check in checks.iter().cloned() {
+            let monitor = Arc::clone(&monitor);
+            let name = format!("check {}", check.name);
+            self.spawn_cancellable(&name, async move {
+                monitor.watch(check).await;
+            });
+        }
+        info!("Now running {} health checks", checks.len());
+
+        self.cancellation().cancelled().await;
+        info!("Stopped running health checks");
+    }
+}
+
+impl Instance {
+    /// Serves the results as JSON on `/checks` and `/checks/<name>`, and in
+    /// Prometheus format on `/metrics`
+    fn serve(&self, results: &Arc<Results>) {
+        let queried = Arc::clone(results);
+        let route = self.route(Method::Get, "/checks/*", move |request| {
+            let response = checks(&queried, &request.path);
+            async move { response }
+        });
+        match route {
+            Ok(()) | Err(RouteError::Unavailable) => {}
+            Err(e) => warn!("Failed to serve health checks: {e}"),
+        }
+
+        let scraped = Arc::clone(results);
+        let route = self.route(Method::Get, "/metrics", move |_| {
+            let body = prometheus::render(&scraped);
+            async move {
+                HttpResponse::new(200)
+                    .with_header("Content-Type", prometheus::CONTENT_TYPE)
+                    .with_body(body)
+            }
+        });
+        match route {
+            Ok(()) | Err(RouteError::Unavailable) => {}
+            Err(e) => warn!("Failed to serve health metrics: {e}"),
+        }
+    }
+}
+
+/// Answers `GET /checks` with every check, and `GET /checks/<name>` with
+/// a single one
+fn checks(results: &Results, path: &str) -> HttpResponse {
+    let name = path
+        .strip_prefix("/checks/")
+        .filter(|name| !name.is_empty());
+    let Some(name) = name else {
+        return HttpResponse::json(results.to_json().to_string());
+    };
+    results.check_json(name).map_or_else(
+        || HttpResponse::new(404).with_body(format!("No check `{name}`")),
+        |check| HttpResponse::json(check.to_string()),
+    )
+}
@@ -0,0 +1,216 @@
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use nexus_api::RuntimeRef;
+use nexus_utils::discord::Alerts;
+use serde_json::json;
+use tracing::{info, warn};
+
+use crate::config::Check;
+use crate::probe::{self, Outcome};
+use crate::state::{Health, Results, Transition};
+
+/// Runs the checks, and alerts on their changes of health.
+#[derive(Debug)]
+pub struct Monitor {
+    runtime: RuntimeRef,
+    results: Arc<Results>,
+    alerts: Alerts,
+}
+
+impl Monitor {
+    pub const fn new(
+        runtime: RuntimeRef,
+        results: Arc<Results>,
+        alerts: Alerts,
+    ) -> Self {
+        Self {
+            runtime,
+            results,
+            alerts,
+        }
+    }
+
+    /// Runs `check` every `interval`, until the task is dropped
+    pub async fn watch(&self, check: Check) {
+        loop {
+            let started = self.runtime.now();
+            let outcome = probe::run(&self.runtime, &check).await;
+            let transition =
+                self.results
+                    .record(&check, SystemTime::now(), outcome.clone());
+            if let Some(transition) = transition {
+                self.notify(&check, transition, &outcome).await;
+            }
+            // Runs don't drift by how long they take
+            self.runtime.sleep_until(started + check.interval).await;
+        }
+    }
+
+    async fn notify(
+        &self,
+        check: &Check,
+        transition: Transition,
+        outcome: &Outcome,
+    ) {
+        let name = &check.name;
+        // To the second, as that's all that's kept across restarts
+        let lasted = Duration::from_secs(transition.lasted.as_secs());
+        let lasted = humantime::format_duration(lasted);
+        let (title, color) = match (transition.from, transition.to) {
+            (Health::Pending, Health::Up) => {
+                info!("Check `{name}` is up: {}", outcome.message);
+                return;
+            }
+            (_, Health::Down) => {
+                warn!("Check `{name}` is down: {}", outcome.message);
+                (format!(":red_circle: {name} is down"), 15_548_997)
+            }
+            (_, Health::Up) => {
+                info!("Check `{name}` is back up after {lasted}");
+                (format!(":green_circle: {name} is back up"), 5_763_719)
+            }
+            (_, Health::Pending) => return,
+        };
+
+        let mut fields = vec![
+            json!({
+                "name": "Target",
+                "value": format!("`{}`", check.probe.target()),
+            }),
+            json!({ "name": "Result", "value": outcome.message }),
+            json!({
+                "name": "Latency",
+                "value": format!("{} ms", outcome.latency.as_millis()),
+                "inline": true,
+            }),
+        ];
+        if transition.from == Health::Down {
+            fields.push(json!({
+                "name": "Downtime",
+                "value": lasted.to_string(),
+                "inline": true,
+            }));
+        }
+        let embed = json!({
+            "title": title,
+            "color": color,
+            "fields": fields,
+        });
+        if let Err(e) = self.alerts.send(embed).await {
+            warn!("Failed to post the health of `{name}`: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use nexus_api::{
+        BoxFuture, CancellationToken, HttpClient, HttpError, HttpRequest,
+        HttpResponse, TokioRuntimeHandle,
+    };
+    use serde_json::Value;
+
+    use super::*;
+    use crate::config::Probe;
+
+    /// Keeps the messages posted, which Discord accepts
+    #[derive(Debug, Default)]
+    struct Posted(Mutex<Vec<Value>>);
+
+    impl HttpClient for Posted {
+        fn execute(
+            &self,
+            request: HttpRequest,
+        ) -> BoxFuture<Result<HttpResponse, HttpError>> {
+            let message = serde_json::from_slice(&request.body).unwrap();
+            self.0.lock().unwrap().push(message);
+            Box::pin(async { Ok(HttpResponse::json(r#"{"id":"1"}"#)) })
+        }
+    }
+
+    fn monitor(posted: &Arc<Posted>) -> Monitor {
+        let runtime = TokioRuntimeHandle::new(
+            tokio::runtime::Handle::current(),
+            "nexus-healthcheck",
+            CancellationToken::new(),
+        )
+        .with_http(Arc::clone(posted) as _);
+        let runtime: RuntimeRef = Arc::new(runtime);
+        let alerts = Alerts::new(
+            &runtime,
+            Some("https://discord.com/api/webhooks/1/a".into()),
+            &["@here".into()],
+        );
+        let results = Arc::new(Results::new(&[], None));
+        Monitor::new(runtime, results, alerts)
+    }
+
+    fn check() -> Check {
+        Check {
+            name: "database".into(),
+            interval: Duration::from_mins(1),
+            timeout: Duration::from_secs(10),
+            down_after: 1,
+            up_after: 1,
+            probe: Probe::Tcp {
+                host: "127.0.0.1".into(),
+                port: 5432,
+            },
+        }
+    }
+
+    fn outcome(success: bool) -> Outcome {
+        Outcome {
+            success,
+            latency: Duration::from_millis(3),
+            message: if success { "connected" } else { "refused" }.into(),
+        }
+    }
+
+    const fn transition(from: Health, to: Health) -> Transition {
+        Transition {
+            from,
+            to,
+            lasted: Duration::from_secs(90),
+        }
+    }
+
+    #[tokio::test]
+    async fn is_silent_on_the_first_success() {
+        let posted = Arc::new(Posted::default());
+        let up = transition(Health::Pending, Health::Up);
+        monitor(&posted).notify(&check(), up, &outcome(true)).await;
+        assert!(posted.0.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn alerts_on_downs_and_recoveries() {
+        let posted = Arc::new(Posted::default());
+        let monitor = monitor(&posted);
+        let down = transition(Health::Up, Health::Down);
+        monitor.notify(&check(), down, &outcome(false)).await;
+        let up = transition(Health::Down, Health::Up);
+        monitor.notify(&check(), up, &outcome(true)).await;
+
+        let posted = std::mem::take(&mut *posted.0.lock().unwrap());
+        let [down, up] = posted.as_slice() else {
+            panic!("posted {posted:?}");
+        };
+        assert_eq!(down["content"], "@here");
+        assert_eq!(down["embeds"][0]["title"], ":red_circle: database is down");
+        let downtime = |message: &Value| {
+            let fields = message["embeds"][0]["fields"].as_array().unwrap();
+            let field = fields.iter().find(|f| f["name"] == "Downtime")?;
+            Some(field["value"].clone())
+        };
+        assert_eq!(downtime(down), None);
+        assert_eq!(
+            up["embeds"][0]["title"],
+            ":green_circle: database is back up"
+        );
+        assert_eq!(downtime(up), Some("1m 30s".into()));
+    }
+}
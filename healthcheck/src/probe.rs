@@ -0,0 +1,360 @@
+use std::time::Duration;
+
+use nexus_api::{HttpRequest, Method, ProcessCommand, RuntimeRef};
+
+use crate::config::{Check, Probe};
+
+/// Result of a single run of a check.
+#[derive(Debug, Clone)]
+pub struct Outcome {
+    pub success: bool,
+    /// How long the run took, until it failed or timed out included
+    pub latency: Duration,
+    /// What was found, e.g. `HTTP 200` or the error
+    pub message: String,
+}
+
+/// Runs `check` once, failing it once its timeout has passed
+pub async fn run(runtime: &RuntimeRef, check: &Check) -> Outcome {
+    let started = runtime.now();
+    let probe = check.probe.clone();
+    let timeout = check.timeout;
+    let result = runtime
+        .timeout(
+            timeout,
+            probe_once(RuntimeRef::clone(runtime), probe, timeout),
+        )
+        .await
+        .unwrap_or_else(|_| {
+            Err(format!(
+                "timed out after {}",
+                humantime::format_duration(timeout)
+            ))
+        });
+    let latency = runtime.now().duration_since(started);
+    match result {
+        Ok(message) => Outcome {
+            success: true,
+            latency,
+            message,
+        },
+        Err(message) => Outcome {
+            success: false,
+            latency,
+            message,
+        },
+    }
+}
+
+/// What was found if the probe succeeded, else why it failed
+async fn probe_once(
+    runtime: RuntimeRef,
+    probe: Probe,
+    timeout: Duration,
+) -> Result<String, String> {
+    match probe {
+        Probe::Tcp { host, port } => {
+            let address = runtime
+                .connect_tcp(&host, port)
+                .await
+                .map_err(|e| e.to_string())?;
+            Ok(format!("connected to {address}"))
+        }
+        Probe::Http {
+            url,
+            method,
+            expected_status,
+            body_contains,
+        } => {
+            let method = method
+                .as_deref()
+                .and_then(Method::parse)
+                .unwrap_or(Method::Get);
+            // A failure is what's being checked, so it isn't retried
+            let request =
+                HttpRequest::new(method, url).timeout(timeout).retry(false);
+            let http = runtime.http().map_err(|e| e.to_string())?;
+            let response =
+                http.execute(request).await.map_err(|e| e.to_string())?;
+            let status = response.status;
+            let expected = if expected_status.is_empty() {
+                response.is_success()
+            } else {
+                expected_status.contains(&status)
+            };
+            if !expected {
+                return Err(format!("unexpected HTTP {status}"));
+            }
+            if let Some(needle) = body_contains
+                && !response.text().contains(&needle)
+            {
+                return Err(format!("HTTP {status}, body lacks `{needle}`"));
+            }
+            Ok(format!("HTTP {status}"))
+        }
+        Probe::Dns { host, expected } => {
+            let ips =
+                runtime.resolve(&host).await.map_err(|e| e.to_string())?;
+            let found = ips
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+            if ips.is_empty() {
+                return Err("no address".into());
+            }
+            if !expected.is_empty()
+                && !ips.iter().any(|ip| expected.contains(ip))
+            {
+                return Err(format!("unexpected addresses {found}"));
+            }
+            Ok(format!("resolved to {found}"))
+        }
+        Probe::Command {
+            command,
+            expected_code,
+        } => {
+            let (program, args) =
+                command.split_first().ok_or("no command to run")?;
+            let command =
+                ProcessCommand::new(program).args(args).timeout(timeout);
+            let output = runtime
+                .run_process(command)
+                .await
+                .map_err(|e| e.to_string())?;
+            // None if it was killed by a signal
+            let code = output.status.code();
+            if code == Some(expected_code) {
+                return Ok(format!("exited with {expected_code}"));
+            }
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let stderr = stderr.trim().lines().last().unwrap_or_default();
+            let status = code.map_or_else(
+                || output.status.to_string(),
+                |code| format!("exited with {code}"),
+            );
+            Err(if stderr.is_empty() {
+                status
+            } else {
+                format!("{status}: {stderr}")
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    use nexus_api::{
+        BoxFuture, CancellationToken, HttpClient, HttpError, HttpResponse,
+        Permissions, TokioRuntimeHandle,
+    };
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::*;
+
+    /// Sends requests as they are, over plain HTTP/1.1
+    #[derive(Debug)]
+    struct PlainHttp;
+
+    impl HttpClient for PlainHttp {
+        fn execute(
+            &self,
+            request: HttpRequest,
+        ) -> BoxFuture<Result<HttpResponse, HttpError>> {
+            Box::pin(async move {
+                let url = request.url.strip_prefix("http://").unwrap();
+                let (host, path) = url.split_once('/').unwrap_or((url, ""));
+                let failed =
+                    |e: std::io::Error| HttpError::Request(e.to_string());
+                let mut stream =
+                    TcpStream::connect(host).await.map_err(failed)?;
+                let head = format!(
+                    "{} /{path} HTTP/1.1\r\nHost: {host}\r\nConnection: close\r\n\r\n",
+                    request.method
+                );
+                stream.write_all(head.as_bytes()).await.map_err(failed)?;
+                let mut answer = Vec::new();
+                stream.read_to_end(&mut answer).await.map_err(failed)?;
+                let answer = String::from_utf8(answer).unwrap();
+                let (head, body) = answer.split_once("\r\n\r\n").unwrap();
+                let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+                Ok(HttpResponse::new(status).with_body(body))
+            })
+        }
+    }
+
+    fn runtime() -> RuntimeRef {
+        let permissions = Permissions {
+            network: vec!["127.0.0.1".into()],
+            exec: vec!["sh".into()],
+            ..Permissions::default()
+        };
+        let runtime = TokioRuntimeHandle::new(
+            tokio::runtime::Handle::current(),
+            "nexus-healthcheck",
+            CancellationToken::new(),
+        )
+        .with_permissions(permissions)
+        .with_http(Arc::new(PlainHttp));
+        Arc::new(runtime)
+    }
+
+    fn check(probe: Probe) -> Check {
+        Check {
+            name: "test".into(),
+            interval: Duration::from_mins(1),
+            timeout: Duration::from_secs(5),
+            down_after: 1,
+            up_after: 1,
+            probe,
+        }
+    }
+
+    /// Answers every connection with `answer`, unless it's `None`, in which
+    /// case it never answers
+    async fn serve(answer: Option<&'static str>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    // The request's head, which fits in a single read
+                    let mut request = [0; 1_024];
+                    let read = stream.read(&mut request).await.unwrap();
+                    assert_ne!(read, 0);
+                    let Some(answer) = answer else {
+                        std::future::pending::<()>().await;
+                        return;
+                    };
+                    stream.write_all(answer.as_bytes()).await.unwrap();
+                });
+            }
+        });
+        address
+    }
+
+    fn http(address: SocketAddr, path: &str) -> Probe {
+        Probe::Http {
+            url: format!("http://{address}/{path}"),
+            method: None,
+            expected_status: Vec::new(),
+            body_contains: None,
+        }
+    }
+
+    async fn probe(probe: Probe) -> Outcome {
+        run(&runtime(), &check(probe)).await
+    }
+
+    #[tokio::test]
+    async fn connects_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let host = "127.0.0.1".to_string();
+        let outcome = probe(Probe::Tcp { host, port }).await;
+        assert!(outcome.success, "{}", outcome.message);
+        assert_eq!(outcome.message, format!("connected to 127.0.0.1:{port}"));
+
+        // Nothing listens on it anymore
+        drop(listener);
+        let host = "127.0.0.1".to_string();
+        assert!(!probe(Probe::Tcp { host, port }).await.success);
+    }
+
+    #[tokio::test]
+    async fn fails_on_hosts_not_allowed() {
+        let host = "localhost".to_string();
+        let outcome = probe(Probe::Tcp { host, port: 22 }).await;
+        assert!(!outcome.success);
+    }
+
+    #[tokio::test]
+    async fn checks_the_http_status() {
+        let ok = serve(Some("HTTP/1.1 200 OK\r\n\r\nall good")).await;
+        let outcome = probe(http(ok, "health")).await;
+        assert!(outcome.success, "{}", outcome.message);
+        assert_eq!(outcome.message, "HTTP 200");
+
+        let unavailable = serve(Some("HTTP/1.1 503 Unavailable\r\n\r\n")).await;
+        let outcome = probe(http(unavailable, "health")).await;
+        assert!(!outcome.success);
+        assert_eq!(outcome.message, "unexpected HTTP 503");
+
+        let mut expected = http(unavailable, "health");
+        if let Probe::Http {
+            expected_status, ..
+        } = &mut expected
+        {
+            *expected_status = vec![503];
+        }
+        assert!(probe(expected).await.success);
+    }
+
+    #[tokio::test]
+    async fn checks_the_http_body() {
+        let address = serve(Some("HTTP/1.1 200 OK\r\n\r\nstatus: ok")).await;
+        let contains = |needle: &str| {
+            let mut probe = http(address, "");
+            if let Probe::Http { body_contains, .. } = &mut probe {
+                *body_contains = Some(needle.into());
+            }
+            probe
+        };
+        assert!(probe(contains("ok")).await.success);
+        let outcome = probe(contains("ready")).await;
+        assert!(!outcome.success);
+        assert_eq!(outcome.message, "HTTP 200, body lacks `ready`");
+    }
+
+    #[tokio::test]
+    async fn times_out() {
+        let address = serve(None).await;
+        let mut check = check(http(address, ""));
+        check.timeout = Duration::from_millis(100);
+        let outcome = run(&runtime(), &check).await;
+        assert!(!outcome.success);
+        assert_eq!(outcome.message, "timed out after 100ms");
+        assert!(outcome.latency >= check.timeout);
+    }
+
+    #[tokio::test]
+    async fn resolves_hosts() {
+        let resolve = |expected: &[&str]| Probe::Dns {
+            host: "127.0.0.1".into(),
+            expected: expected.iter().map(|ip| ip.parse().unwrap()).collect(),
+        };
+        let outcome = probe(resolve(&[])).await;
+        assert!(outcome.success, "{}", outcome.message);
+        assert_eq!(outcome.message, "resolved to 127.0.0.1");
+        assert!(probe(resolve(&["10.0.0.1", "127.0.0.1"])).await.success);
+        let outcome = probe(resolve(&["10.0.0.1"])).await;
+        assert_eq!(outcome.message, "unexpected addresses 127.0.0.1");
+    }
+
+    #[tokio::test]
+    async fn checks_the_exit_code_of_commands() {
+        let command = |script: &str, expected_code| Probe::Command {
+            command: vec!["sh".into(), "-c".into(), script.into()],
+            expected_code,
+        };
+        let outcome = probe(command("exit 0", 0)).await;
+        assert!(outcome.success, "{}", outcome.message);
+        assert!(probe(command("exit 3", 3)).await.success);
+        let outcome =
+            probe(command("echo starting >&2; echo oops >&2; exit 3", 0)).await;
+        assert!(!outcome.success);
+        assert_eq!(outcome.message, "exited with 3: oops");
+
+        // Not allowed to run
+        let not_allowed = Probe::Command {
+            command: vec!["true".into()],
+            expected_code: 0,
+        };
+        assert!(!probe(not_allowed).await.success);
+    }
+}
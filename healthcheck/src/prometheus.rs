@@ -0,0 +1,30 @@
+use std::fmt;
+
+use nexus_utils::prometheus::{self, gauge, sample};
+
+use crate::state::Results;
+
+pub use prometheus::CONTENT_TYPE;
+
+/// Renders the checks that ran in the Prometheus text exposition format.
+pub fn render(results: &Results) -> String {
+    prometheus::render(|out| write_results(out, results))
+}
+
+fn write_results(out: &mut String, results: &Results) -> fmt::Result {
+    let samples = results.samples();
+
+    let name = "nexus_check_up";
+    gauge(out, name, "Whether a health check is up.")?;
+    for (check, target, up, _) in &samples {
+        let up = if *up { 1.0 } else { 0.0 };
+        sample(out, name, &[("check", check), ("target", target)], up)?;
+    }
+    let name = "nexus_check_latency_seconds";
+    gauge(out, name, "How long the latest run of a health check took.")?;
+    for (check, target, _, latency) in &samples {
+        let labels = [("check", check.as_str()), ("target", target.as_str())];
+        sample(out, name, &labels, latency.as_secs_f64())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,406 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use nexus_api::StorageRef;
+use serde_json::{Value, json};
+use tracing::warn;
+
+use crate::config::Check;
+use crate::probe::Outcome;
+
+/// Prefix of the keys of the checks' health, kept across restarts so that
+/// a restart neither repeats nor misses an alert
+const HEALTH_PREFIX: &str = "health.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Health {
+    /// Not run yet
+    Pending,
+    Up,
+    Down,
+}
+
+impl Health {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Up => "up",
+            Self::Down => "down",
+        }
+    }
+
+    fn parse(health: &str) -> Option<Self> {
+        match health {
+            "up" => Some(Self::Up),
+            "down" => Some(Self::Down),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Health {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Change of a check's health.
+#[derive(Debug, Clone, Copy)]
+pub struct Transition {
+    pub from: Health,
+    pub to: Health,
+    /// How long it was in its previous state
+    pub lasted: Duration,
+}
+
+/// Health of a check, along with its latest run.
+#[derive(Debug)]
+pub struct CheckState {
+    /// e.g. `tcp 127.0.0.1:5432`
+    pub target: String,
+    pub health: Health,
+    /// When `health` last changed
+    pub since: SystemTime,
+    /// Consecutive failed runs, reset by a successful one
+    failures: u32,
+    /// Consecutive successful runs, reset by a failed one
+    successes: u32,
+    pub last: Option<(SystemTime, Outcome)>,
+    pub runs: u64,
+    pub failed_runs: u64,
+}
+
+impl CheckState {
+    fn new(check: &Check, saved: Option<(Health, SystemTime)>) -> Self {
+        let (health, since) =
+            saved.unwrap_or_else(|| (Health::Pending, SystemTime::now()));
+        Self {
+            target: check.probe.target(),
+            health,
+            since,
+            failures: 0,
+            successes: 0,
+            last: None,
+            runs: 0,
+            failed_runs: 0,
+        }
+    }
+
+    /// Records a run, returning the change of health it led to, if any.
+    /// A check is down after `down_after` consecutive failures, and back
+    /// up after `up_after` consecutive successes. A pending check is up on
+    /// its first success, as there's nothing to recover from.
+    fn record(
+        &mut self,
+        check: &Check,
+        at: SystemTime,
+        outcome: Outcome,
+    ) -> Option<Transition> {
+        self.runs += 1;
+        if outcome.success {
+            self.successes += 1;
+            self.failures = 0;
+        } else {
+            self.failed_runs += 1;
+            self.failures += 1;
+            self.successes = 0;
+        }
+        self.last = Some((at, outcome));
+
+        let health = match self.health {
+            Health::Pending if self.successes > 0 => Health::Up,
+            Health::Down if self.successes >= check.up_after => Health::Up,
+            Health::Pending | Health::Up
+                if self.failures >= check.down_after =>
+            {
+                Health::Down
+            }
+            health => health,
+        };
+        if health == self.health {
+            return None;
+        }
+        let transition = Transition {
+            from: self.health,
+            to: health,
+            lasted: at.duration_since(self.since).unwrap_or_default(),
+        };
+        self.health = health;
+        self.since = at;
+        Some(transition)
+    }
+
+    fn to_json(&self, name: &str) -> Value {
+        let last = self.last.as_ref().map(|(at, outcome)| {
+            json!({
+                "at": unix_secs(*at),
+                "success": outcome.success,
+                "latency_ms": outcome.latency.as_millis(),
+                "message": outcome.message,
+            })
+        });
+        json!({
+            "name": name,
+            "target": self.target,
+            "health": self.health.as_str(),
+            "since": unix_secs(self.since),
+            "runs": self.runs,
+            "failed_runs": self.failed_runs,
+            "last": last,
+        })
+    }
+}
+
+/// State of every check, shared by their tasks and the routes.
+#[derive(Debug)]
+pub struct Results {
+    checks: Mutex<BTreeMap<String, CheckState>>,
+    storage: Option<StorageRef>,
+}
+
+impl Results {
+    /// Every check pending, unless its health was saved before a restart
+    pub fn new(checks: &[Check], storage: Option<StorageRef>) -> Self {
+        let states = checks
+            .iter()
+            .map(|check| {
+                let saved = storage
+                    .as_ref()
+                    .and_then(|storage| saved(storage, &check.name));
+                (check.name.clone(), CheckState::new(check, saved))
+            })
+            .collect();
+        Self {
+            checks: Mutex::new(states),
+            storage,
+        }
+    }
+
+    /// Records a run of `check`, saving its health if it changed
+    pub fn record(
+        &self,
+        check: &Check,
+        at: SystemTime,
+        outcome: Outcome,
+    ) -> Option<Transition> {
+        let transition = self
+            .lock()
+            .get_mut(&check.name)
+            .and_then(|state| state.record(check, at, outcome))?;
+        if let Some(storage) = &self.storage {
+            let secs = unix_secs(at);
+            let value = format!("{} {secs}", transition.to);
+            if let Err(e) = storage.put(&key(&check.name), value.as_bytes()) {
+                warn!("Failed to save the health of `{}`: {e}", check.name);
+            }
+        }
+        Some(transition)
+    }
+
+    /// Every check, as JSON
+    pub fn to_json(&self) -> Value {
+        let checks = self
+            .lock()
+            .iter()
+            .map(|(name, state)| state.to_json(name))
+            .collect::<Vec<_>>();
+        json!({ "checks": checks })
+    }
+
+    /// A single check, as JSON, if it exists
+    pub fn check_json(&self, name: &str) -> Option<Value> {
+        self.lock().get(name).map(|state| state.to_json(name))
+    }
+
+    /// Health and latency of each check, as `(name, target, up, latency)`,
+    /// skipping those that didn't run yet
+    pub fn samples(&self) -> Vec<(String, String, bool, Duration)> {
+        self.lock()
+            .iter()
+            .filter_map(|(name, state)| {
+                let (_, outcome) = state.last.as_ref()?;
+                let up = state.health == Health::Up;
+                Some((name.clone(), state.target.clone(), up, outcome.latency))
+            })
+            .collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, CheckState>> {
+        self.checks.lock().expect("Results poisoned")
+    }
+}
+
+/// Health of `name` and since when, as saved by [`Results::record`]
+fn saved(storage: &StorageRef, name: &str) -> Option<(Health, SystemTime)> {
+    let saved = storage
+        .get(&key(name))
+        .inspect_err(|e| warn!("Failed to read the health of `{name}`: {e}"))
+        .ok()
+        .flatten()?;
+    let saved = String::from_utf8(saved).ok()?;
+    let (health, since) = saved.split_once(' ')?;
+    let since = UNIX_EPOCH + Duration::from_secs(since.parse().ok()?);
+    Some((Health::parse(health)?, since))
+}
+
+fn key(name: &str) -> Vec<u8> {
+    format!("{HEALTH_PREFIX}{name}").into_bytes()
+}
+
+fn unix_secs(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::slice;
+    use std::sync::Arc;
+
+    use nexus_api::{MemoryStorage, Storage};
+
+    use super::*;
+    use crate::config::Probe;
+
+    fn check(down_after: u32, up_after: u32) -> Check {
+        Check {
+            name: "database".into(),
+            interval: Duration::from_mins(1),
+            timeout: Duration::from_secs(10),
+            down_after,
+            up_after,
+            probe: Probe::Tcp {
+                host: "127.0.0.1".into(),
+                port: 5432,
+            },
+        }
+    }
+
+    fn outcome(success: bool) -> Outcome {
+        Outcome {
+            success,
+            latency: Duration::from_millis(3),
+            message: if success { "connected" } else { "refused" }.into(),
+        }
+    }
+
+    /// `secs` after the epoch, to the second as saved
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn record(
+        results: &Results,
+        check: &Check,
+        secs: u64,
+        success: bool,
+    ) -> Option<(Health, Health, Duration)> {
+        let transition = results.record(check, at(secs), outcome(success))?;
+        Some((transition.from, transition.to, transition.lasted))
+    }
+
+    fn health(results: &Results) -> Value {
+        results.check_json("database").unwrap()["health"].clone()
+    }
+
+    #[test]
+    fn is_up_on_the_first_success() {
+        let check = check(2, 3);
+        let results = Results::new(slice::from_ref(&check), None);
+        assert_eq!(health(&results), "pending");
+        let (from, to, _) = record(&results, &check, 10, true).unwrap();
+        // Alerted on by nothing, see `Monitor::notify`
+        assert_eq!((from, to), (Health::Pending, Health::Up));
+        assert_eq!(record(&results, &check, 20, true), None);
+    }
+
+    #[test]
+    fn is_down_after_consecutive_failures() {
+        let check = check(2, 1);
+        let results = Results::new(slice::from_ref(&check), None);
+        record(&results, &check, 0, true);
+        assert_eq!(record(&results, &check, 60, false), None);
+        // Resets the failures
+        assert_eq!(record(&results, &check, 120, true), None);
+        assert_eq!(record(&results, &check, 180, false), None);
+        assert_eq!(
+            record(&results, &check, 240, false),
+            Some((Health::Up, Health::Down, Duration::from_mins(4)))
+        );
+        assert_eq!(record(&results, &check, 300, false), None);
+        assert_eq!(health(&results), "down");
+    }
+
+    #[test]
+    fn reports_downtime_when_back_up() {
+        let check = check(1, 2);
+        let results = Results::new(slice::from_ref(&check), None);
+        record(&results, &check, 0, true);
+        record(&results, &check, 100, false);
+        assert_eq!(record(&results, &check, 160, true), None);
+        assert_eq!(record(&results, &check, 220, false), None);
+        assert_eq!(record(&results, &check, 280, true), None);
+        assert_eq!(
+            record(&results, &check, 340, true),
+            Some((Health::Down, Health::Up, Duration::from_mins(4)))
+        );
+    }
+
+    #[test]
+    fn can_be_down_before_ever_being_up() {
+        let check = check(2, 1);
+        let results = Results::new(slice::from_ref(&check), None);
+        assert_eq!(record(&results, &check, 0, false), None);
+        let (from, to, _) = record(&results, &check, 60, false).unwrap();
+        assert_eq!((from, to), (Health::Pending, Health::Down));
+    }
+
+    #[test]
+    fn restores_health_from_storage() {
+        let check = check(1, 1);
+        let storage: StorageRef = Arc::new(MemoryStorage::default());
+        let results =
+            Results::new(slice::from_ref(&check), Some(storage.clone()));
+        record(&results, &check, 1_000, true);
+        record(&results, &check, 2_000, false);
+        drop(results);
+
+        // After a restart, still down since then, so that recovering is
+        // alerted on along with the whole downtime
+        let results = Results::new(slice::from_ref(&check), Some(storage));
+        let json = results.check_json("database").unwrap();
+        assert_eq!(json["health"], "down");
+        assert_eq!(json["since"], 2_000);
+        assert_eq!(
+            record(&results, &check, 2_500, true),
+            Some((Health::Down, Health::Up, Duration::from_secs(500)))
+        );
+    }
+
+    #[test]
+    fn ignores_unreadable_saved_health() {
+        let check = check(1, 1);
+        let storage = MemoryStorage::default();
+        storage.put(&key("database"), b"sideways 12").unwrap();
+        let results = Results::new(&[check], Some(Arc::new(storage)));
+        assert_eq!(health(&results), "pending");
+    }
+
+    #[test]
+    fn samples_checks_that_ran() {
+        let up = check(1, 1);
+        let mut other = check(1, 1);
+        other.name = "website".into();
+        let results = Results::new(&[up.clone(), other], None);
+        record(&results, &up, 0, true);
+        let samples = results.samples();
+        assert_eq!(samples.len(), 1);
+        let (name, target, up, latency) = &samples[0];
+        assert_eq!(name, "database");
+        assert_eq!(target, "tcp 127.0.0.1:5432");
+        assert!(*up);
+        assert_eq!(*latency, Duration::from_millis(3));
+    }
+}
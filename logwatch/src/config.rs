@@ -0,0 +1,303 @@
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use std::collections::HashSet;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::template::Template;
+
+/// Settings of the plugin, from `[plugins.nexus-logwatch.settings]` in
+/// the host's `nexus.toml`.
+///
+/// ```toml
+/// [plugins.nexus-logwatch.settings]
+/// webhook_url = "https://discord.com/api/webhooks/..."
+///
+/// [[plugins.nexus-logwatch.settings.files]]
+/// name = "nginx"
+/// path = "/var/log/nginx/error.log"
+///
+/// [[plugins.nexus-logwatch.settings.rules]]
+/// name = "Upstream timed out"
+/// files = ["nginx"]
+/// pattern = 'upstream timed out .* upstream: "(?P<upstream>[^"]+)"'
+/// severity = "error"
+/// dedup_key = "{upstream}"
+/// rate_limit = { count = 5, per = "10m" }
+/// ```
+///
+/// Files need a read permission.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Discord webhook matches are posted to, none if unset
+    pub webhook_url: Option<String>,
+    /// Mentions added to the alerts, e.g. `<@1234>` or `@here`
+    pub user_tags: Vec<String>,
+    /// Matches of rules at least this severe are posted, the others are
+    /// only logged
+    pub alert_severity: Severity,
+    /// Default of the rules that don't set their own
+    #[serde(deserialize_with = "duration")]
+    pub dedup_window: Duration,
+    pub files: Vec<FileConfig>,
+    pub rules: Vec<RuleConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            webhook_url: None,
+            user_tags: Vec::new(),
+            alert_severity: Severity::Warning,
+            dedup_window: Duration::from_mins(5),
+            files: Vec::new(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+/// Log file followed from its end, across rotations.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileConfig {
+    /// Unique, shown in alerts and referred to by rules
+    pub name: String,
+    pub path: PathBuf,
+    /// Read the lines already in the file the first time it's followed
+    #[serde(default)]
+    pub from_start: bool,
+}
+
+/// Pattern looked for in every line of some files.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleConfig {
+    /// Unique, shown in alerts and metrics
+    pub name: String,
+    /// Names of the files it applies to, all of them if empty
+    #[serde(default)]
+    pub files: Vec<String>,
+    /// Regular expression, whose named captures can be used in templates
+    pub pattern: String,
+    #[serde(default)]
+    pub severity: Severity,
+    /// Template of what identifies a match, e.g. `{client}`. Matches with
+    /// the same key aren't alerted on again within the dedup window. Every
+    /// match is distinct if unset.
+    pub dedup_key: Option<String>,
+    #[serde(default, deserialize_with = "optional_duration")]
+    pub dedup_window: Option<Duration>,
+    pub rate_limit: Option<RateLimit>,
+    /// Template of the alert's text, the matched line if unset
+    pub message: Option<String>,
+}
+
+/// At most `count` alerts every `per`, the others are only counted.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    pub count: u32,
+    #[serde(deserialize_with = "duration")]
+    pub per: Duration,
+}
+
+/// How bad a match is, which sets the level of its tracing event.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    #[default]
+    Warning,
+    Error,
+    Critical,
+}
+
+impl Severity {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Info => "info",
+            Self::Warning => "warning",
+            Self::Error => "error",
+            Self::Critical => "critical",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A rule with its pattern and templates compiled, and the defaults it
+/// didn't override applied
+#[derive(Debug)]
+pub struct Rule {
+    pub name: String,
+    /// Empty if it applies to every file
+    pub files: Vec<String>,
+    pub regex: Regex,
+    pub severity: Severity,
+    /// Whether its matches are posted
+    pub alert: bool,
+    pub dedup_key: Option<Template>,
+    pub dedup_window: Duration,
+    pub rate_limit: Option<RateLimit>,
+    pub message: Option<Template>,
+}
+
+impl Rule {
+    pub fn applies_to(&self, file: &str) -> bool {
+        self.files.is_empty() || self.files.iter().any(|name| name == file)
+    }
+}
+
+/// Fields every template may use besides the rule's named captures
+pub const BUILTIN_FIELDS: [&str; 3] = ["file", "rule", "line"];
+
+/// Why the settings were rejected.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// Not valid TOML, or not matching [`Config`]
+    Parse(toml::de::Error),
+    /// Well-formed, but unusable
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse(e) => write!(f, "failed to parse settings: {e}"),
+            Self::Invalid(e) => write!(f, "invalid settings: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Parses and validates the plugin's settings, defaults if there's none
+    pub fn load(settings: Option<&str>) -> Result<Self, ConfigError> {
+        let config = match settings {
+            Some(settings) => {
+                toml::from_str::<Self>(settings).map_err(ConfigError::Parse)?
+            }
+            None => Self::default(),
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |e: String| Err(ConfigError::Invalid(e));
+        if let Some(url) = &self.webhook_url
+            && !url.starts_with("https://")
+            && !url.starts_with("http://")
+        {
+            return invalid(format!("`webhook_url` isn't a URL: {url}"));
+        }
+        let mut files = HashSet::new();
+        for file in &self.files {
+            let name = &file.name;
+            if name.is_empty() || name.contains('/') {
+                return invalid(format!("invalid file name `{name}`"));
+            }
+            if !files.insert(name.as_str()) {
+                return invalid(format!("file `{name}` is defined twice"));
+            }
+            if file.path.as_os_str().is_empty() {
+                return invalid(format!("file `{name}` has no path"));
+            }
+        }
+        let mut rules = HashSet::new();
+        for rule in &self.rules {
+            let name = &rule.name;
+            if name.is_empty() {
+                return invalid("a rule has no name".into());
+            }
+            if !rules.insert(name.as_str()) {
+                return invalid(format!("rule `{name}` is defined twice"));
+            }
+            if let Some(file) = rule
+                .files
+                .iter()
+                .find(|file| !files.contains(file.as_str()))
+            {
+                return invalid(format!(
+                    "rule `{name}` applies to an unknown file `{file}`"
+                ));
+            }
+            if rule
+                .rate_limit
+                .is_some_and(|limit| limit.count == 0 || limit.per.is_zero())
+            {
+                return invalid(format!(
+                    "rule `{name}` needs a non-zero rate limit"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Configured rules, compiled
+    pub fn rules(&self) -> Result<Vec<Rule>, ConfigError> {
+        self.rules.iter().map(|rule| self.compile(rule)).collect()
+    }
+
+    fn compile(&self, rule: &RuleConfig) -> Result<Rule, ConfigError> {
+        let name = &rule.name;
+        let regex = Regex::new(&rule.pattern).map_err(|e| {
+            ConfigError::Invalid(format!(
+                "rule `{name}` has a bad pattern: {e}"
+            ))
+        })?;
+        let template = |template: Option<&String>, field: &str| {
+            let Some(template) = template else {
+                return Ok(None);
+            };
+            let template = Template::parse(template).map_err(|e| {
+                ConfigError::Invalid(format!("rule `{name}` {field}: {e}"))
+            })?;
+            let captures = regex.capture_names().flatten().collect::<Vec<_>>();
+            let unknown = template.fields().find(|field| {
+                !BUILTIN_FIELDS.contains(field) && !captures.contains(field)
+            });
+            if let Some(unknown) = unknown {
+                return Err(ConfigError::Invalid(format!(
+                    "rule `{name}` {field} uses `{unknown}`, which isn't \
+                     captured"
+                )));
+            }
+            Ok(Some(template))
+        };
+        Ok(Rule {
+            name: name.clone(),
+            files: rule.files.clone(),
+            severity: rule.severity,
+            alert: rule.severity >= self.alert_severity,
+            dedup_key: template(rule.dedup_key.as_ref(), "dedup_key")?,
+            dedup_window: rule.dedup_window.unwrap_or(self.dedup_window),
+            rate_limit: rule.rate_limit,
+            message: template(rule.message.as_ref(), "message")?,
+            regex,
+        })
+    }
+}
+
+/// Human-readable duration, e.g. `1h 30m`
+fn duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Duration, D::Error> {
+    let duration = String::deserialize(deserializer)?;
+    humantime::parse_duration(&duration).map_err(serde::de::Error::custom)
+}
+
+fn optional_duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    duration(deserializer).map(Some)
+}
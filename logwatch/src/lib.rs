@@ -0,0 +1,114 @@
+mod config;
+mod prometheus;
+mod rules;
+mod template;
+mod watcher;
+
+use std::sync::Arc;
+
+use config::Config;
+use nexus_api::{
+    HttpResponse, Meta, Method, RouteError, TailCursor, TailOptions, r#impl,
+};
+use nexus_utils::discord::Alerts;
+use rules::Rules;
+use tracing::{error, info, warn};
+use watcher::Watcher;
+
+r#impl! {
+    pub static META: Meta = Meta {
+        name: env!("CARGO_PKG_NAME"),
+        authors: env!("CARGO_PKG_AUTHORS"),
+        version: env!("CARGO_PKG_VERSION"),
+    };
+
+    async fn main(&self) {
+        let config = Config::load(self.settings());
+        let (config, rules) = match config.and_then(|config| {
+            let rules = config.rules()?;
+            Ok((config, rules))
+        }) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                error!("Not watching logs, {e}");
+                return;
+            }
+        };
+        if config.files.is_empty() || rules.is_empty() {
+            info!("No log files or rules configured");
+            return;
+        }
+
+        let rules = Arc::new(Rules::new(rules));
+        self.serve(&rules);
+        let storage = self
+            .storage()
+            .inspect_err(|e| warn!("Positions in the logs won't be kept: {e}"))
+            .ok();
+        let watcher = Arc::new(Watcher::new(
+            rules,
+            Alerts::new(
+                self.runtime(),
+                config.webhook_url.clone(),
+                &config.user_tags,
+            ),
+            storage,
+        ));
+
+        let mut followed = 0;
+        for file in &config.files {
+            // Picks up where it stopped, so no line is missed or repeated
+            let cursor = watcher.cursor(file);
+            let resume = cursor.load();
+            let options = TailOptions {
+                from_start: file.from_start,
+                resume: resume.and_then(|c| TailCursor::from_bytes(&c)),
+                ..TailOptions::default()
+            };
+            let lines = match self.tail_file(&file.path, options).await {
+                Ok(lines) => lines,
+                Err(e) => {
+                    error!(
+                        "Not watching `{}`, failed to read {}: {e}",
+                        file.name,
+                        file.path.display()
+                    );
+                    continue;
+                }
+            };
+            let watcher = Arc::clone(&watcher);
+            let file = file.clone();
+            let name = format!("watch {}", file.name);
+            self.spawn_cancellable(&name, async move {
+                watcher.follow(&file, lines, cursor).await;
+            });
+            followed += 1;
+        }
+        if followed == 0 {
+            return;
+        }
+        info!("Now watching {followed} log files");
+
+        self.cancellation().cancelled().await;
+        info!("Stopped watching logs");
+    }
+}
+
+impl Instance {
+    /// Serves the counts of matches in Prometheus format on `/metrics`
+    fn serve(&self, rules: &Arc<Rules>) {
+        let scraped = Arc::clone(rules);
+        let route = self.route(Method::Get, "/metrics", move |_| {
+            let body = prometheus::render(&scraped);
+            async move {
+                HttpResponse::new(200)
+                    .with_header("Content-Type", prometheus::CONTENT_TYPE)
+                    .with_body(body)
+            }
+        });
+        match route {
+            Ok(()) | Err(RouteError::Unavailable) => {}
+            Err(e) => warn!("Failed to serve log metrics: {e}"),
+        }
+    }
+}
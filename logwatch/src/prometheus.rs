@@ -0,0 +1,43 @@
+use std::fmt;
+
+use nexus_utils::prometheus::{self, counter, sample};
+
+use crate::rules::Rules;
+
+pub use prometheus::CONTENT_TYPE;
+
+/// Renders the counts of the rules' matches in the Prometheus text
+/// exposition format.
+pub fn render(rules: &Rules) -> String {
+    prometheus::render(|out| write_counts(out, rules))
+}
+
+fn write_counts(out: &mut String, rules: &Rules) -> fmt::Result {
+    let counts = rules.counts();
+
+    let name = "nexus_logwatch_matches_total";
+    counter(out, name, "Lines matched by a rule.")?;
+    for (rule, counts) in &counts {
+        let labels = [
+            ("rule", rule.name.as_str()),
+            ("severity", rule.severity.as_str()),
+        ];
+        #[allow(clippy::cast_precision_loss)]
+        sample(out, name, &labels, counts.matches as f64)?;
+    }
+    let name = "nexus_logwatch_reported_total";
+    counter(
+        out,
+        name,
+        "Matches of a rule reported, neither duplicate nor rate limited.",
+    )?;
+    for (rule, counts) in &counts {
+        let labels = [
+            ("rule", rule.name.as_str()),
+            ("severity", rule.severity.as_str()),
+        ];
+        #[allow(clippy::cast_precision_loss)]
+        sample(out, name, &labels, counts.reported as f64)?;
+    }
+    Ok(())
+}
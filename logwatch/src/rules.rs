@@ -0,0 +1,221 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::config::{RateLimit, Rule};
+
+/// What's done with a match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// Reported, along with how many matches weren't since the previous
+    /// report
+    Report { suppressed: u64 },
+    /// Same dedup key as a match reported within the dedup window
+    Duplicate,
+    /// Too many reports within the rate limit's period
+    RateLimited,
+}
+
+/// Deduplication and rate limiting of a rule's matches.
+#[derive(Debug)]
+struct Limiter {
+    dedup_window: Duration,
+    rate_limit: Option<RateLimit>,
+    /// When each dedup key was last reported
+    reported: HashMap<String, Instant>,
+    /// When the latest reports were, within the rate limit's period
+    recent: VecDeque<Instant>,
+    /// Matches not reported since the previous report
+    suppressed: u64,
+}
+
+impl Limiter {
+    fn new(rule: &Rule) -> Self {
+        Self {
+            dedup_window: rule.dedup_window,
+            rate_limit: rule.rate_limit,
+            reported: HashMap::new(),
+            recent: VecDeque::new(),
+            suppressed: 0,
+        }
+    }
+
+    fn check(&mut self, key: Option<&str>, now: Instant) -> Verdict {
+        let window = self.dedup_window;
+        self.reported
+            .retain(|_, last| now.duration_since(*last) < window);
+        if let Some(key) = key
+            && self.reported.contains_key(key)
+        {
+            self.suppressed += 1;
+            return Verdict::Duplicate;
+        }
+        if let Some(RateLimit { count, per }) = self.rate_limit {
+            while self
+                .recent
+                .front()
+                .is_some_and(|sent| now.duration_since(*sent) >= per)
+            {
+                self.recent.pop_front();
+            }
+            if self.recent.len() >= count as usize {
+                self.suppressed += 1;
+                return Verdict::RateLimited;
+            }
+            self.recent.push_back(now);
+        }
+        if let Some(key) = key {
+            self.reported.insert(key.to_owned(), now);
+        }
+        Verdict::Report {
+            suppressed: std::mem::take(&mut self.suppressed),
+        }
+    }
+}
+
+/// Counts of a rule's matches since the plugin started.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Counts {
+    pub matches: u64,
+    pub reported: u64,
+}
+
+#[derive(Debug)]
+struct RuleState {
+    limiter: Limiter,
+    counts: Counts,
+}
+
+/// The rules, shared by the followed files, with the state of each.
+#[derive(Debug)]
+pub struct Rules {
+    rules: Vec<Rule>,
+    /// By rule index
+    states: Mutex<Vec<RuleState>>,
+}
+
+impl Rules {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        let states = rules
+            .iter()
+            .map(|rule| RuleState {
+                limiter: Limiter::new(rule),
+                counts: Counts::default(),
+            })
+            .collect();
+        Self {
+            rules,
+            states: Mutex::new(states),
+        }
+    }
+
+    /// Rules applying to `file`, by index
+    pub fn for_file(&self, file: &str) -> Vec<usize> {
+        (0..self.rules.len())
+            .filter(|&i| self.rules[i].applies_to(file))
+            .collect()
+    }
+
+    pub fn get(&self, index: usize) -> &Rule {
+        &self.rules[index]
+    }
+
+    /// Records a match of the rule at `index`, and whether it's reported
+    pub fn check(
+        &self,
+        index: usize,
+        key: Option<&str>,
+        now: Instant,
+    ) -> Verdict {
+        let mut states = self.lock();
+        let state = &mut states[index];
+        state.counts.matches += 1;
+        let verdict = state.limiter.check(key, now);
+        if matches!(verdict, Verdict::Report { .. }) {
+            state.counts.reported += 1;
+        }
+        drop(states);
+        verdict
+    }
+
+    /// Each rule along with its counts
+    pub fn counts(&self) -> Vec<(&Rule, Counts)> {
+        let counts = self.lock().iter().map(|state| state.counts).collect();
+        self.rules.iter().zip::<Vec<_>>(counts).collect()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<RuleState>> {
+        self.states.lock().expect("Rule states poisoned")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(dedup_window: u64, rate_limit: Option<(u32, u64)>) -> Limiter {
+        Limiter {
+            dedup_window: Duration::from_secs(dedup_window),
+            rate_limit: rate_limit.map(|(count, per)| RateLimit {
+                count,
+                per: Duration::from_secs(per),
+            }),
+            reported: HashMap::new(),
+            recent: VecDeque::new(),
+            suppressed: 0,
+        }
+    }
+
+    const fn report(suppressed: u64) -> Verdict {
+        Verdict::Report { suppressed }
+    }
+
+    #[test]
+    fn reports_everything_without_limits() {
+        let mut limiter = limiter(0, None);
+        let now = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(limiter.check(Some("a"), now), report(0));
+            assert_eq!(limiter.check(None, now), report(0));
+        }
+    }
+
+    #[test]
+    fn drops_duplicates_within_the_window() {
+        let mut limiter = limiter(60, None);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        assert_eq!(limiter.check(Some("a"), at(0)), report(0));
+        assert_eq!(limiter.check(Some("a"), at(30)), Verdict::Duplicate);
+        assert_eq!(limiter.check(Some("b"), at(30)), report(1));
+        assert_eq!(limiter.check(None, at(40)), report(0));
+        assert_eq!(limiter.check(Some("a"), at(59)), Verdict::Duplicate);
+        assert_eq!(limiter.check(Some("a"), at(60)), report(1));
+        assert_eq!(limiter.check(Some("a"), at(61)), Verdict::Duplicate);
+    }
+
+    #[test]
+    fn limits_the_rate_of_reports() {
+        let mut limiter = limiter(0, Some((2, 10)));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        assert_eq!(limiter.check(None, at(0)), report(0));
+        assert_eq!(limiter.check(None, at(1)), report(0));
+        assert_eq!(limiter.check(None, at(2)), Verdict::RateLimited);
+        assert_eq!(limiter.check(None, at(9)), Verdict::RateLimited);
+        assert_eq!(limiter.check(None, at(10)), report(2));
+        assert_eq!(limiter.check(None, at(10)), Verdict::RateLimited);
+        assert_eq!(limiter.check(None, at(11)), report(1));
+    }
+
+    #[test]
+    fn rate_limited_matches_are_not_deduplicated() {
+        let mut limiter = limiter(60, Some((1, 10)));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        assert_eq!(limiter.check(Some("a"), at(0)), report(0));
+        assert_eq!(limiter.check(Some("b"), at(1)), Verdict::RateLimited);
+        assert_eq!(limiter.check(Some("a"), at(2)), Verdict::Duplicate);
+        assert_eq!(limiter.check(Some("b"), at(10)), report(2));
+    }
+}
@@ -0,0 +1,123 @@
+/// Text with `{field}` placeholders, filled in from a match, e.g.
+/// `{client} failed on {file}`. Braces are escaped by doubling them.
+#[derive(Debug, Clone)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    Text(String),
+    Field(String),
+}
+
+impl Template {
+    /// Splits `template` into text and fields
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.next_if_eq(&'{').is_some() => text.push('{'),
+                '}' if chars.next_if_eq(&'}').is_some() => text.push('}'),
+                '{' => {
+                    let mut field = String::new();
+                    let closed = chars.by_ref().any(|c| {
+                        let end = c == '}';
+                        if !end {
+                            field.push(c);
+                        }
+                        end
+                    });
+                    if !closed || field.is_empty() {
+                        return Err("empty or unclosed `{}`".into());
+                    }
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Field(field));
+                }
+                '}' => return Err("unmatched `}`, double it as `}}`".into()),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Self { parts })
+    }
+
+    /// Names of the fields it uses
+    pub fn fields(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().filter_map(|part| match part {
+            Part::Field(field) => Some(field.as_str()),
+            Part::Text(_) => None,
+        })
+    }
+
+    /// Fills in the fields with `value`, empty where there's none, e.g.
+    /// for optional captures that didn't participate in the match
+    pub fn render<'a>(
+        &self,
+        value: impl Fn(&str) -> Option<&'a str>,
+    ) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.as_str(),
+                Part::Field(field) => value(field).unwrap_or_default(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str) -> String {
+        let template = Template::parse(template).unwrap();
+        template.render(|field| match field {
+            "client" => Some("10.0.0.1"),
+            "file" => Some("nginx"),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn fills_in_the_fields() {
+        assert_eq!(
+            render("{client} failed on {file}"),
+            "10.0.0.1 failed on nginx"
+        );
+        assert_eq!(render("{file}{client}"), "nginx10.0.0.1");
+        assert_eq!(render("no fields"), "no fields");
+        assert_eq!(render(""), "");
+    }
+
+    #[test]
+    fn leaves_missing_fields_empty() {
+        assert_eq!(render("[{user}] on {file}"), "[] on nginx");
+    }
+
+    #[test]
+    fn unescapes_doubled_braces() {
+        assert_eq!(render("{{client}} is {client}"), "{client} is 10.0.0.1");
+        assert_eq!(render("}}{{"), "}{");
+        assert_eq!(render("{{{client}}}"), "{10.0.0.1}");
+    }
+
+    #[test]
+    fn lists_its_fields() {
+        let template = Template::parse("{{a}} {b} and {c}").unwrap();
+        assert_eq!(template.fields().collect::<Vec<_>>(), ["b", "c"]);
+    }
+
+    #[test]
+    fn rejects_unbalanced_braces() {
+        for template in ["{client", "failed on {", "{}", "a } b", "{a}}"] {
+            assert!(Template::parse(template).is_err(), "{template}");
+        }
+    }
+}
@@ -0,0 +1,303 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use nexus_api::{Lines, StorageRef, TailCursor};
+use nexus_utils::cursor::SavedCursor;
+use nexus_utils::discord::{Alerts, field};
+use regex::Captures;
+use serde_json::json;
+use tracing::{error, info, warn};
+
+use crate::config::{FileConfig, Rule, Severity};
+use crate::rules::{Rules, Verdict};
+
+/// Longest value shown in an alert, Discord caps embed fields at 1024
+const MAX_VALUE: usize = 1_000;
+
+/// Line of a file matched by a rule.
+#[derive(Debug)]
+struct Match<'a> {
+    rule: &'a Rule,
+    file: &'a str,
+    line: &'a str,
+    /// Named captures that participated in the match
+    captures: Vec<(&'a str, &'a str)>,
+    key: Option<String>,
+    message: String,
+}
+
+impl<'a> Match<'a> {
+    fn new(
+        rule: &'a Rule,
+        file: &'a str,
+        line: &'a str,
+        captures: &Captures<'a>,
+    ) -> Self {
+        let captures = rule
+            .regex
+            .capture_names()
+            .flatten()
+            .filter_map(|name| Some((name, captures.name(name)?.as_str())))
+            .collect::<Vec<_>>();
+        let value = |field: &str| match field {
+            "file" => Some(file),
+            "rule" => Some(rule.name.as_str()),
+            "line" => Some(line),
+            field => captures
+                .iter()
+                .find(|(name, _)| *name == field)
+                .map(|(_, value)| *value),
+        };
+        let key = rule.dedup_key.as_ref().map(|key| key.render(value));
+        let message = rule
+            .message
+            .as_ref()
+            .map_or_else(|| line.to_owned(), |message| message.render(value));
+        Self {
+            rule,
+            file,
+            line,
+            captures,
+            key,
+            message,
+        }
+    }
+
+    /// Logs the match under the `logwatch` target, at its severity's level
+    fn emit(&self) {
+        let rule = &self.rule.name;
+        let file = self.file;
+        let key = self.key.as_deref().unwrap_or_default();
+        let message = &self.message;
+        match self.rule.severity {
+            Severity::Info => {
+                info!(target: "logwatch", rule, file, key, "{message}");
+            }
+            Severity::Warning => {
+                warn!(target: "logwatch", rule, file, key, "{message}");
+            }
+            Severity::Error | Severity::Critical => {
+                let severity = self.rule.severity.as_str();
+                error!(
+                    target: "logwatch",
+                    rule, file, key, severity, "{message}"
+                );
+            }
+        }
+    }
+}
+
+/// Follows log files, reporting the lines matching the rules.
+#[derive(Debug)]
+pub struct Watcher {
+    rules: Arc<Rules>,
+    alerts: Alerts,
+    storage: Option<StorageRef>,
+}
+
+impl Watcher {
+    pub const fn new(
+        rules: Arc<Rules>,
+        alerts: Alerts,
+        storage: Option<StorageRef>,
+    ) -> Self {
+        Self {
+            rules,
+            alerts,
+            storage,
+        }
+    }
+
+    /// Where reading `file` stopped, to resume from after a restart.
+    /// Kept per file path, so that another file doesn't resume at a random
+    /// offset.
+    pub fn cursor(&self, file: &FileConfig) -> SavedCursor {
+        let key = format!("cursor.{}", file.path.display());
+        let log = format!("`{}`", file.name);
+        SavedCursor::new(self.storage.clone(), key, log)
+    }
+
+    /// Handles every line of `lines`, until the file can't be followed
+    pub async fn follow(
+        &self,
+        file: &FileConfig,
+        mut lines: Lines,
+        mut cursor: SavedCursor,
+    ) {
+        let rules = self.rules.for_file(&file.name);
+        while let Some(line) = lines.next_line().await {
+            // Lines that aren't valid UTF-8 are read lossily, so errors are
+            // about opening or seeking the file, and no line is skipped
+            let reported = match line {
+                Ok(line) => self.check(&rules, file, &line).await,
+                Err(e) => {
+                    warn!("Failed to read `{}`: {e}", file.name);
+                    false
+                }
+            };
+            let position = lines.cursor().map(TailCursor::to_bytes);
+            cursor.save(
+                position.as_ref().map(|p| &p[..]),
+                reported,
+                Instant::now(),
+            );
+        }
+        error!("Stopped following `{}`", file.name);
+    }
+
+    /// Checks `line` against `rules`, and whether a match was reported
+    async fn check(
+        &self,
+        rules: &[usize],
+        file: &FileConfig,
+        line: &str,
+    ) -> bool {
+        let mut reported = false;
+        for &index in rules {
+            let rule = self.rules.get(index);
+            let Some(captures) = rule.regex.captures(line) else {
+                continue;
+            };
+            let found = Match::new(rule, &file.name, line, &captures);
+            let verdict =
+                self.rules
+                    .check(index, found.key.as_deref(), Instant::now());
+            if let Verdict::Report { suppressed } = verdict {
+                reported = true;
+                self.report(&found, suppressed).await;
+            }
+        }
+        reported
+    }
+
+    async fn report(&self, found: &Match<'_>, suppressed: u64) {
+        found.emit();
+        if !found.rule.alert {
+            return;
+        }
+
+        let rule = &found.rule.name;
+        let (title, color) = match found.rule.severity {
+            Severity::Info => {
+                (format!(":information_source: {rule}"), 3_447_003)
+            }
+            Severity::Warning => (format!(":warning: {rule}"), 16_705_372),
+            Severity::Error => (format!(":x: {rule}"), 15_548_997),
+            Severity::Critical => {
+                (format!(":rotating_light: {rule}"), 10_038_562)
+            }
+        };
+        let mut fields = vec![
+            field("File", &code(found.file), true),
+            field("Severity", found.rule.severity.as_str(), true),
+        ];
+        if let Some(key) = found.key.as_deref().filter(|key| !key.is_empty()) {
+            fields.push(field("Key", &code(key), true));
+        }
+        if suppressed > 0 {
+            let suppressed = format!("{suppressed} since the previous alert");
+            fields.push(field("Suppressed", &suppressed, true));
+        }
+        // Up to Discord's 25 fields
+        for (name, value) in found.captures.iter().take(20) {
+            fields.push(field(name, &code(value), true));
+        }
+        let mut description = truncate(&found.message);
+        // The line is the message unless it has a template
+        if found.rule.message.is_some() {
+            let line = truncate(found.line);
+            description = format!("{description}\n```\n{line}\n```");
+        }
+        let embed = json!({
+            "title": title,
+            "description": description,
+            "color": color,
+            "fields": fields,
+        });
+        if let Err(e) = self.alerts.send(embed).await {
+            warn!("Failed to post a match of `{rule}`: {e}");
+        }
+    }
+}
+
+fn code(value: &str) -> String {
+    format!("`{}`", truncate(value))
+}
+
+/// At most [`MAX_VALUE`] characters of `text`, with an ellipsis if cut
+fn truncate(text: &str) -> String {
+    match text.char_indices().nth(MAX_VALUE) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use nexus_api::{
+        CancellationToken, MemoryStorage, Permissions, RuntimeHandle, Storage,
+        TailOptions, TokioRuntimeHandle,
+    };
+
+    use super::*;
+    use crate::config::Config;
+
+    #[tokio::test]
+    async fn reports_lines_that_are_not_utf8() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().canonicalize().unwrap();
+        let path = dir.join("app.log");
+        std::fs::write(&path, b"caf\xe9 failed\nstarted\n").unwrap();
+        let settings = format!(
+            r#"
+            [[files]]
+            name = "app"
+            path = "{}"
+            from_start = true
+
+            [[rules]]
+            name = "Failure"
+            pattern = "failed"
+            "#,
+            path.display()
+        );
+        let config = Config::load(Some(&settings)).unwrap();
+        let rules = Arc::new(Rules::new(config.rules().unwrap()));
+        let storage = Arc::new(MemoryStorage::default());
+        let watcher = Watcher::new(
+            Arc::clone(&rules),
+            Alerts::default(),
+            Some(Arc::clone(&storage) as StorageRef),
+        );
+
+        let runtime = TokioRuntimeHandle::new(
+            tokio::runtime::Handle::current(),
+            "nexus-logwatch",
+            CancellationToken::new(),
+        )
+        .with_permissions(Permissions {
+            read: vec![dir],
+            ..Permissions::default()
+        });
+        let file = &config.files[0];
+        let options = TailOptions {
+            from_start: true,
+            ..TailOptions::default()
+        };
+        let lines = runtime.tail_file(&path, options).await.unwrap();
+        let cursor = watcher.cursor(file);
+        let followed = watcher.follow(file, lines, cursor);
+        tokio::time::timeout(Duration::from_millis(500), followed)
+            .await
+            .unwrap_err();
+
+        let (_, counts) = rules.counts()[0];
+        assert_eq!((counts.matches, counts.reported), (1, 1));
+        // Saved right after the report, past the invalid byte
+        let key = format!("cursor.{}", path.display());
+        let saved = storage.get(key.as_bytes()).unwrap().unwrap();
+        assert_eq!(TailCursor::from_bytes(&saved).unwrap().offset, 12);
+    }
+}
@@ -0,0 +1,146 @@
+#!/usr/bin/env python3
+"""Answers the few Docker Engine API calls `metrics/src/docker.rs` makes,
+on a Unix socket, with containers that change as they're listed:
+
+- `web` keeps running and stays healthy
+- `worker` exits with 137 at the 3rd listing
+- `db` is restarted by its restart policy at the 4th listing
+- `cache` fails its health check from the 2nd listing on
+
+    python3 mock_engine.py /tmp/docker.sock
+"""
+
+import json
+import os
+import socketserver
+import sys
+import time
+from http.server import BaseHTTPRequestHandler
+from urllib.parse import urlparse
+
+STARTED = time.time()
+listings = 0
+
+
+def containers():
+    """State of each container at the current listing"""
+    since = "2026-01-01T00:00:00Z"
+    db_started, db_restarts = since, 0
+    if listings >= 4:
+        db_started, db_restarts = "2026-01-01T01:00:00Z", 1
+    worker = ("running", 0) if listings < 3 else ("exited", 137)
+    cache_health = "healthy" if listings < 2 else "unhealthy"
+    return {
+        "web": ("nginx:1.27", "running", 0, since, 0, "healthy"),
+        "worker": ("app:latest", *worker, since, 0, None),
+        "db": ("postgres:17", "running", 0, db_started, db_restarts, None),
+        "cache": ("redis:7", "running", 0, since, 0, cache_health),
+    }
+
+
+def container_id(name):
+    return (name.encode().hex() * 64)[:64]
+
+
+def inspect(name, image, status, exit_code, started_at, restarts, health):
+    state = {
+        "Status": status,
+        "Running": status == "running",
+        "ExitCode": exit_code,
+        "StartedAt": started_at,
+    }
+    if health is not None:
+        state["Health"] = {"Status": health, "FailingStreak": 0}
+    return {
+        "Id": container_id(name),
+        "Name": f"/{name}",
+        "RestartCount": restarts,
+        "State": state,
+    }
+
+
+def stats(name):
+    """Counters growing steadily since the mock started"""
+    elapsed = time.time() - STARTED
+    seed = len(name)
+    return {
+        "cpu_stats": {
+            "cpu_usage": {"total_usage": int(elapsed * seed * 1e7)},
+            "system_cpu_usage": int(elapsed * 4e9),
+            "online_cpus": 4,
+        },
+        "memory_stats": {
+            "usage": seed * 32 * 1024 * 1024,
+            "limit": 2 * 1024 * 1024 * 1024,
+            "stats": {"inactive_file": 4 * 1024 * 1024},
+        },
+        "networks": {
+            "eth0": {
+                "rx_bytes": int(elapsed * seed * 1024),
+                "tx_bytes": int(elapsed * seed * 512),
+                "rx_packets": int(elapsed * 100),
+                "tx_packets": int(elapsed * 80),
+                "rx_errors": 0,
+                "tx_errors": 0,
+            }
+        },
+    }
+
+
+class Handler(BaseHTTPRequestHandler):
+    protocol_version = "HTTP/1.1"
+
+    def do_GET(self):
+        global listings
+        url = urlparse(self.path)
+        parts = url.path.strip("/").split("/")
+        current = containers()
+        by_id = {container_id(name): name for name in current}
+        if parts == ["containers", "json"]:
+            listings += 1
+            current = containers()
+            body = [
+                {
+                    "Id": container_id(name),
+                    "Names": [f"/{name}"],
+                    "Image": image,
+                    "State": status,
+                }
+                for name, (image, status, *_) in current.items()
+            ]
+            return self.answer(200, body)
+        if len(parts) == 3 and parts[0] == "containers":
+            name = by_id.get(parts[1])
+            if name is None:
+                return self.answer(404, {"message": "No such container"})
+            if parts[2] == "json":
+                return self.answer(200, inspect(name, *current[name]))
+            if parts[2] == "stats":
+                return self.answer(200, stats(name))
+        self.answer(404, {"message": "page not found"})
+
+    def answer(self, status, body):
+        body = json.dumps(body).encode()
+        self.send_response(status)
+        self.send_header("Content-Type", "application/json")
+        self.send_header("Content-Length", str(len(body)))
+        self.send_header("Connection", "close")
+        self.end_headers()
+        self.wfile.write(body)
+
+    def address_string(self):
+        # Peers of Unix sockets have no address
+        return "local"
+
+
+class Server(socketserver.ThreadingMixIn, socketserver.UnixStreamServer):
+    daemon_threads = True
+
+
+if __name__ == "__main__":
+    path = sys.argv[1] if len(sys.argv) > 1 else "/tmp/docker.sock"
+    if os.path.exists(path):
+        os.remove(path)
+    with Server(path, Handler) as server:
+        print(f"Mock Docker Engine listening on {path}", flush=True)
+        server.serve_forever()
@@ -0,0 +1,392 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+use tracing::warn;
+
+/// Side of the threshold that triggers a rule.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Comparison {
+    #[default]
+    Above,
+    Below,
+}
+
+/// Alert on a series crossing a threshold, e.g. "CPU > 90% for 5m".
+#[derive(Debug, Clone, Deserialize)]
+pub struct AlertRule {
+    pub name: String,
+    /// Series as listed by `/history`, where `*` matches anything,
+    /// e.g. `disk.*.percent`
+    pub series: String,
+    #[serde(default)]
+    pub when: Comparison,
+    pub threshold: f64,
+    /// How far back from the threshold the value must go to resolve,
+    /// so that a value hovering around it doesn't flap
+    #[serde(default)]
+    pub hysteresis: f64,
+    /// How long the threshold must stay crossed before firing
+    #[serde(
+        default,
+        rename = "for",
+        deserialize_with = "crate::config::duration"
+    )]
+    pub for_: Duration,
+}
+
+impl AlertRule {
+    /// CPU, memory, disks and network errors, with sensible thresholds
+    pub fn defaults() -> Vec<Self> {
+        let rule =
+            |name: &str, series: &str, threshold, hysteresis, mins| Self {
+                name: name.into(),
+                series: series.into(),
+                when: Comparison::Above,
+                threshold,
+                hysteresis,
+                for_: Duration::from_mins(mins),
+            };
+        vec![
+            rule("High CPU usage", "cpu", 90.0, 5.0, 5),
+            rule("High memory usage", "memory.percent", 95.0, 3.0, 1),
+            rule("Disk almost full", "disk.*.percent", 85.0, 2.0, 0),
+            rule("Receive errors", "net.*.rx_errors", 1.0, 0.5, 1),
+            rule("Transmit errors", "net.*.tx_errors", 1.0, 0.5, 1),
+        ]
+    }
+
+    fn matches(&self, key: &str) -> bool {
+        self.series.split_once('*').map_or_else(
+            || self.series == key,
+            |(prefix, suffix)| {
+                key.len() >= prefix.len() + suffix.len()
+                    && key.starts_with(prefix)
+                    && key.ends_with(suffix)
+            },
+        )
+    }
+
+    fn breached(&self, value: f64) -> bool {
+        match self.when {
+            Comparison::Above => value > self.threshold,
+            Comparison::Below => value < self.threshold,
+        }
+    }
+
+    fn cleared(&self, value: f64) -> bool {
+        match self.when {
+            Comparison::Above => value <= self.threshold - self.hysteresis,
+            Comparison::Below => value >= self.threshold + self.hysteresis,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    /// Breached since then, but not for long enough yet
+    Pending(Instant),
+    /// Along with the latest value
+    Firing(f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertState {
+    Firing,
+    Resolved,
+    /// One-off, e.g. a process restarting, which never resolves
+    Triggered,
+}
+
+impl AlertState {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Firing => "firing",
+            Self::Resolved => "resolved",
+            Self::Triggered => "triggered",
+        }
+    }
+}
+
+/// Transition of a rule on a series.
+#[derive(Debug, Clone)]
+pub struct AlertEvent {
+    pub rule: String,
+    pub series: String,
+    pub value: f64,
+    /// None for one-off events
+    pub threshold: Option<f64>,
+    pub state: AlertState,
+}
+
+impl AlertEvent {
+    /// Logs the event under the `alert` target,
+    /// which the Discord layer turns into its own embeds
+    pub fn emit(&self) {
+        let state = self.state.as_str();
+        if let Some(threshold) = self.threshold {
+            warn!(
+                target: "alert",
+                state,
+                rule = %self.rule,
+                series = %self.series,
+                value = self.value,
+                threshold,
+                "{} on {}: {:.2} (threshold {:.2})",
+                self.rule,
+                self.series,
+                self.value,
+                threshold,
+            );
+        } else {
+            warn!(
+                target: "alert",
+                state,
+                rule = %self.rule,
+                series = %self.series,
+                value = self.value,
+                "{} ({} is now {})",
+                self.rule,
+                self.series,
+                self.value,
+            );
+        }
+    }
+}
+
+/// Evaluates rules against each collection.
+#[derive(Debug)]
+pub struct Alerts {
+    rules: Vec<AlertRule>,
+    /// State of each rule (by index) on each series it matched
+    states: HashMap<(usize, String), State>,
+}
+
+impl Alerts {
+    pub fn new(rules: Vec<AlertRule>) -> Self {
+        Self {
+            rules,
+            states: HashMap::new(),
+        }
+    }
+
+    /// Updates every rule with `samples`, returning what fired or resolved.
+    /// Series missing from `samples`, e.g. of a removed container, resolve
+    /// with their last value.
+    pub fn evaluate(
+        &mut self,
+        now: Instant,
+        samples: &[(String, f64)],
+    ) -> Vec<AlertEvent> {
+        let mut events = Vec::new();
+        for (index, rule) in self.rules.iter().enumerate() {
+            for (key, value) in samples.iter().filter(|(k, _)| rule.matches(k))
+            {
+                let id = (index, key.clone());
+                let event = |state| AlertEvent {
+                    rule: rule.name.clone(),
+                    series: key.clone(),
+                    value: *value,
+                    threshold: Some(rule.threshold),
+                    state,
+                };
+
+                match self.states.get(&id).copied() {
+                    None if rule.breached(*value) => {
+                        if rule.for_.is_zero() {
+                            self.states.insert(id, State::Firing(*value));
+                            events.push(event(AlertState::Firing));
+                        } else {
+                            self.states.insert(id, State::Pending(now));
+                        }
+                    }
+                    Some(State::Pending(_)) if !rule.breached(*value) => {
+                        self.states.remove(&id);
+                    }
+                    Some(State::Pending(since))
+                        if now.duration_since(since) >= rule.for_ =>
+                    {
+                        self.states.insert(id, State::Firing(*value));
+                        events.push(event(AlertState::Firing));
+                    }
+                    Some(State::Firing(_)) if rule.cleared(*value) => {
+                        self.states.remove(&id);
+                        events.push(event(AlertState::Resolved));
+                    }
+                    Some(State::Firing(_)) => {
+                        self.states.insert(id, State::Firing(*value));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let reported = samples
+            .iter()
+            .map(|(key, _)| key.as_str())
+            .collect::<HashSet<_>>();
+        self.states.retain(|(index, key), state| {
+            if reported.contains(key.as_str()) {
+                return true;
+            }
+            if let State::Firing(value) = *state {
+                let rule = &self.rules[*index];
+                events.push(AlertEvent {
+                    rule: rule.name.clone(),
+                    series: key.clone(),
+                    value,
+                    threshold: Some(rule.threshold),
+                    state: AlertState::Resolved,
+                });
+            }
+            false
+        });
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(when: Comparison, hysteresis: f64, mins: u64) -> AlertRule {
+        AlertRule {
+            name: "Rule".into(),
+            series: "disk.*.percent".into(),
+            when,
+            threshold: 90.0,
+            hysteresis,
+            for_: Duration::from_mins(mins),
+        }
+    }
+
+    fn states(
+        alerts: &mut Alerts,
+        now: Instant,
+        samples: &[(&str, f64)],
+    ) -> Vec<(String, AlertState)> {
+        let samples = samples
+            .iter()
+            .map(|(key, value)| ((*key).to_owned(), *value))
+            .collect::<Vec<_>>();
+        alerts
+            .evaluate(now, &samples)
+            .into_iter()
+            .map(|event| (event.series, event.state))
+            .collect()
+    }
+
+    #[test]
+    fn matches_wildcards() {
+        let rule = rule(Comparison::Above, 0.0, 0);
+        assert!(rule.matches("disk./.percent"));
+        assert!(rule.matches("disk./dev/sda1.percent"));
+        assert!(!rule.matches("disk.percent"));
+        assert!(!rule.matches("disk./.used"));
+    }
+
+    #[test]
+    fn resolves_past_the_hysteresis() {
+        let mut alerts = Alerts::new(vec![rule(Comparison::Above, 5.0, 0)]);
+        let now = Instant::now();
+        let key = "disk./.percent";
+        let firing = vec![(key.to_owned(), AlertState::Firing)];
+        let resolved = vec![(key.to_owned(), AlertState::Resolved)];
+
+        assert_eq!(states(&mut alerts, now, &[(key, 95.0)]), firing);
+        assert_eq!(states(&mut alerts, now, &[(key, 96.0)]), []);
+        // Back under the threshold, but not by enough
+        assert_eq!(states(&mut alerts, now, &[(key, 88.0)]), []);
+        assert_eq!(states(&mut alerts, now, &[(key, 85.0)]), resolved);
+        assert_eq!(states(&mut alerts, now, &[(key, 85.0)]), []);
+    }
+
+    #[test]
+    fn compares_below_the_threshold() {
+        let mut alerts = Alerts::new(vec![rule(Comparison::Below, 5.0, 0)]);
+        let now = Instant::now();
+        let key = "disk./.percent";
+
+        assert_eq!(states(&mut alerts, now, &[(key, 95.0)]), []);
+        assert_eq!(
+            states(&mut alerts, now, &[(key, 80.0)]),
+            [(key.to_owned(), AlertState::Firing)]
+        );
+        assert_eq!(states(&mut alerts, now, &[(key, 92.0)]), []);
+        assert_eq!(
+            states(&mut alerts, now, &[(key, 95.0)]),
+            [(key.to_owned(), AlertState::Resolved)]
+        );
+    }
+
+    #[test]
+    fn fires_once_breached_for_long_enough() {
+        let mut alerts = Alerts::new(vec![rule(Comparison::Above, 0.0, 5)]);
+        let start = Instant::now();
+        let at = |mins| start + Duration::from_mins(mins);
+        let key = "disk./.percent";
+
+        assert_eq!(states(&mut alerts, at(0), &[(key, 95.0)]), []);
+        assert_eq!(states(&mut alerts, at(4), &[(key, 95.0)]), []);
+        assert_eq!(
+            states(&mut alerts, at(5), &[(key, 95.0)]),
+            [(key.to_owned(), AlertState::Firing)]
+        );
+    }
+
+    #[test]
+    fn restarts_the_wait_after_a_dip() {
+        let mut alerts = Alerts::new(vec![rule(Comparison::Above, 0.0, 5)]);
+        let start = Instant::now();
+        let at = |mins| start + Duration::from_mins(mins);
+        let key = "disk./.percent";
+
+        assert_eq!(states(&mut alerts, at(0), &[(key, 95.0)]), []);
+        assert_eq!(states(&mut alerts, at(3), &[(key, 50.0)]), []);
+        assert_eq!(states(&mut alerts, at(4), &[(key, 95.0)]), []);
+        assert_eq!(states(&mut alerts, at(8), &[(key, 95.0)]), []);
+        assert_eq!(
+            states(&mut alerts, at(9), &[(key, 95.0)]),
+            [(key.to_owned(), AlertState::Firing)]
+        );
+    }
+
+    #[test]
+    fn resolves_series_no_longer_reported() {
+        let mut alerts = Alerts::new(vec![rule(Comparison::Above, 0.0, 0)]);
+        let now = Instant::now();
+        let (gone, kept) = ("disk./mnt.percent", "disk./.percent");
+
+        states(&mut alerts, now, &[(gone, 95.0), (kept, 95.0)]);
+        let events = alerts.evaluate(now, &[(kept.to_owned(), 97.0)]);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].series, gone);
+        assert_eq!(events[0].state, AlertState::Resolved);
+        assert!((events[0].value - 95.0).abs() < f64::EPSILON);
+        // And fires again if it comes back
+        assert_eq!(
+            states(&mut alerts, now, &[(gone, 95.0), (kept, 97.0)]),
+            [(gone.to_owned(), AlertState::Firing)]
+        );
+    }
+
+    #[test]
+    fn forgets_pending_series_no_longer_reported() {
+        let mut alerts = Alerts::new(vec![rule(Comparison::Above, 0.0, 5)]);
+        let start = Instant::now();
+        let at = |mins| start + Duration::from_mins(mins);
+        let key = "disk./mnt.percent";
+
+        assert_eq!(states(&mut alerts, at(0), &[(key, 95.0)]), []);
+        assert_eq!(states(&mut alerts, at(1), &[]), []);
+        // Waits again from the start
+        assert_eq!(states(&mut alerts, at(5), &[(key, 95.0)]), []);
+        assert_eq!(
+            states(&mut alerts, at(10), &[(key, 95.0)]),
+            [(key.to_owned(), AlertState::Firing)]
+        );
+    }
+}
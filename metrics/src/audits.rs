@@ -1,86 +1,388 @@
-use crate::config::Config;
-use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::process::Command;
-use tokio::sync::Mutex;
-use tokio::task;
+mod bans;
+mod brute_force;
+mod geoip;
+mod journal;
+mod sshd;
 
-pub struct AuditMonitor {}
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant, SystemTime};
 
-impl AuditMonitor {
-    pub fn new(config: Arc<Config>, notifier: Arc<Notifier>) -> Self {
-        AuditMonitor { config, notifier }
+use nexus_api::{Lines, StorageRef};
+use nexus_utils::cursor::SavedCursor;
+use nexus_utils::discord::{Webhook, field};
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::{error, info, warn};
+
+use crate::config::SshAlertsConfig;
+pub use bans::Bans;
+pub use brute_force::BruteForceConfig;
+use brute_force::{BruteForce, Detection, Failure};
+pub use geoip::{GeoIp, GeoIpConfig};
+pub use journal::JournalConfig;
+use journal::JournalReader;
+use sshd::{Accepted, SyslogLine};
+
+/// Where the SSH daemon's log is read from
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogSource {
+    /// Flat text, e.g. `/var/log/auth.log`
+    #[default]
+    Syslog,
+    /// The systemd journal, see [`JournalConfig`]
+    Journal,
+}
+
+/// Message of the SSH daemon, whatever it was read from.
+#[derive(Debug)]
+pub struct LogEntry {
+    /// As logged, e.g. `Jan 5 10:00:00`
+    pub time: String,
+    /// When it was logged, or read if that's unknown
+    pub at: SystemTime,
+    pub host: Option<String>,
+    pub pid: Option<u32>,
+    pub message: String,
+}
+
+impl LogEntry {
+    /// From a syslog line, if it's from one of `identifiers`
+    fn from_syslog(line: &str, identifiers: &[String]) -> Option<Self> {
+        let line = SyslogLine::parse(line)?;
+        identifiers.iter().any(|i| i == line.program).then(|| Self {
+            at: line.logged_at(SystemTime::now()),
+            time: line.time,
+            host: Some(line.host.to_string()),
+            pid: line.pid,
+            message: line.message.to_string(),
+        })
     }
+}
 
-    pub async fn start(&self) {
-        let config = Arc::clone(&self.config);
-        let notifier = Arc::clone(&self.notifier);
-        task::spawn(async move {
-            let last_login_details = Arc::new(Mutex::new((None, 0u64)));
-            monitor_ssh_logins(&config, &notifier, last_login_details).await;
-        });
+/// A whole line or journal entry.
+pub struct Record {
+    /// None if it's not from the SSH daemon
+    entry: Option<LogEntry>,
+    /// Where the journal was read up to
+    cursor: Option<String>,
+}
+
+/// Turns the lines of the log into records.
+enum LogReader {
+    Syslog { identifiers: Vec<String> },
+    Journal(JournalReader),
+}
+
+impl LogReader {
+    fn new(config: &SshAlertsConfig) -> Self {
+        match config.source {
+            LogSource::Syslog => Self::Syslog {
+                identifiers: config.identifiers.clone(),
+            },
+            LogSource::Journal => Self::Journal(JournalReader::new(
+                &config.journal,
+                &config.identifiers,
+            )),
+        }
+    }
+
+    /// The record `line`, as read, completes, if any
+    fn push(&mut self, line: &[u8]) -> Option<Record> {
+        match self {
+            Self::Syslog { identifiers } => {
+                let line = String::from_utf8_lossy(line);
+                let line = line.trim_end_matches('\r');
+                Some(Record {
+                    entry: LogEntry::from_syslog(line, identifiers),
+                    cursor: None,
+                })
+            }
+            Self::Journal(reader) => reader.push(line),
+        }
     }
 }
 
-async fn monitor_ssh_logins(
-    config: &Arc<Config>,
-    notifier: &Arc<Notifier>,
-    last_login_details: Arc<Mutex<(Option<SshLoginDetails>, u64)>>,
-) {
-    let log_path = &config.ssh_alerts.log_path;
-
-    let mut child = Command::new("tail")
-        .arg("-F")
-        .arg(log_path)
-        .stdout(std::process::Stdio::piped())
-        .spawn()
-        .expect("Failed to execute tail command");
-
-    let stdout = child.stdout.take().expect("Failed to capture stdout");
-    let reader = BufReader::new(stdout);
-
-    let mut lines = reader.lines();
-
-    while let Some(line) = lines.next_line().await.unwrap_or(None) {
-        if line.contains("Accepted password for")
-            || line.contains("Accepted publickey for")
-        {
-            if let Some(details) = parse_ssh_login_details(&line) {
-                let should_send = {
-                    let mut last_login = last_login_details.lock().await;
-                    let current_time = SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs();
-                    if *last_login != (Some(details.clone()), current_time) {
-                        if current_time - last_login.1 >= 2 {
-                            *last_login = (Some(details.clone()), current_time);
-                            true
-                        } else {
-                            false
-                        }
-                    } else {
-                        false
-                    }
-                }; // MutexGuard dropped here
-
-                if should_send {
-                    notifier.send_ssh_login_embed(details.clone()).await;
-                }
+/// Accepted SSH login, as parsed from a log entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SshLoginDetails {
+    pub user: String,
+    pub ip: IpAddr,
+    pub port: Option<u16>,
+    /// e.g. `password` or `publickey`
+    pub method: String,
+    /// Type and fingerprint of the public key, e.g. `ED25519 SHA256:...`
+    pub key: Option<String>,
+    pub time: String,
+    pub host: Option<String>,
+    pub pid: Option<u32>,
+}
+
+impl SshLoginDetails {
+    /// From e.g. `Accepted publickey for root from 10.0.0.1 port 22 ssh2`
+    fn parse(entry: &LogEntry) -> Option<Self> {
+        let accepted = Accepted::parse(&entry.message)?;
+        let key = match (accepted.key_type, accepted.fingerprint) {
+            (Some(key_type), Some(fingerprint)) => {
+                Some(format!("{key_type} {fingerprint}"))
             }
+            (key_type, fingerprint) => key_type.or(fingerprint).map(Into::into),
+        };
+        Some(Self {
+            user: accepted.user.to_string(),
+            ip: accepted.address.ip,
+            port: accepted.address.port,
+            method: accepted.method.to_string(),
+            key,
+            time: entry.time.clone(),
+            host: entry.host.clone(),
+            pid: entry.pid,
+        })
+    }
+}
+
+/// Where reading the log stopped, to resume from after a restart: a
+/// [`nexus_api::TailCursor`] if following a file, else a journal cursor
+pub fn cursor(
+    storage: Option<StorageRef>,
+    config: &SshAlertsConfig,
+) -> SavedCursor {
+    let key = config.followed_path().map_or_else(
+        || "audits.cursor.journalctl".to_string(),
+        |path| format!("audits.cursor.{}", path.display()),
+    );
+    SavedCursor::new(storage, key, "the SSH log")
+}
+
+/// Watches the SSH log for accepted logins, posting an embed for each, and
+/// for IPs failing to log in too often.
+pub struct AuditMonitor {
+    reader: LogReader,
+    webhook: Option<Webhook>,
+    brute_force: Option<BruteForce>,
+    bans: Option<Bans>,
+    geoip: Option<GeoIp>,
+    dedup_window: Duration,
+    /// When each user and IP pair was last notified
+    last_notified: HashMap<(String, IpAddr), SystemTime>,
+    cursor: SavedCursor,
+}
+
+impl AuditMonitor {
+    pub fn new(
+        config: &SshAlertsConfig,
+        webhook: Option<Webhook>,
+        cursor: SavedCursor,
+        bans: Option<Bans>,
+        geoip: Option<GeoIp>,
+    ) -> Self {
+        let brute_force = &config.brute_force;
+        Self {
+            reader: LogReader::new(config),
+            webhook,
+            brute_force: brute_force
+                .enabled
+                .then(|| BruteForce::new(brute_force)),
+            bans,
+            geoip,
+            dedup_window: config.dedup_window,
+            last_notified: HashMap::new(),
+            cursor,
+        }
+    }
+
+    /// Handles every line of `lines`, until the log can't be followed
+    pub async fn start(mut self, mut lines: Lines) {
+        if let Some(bans) = &self.bans {
+            bans.restore();
+        }
+        monitor_ssh_logins(&mut self, &mut lines).await;
+    }
+
+    /// Whether `details`, logged at `at`, should be notified, i.e. the same
+    /// user didn't log in from the same IP within the dedup window.
+    /// Timed as logged, so that logins read at once after a restart aren't
+    /// mistaken for duplicates.
+    fn should_send(
+        &mut self,
+        details: &SshLoginDetails,
+        at: SystemTime,
+    ) -> bool {
+        let window = self.dedup_window;
+        self.last_notified.retain(|_, last| {
+            at.duration_since(*last).unwrap_or_default() < window
+        });
+        let key = (details.user.clone(), details.ip);
+        if self.last_notified.contains_key(&key) {
+            return false;
+        }
+        self.last_notified.insert(key, at);
+        true
+    }
+
+    async fn notify(&self, details: &SshLoginDetails) {
+        let SshLoginDetails {
+            user,
+            ip,
+            port,
+            method,
+            key,
+            time,
+            host,
+            pid: _,
+        } = details;
+        info!("SSH login of {user} from {ip} ({method})");
+        let (location, flagged) = self.locate(*ip);
+        if let Some(country) = &flagged {
+            warn!("SSH login of {user} from {ip}, in {country}, not allowed");
+        }
+        let Some(webhook) = &self.webhook else {
+            return;
+        };
+
+        let from = port.map_or_else(
+            || format!("`{ip}`"),
+            |port| format!("`{ip}` port {port}"),
+        );
+        let mut fields = vec![
+            field("User", &format!("`{user}`"), true),
+            field("From", &from, true),
+            field("Method", method, true),
+            field("Time", time, true),
+        ];
+        if let Some(host) = host {
+            fields.push(field("Host", host, true));
+        }
+        fields.extend(location);
+        if let Some(key) = key {
+            fields.push(field("Key", &format!("`{key}`"), false));
+        }
+        let (title, color) = if flagged.is_some() {
+            (":warning: SSH login from an unexpected country", 15_105_570)
+        } else {
+            (":key: SSH login", 3_447_003)
+        };
+        let embed = serde_json::json!({
+            "title": title,
+            "fields": fields,
+            "color": color,
+        });
+        if let Err(e) = webhook.send(None, vec![embed]).await {
+            warn!("Failed to post SSH login of {user}: {e}");
+        }
+    }
+
+    /// Alerts on `detection`, banning the IP if configured to
+    async fn brute_force(&self, detection: &Detection) {
+        let Detection {
+            ip,
+            failures,
+            users,
+        } = detection;
+        let banned = match &self.bans {
+            Some(bans) => bans.ban(*ip).await,
+            None => false,
+        };
+        warn!("SSH brute force from {ip}, {failures} failed logins");
+        let Some(webhook) = &self.webhook else {
+            return;
+        };
+
+        let users = users
+            .iter()
+            .take(10)
+            .map(|user| format!("`{user}`"))
+            .collect::<Vec<_>>();
+        let users = if users.is_empty() {
+            "-".to_string()
+        } else {
+            users.join(", ")
+        };
+        let action = if banned { "Banned" } else { "None" };
+        let mut fields = vec![
+            field("From", &format!("`{ip}`"), true),
+            field("Failures", &failures.to_string(), true),
+            field("Action", action, true),
+        ];
+        fields.extend(self.locate(*ip).0);
+        fields.push(field("Users", &users, false));
+        let embed = serde_json::json!({
+            "title": ":rotating_light: SSH brute force",
+            "fields": fields,
+            "color": 15_158_332,
+        });
+        if let Err(e) = webhook.send(None, vec![embed]).await {
+            warn!("Failed to post SSH brute force from {ip}: {e}");
         }
     }
+
+    /// Embed fields of where `ip` is, and its country if logins aren't
+    /// expected from there
+    fn locate(&self, ip: IpAddr) -> (Vec<Value>, Option<String>) {
+        let Some(geoip) = &self.geoip else {
+            return (Vec::new(), None);
+        };
+        let location = geoip.lookup(ip);
+        let fields = [
+            ("Location", location.place()),
+            ("Network", location.network()),
+        ];
+        let fields = fields
+            .into_iter()
+            .filter_map(|(name, value)| Some(field(name, &value?, true)))
+            .collect();
+        let flagged = geoip.is_flagged(&location).then_some(location.country);
+        (fields, flagged.flatten())
+    }
 }
 
-fn parse_ssh_login_details(log_line: &str) -> Option<SshLoginDetails> {
-    let parts: Vec<&str> = log_line.split_whitespace().collect();
-    if parts.len() >= 11 {
-        let time = format!("{} {} {}", parts[0], parts[1], parts[2]);
-        let user = parts[8].to_string();
-        let ip = parts[10].to_string();
-        Some(SshLoginDetails { user, ip, time })
-    } else {
-        None
+async fn monitor_ssh_logins(monitor: &mut AuditMonitor, lines: &mut Lines) {
+    // Raw, as the journal's export format holds binary data
+    while let Some(line) = lines.next_raw_line().await {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("Failed to read the SSH log: {e}");
+                continue;
+            }
+        };
+        // Journal entries span several lines in the export format
+        let Some(record) = monitor.reader.push(&line) else {
+            continue;
+        };
+        let login = record
+            .entry
+            .as_ref()
+            .filter(|entry| entry.message.starts_with("Accepted "));
+        if let Some(entry) = login {
+            if let Some(details) = SshLoginDetails::parse(entry) {
+                if monitor.should_send(&details, entry.at) {
+                    monitor.notify(&details).await;
+                }
+            } else {
+                warn!("Unrecognized SSH login: {}", entry.message);
+            }
+        }
+        let detection = record
+            .entry
+            .as_ref()
+            .and_then(|entry| Some((Failure::parse(entry)?, entry.at)))
+            .zip(monitor.brute_force.as_mut())
+            .and_then(|((failure, at), brute_force)| {
+                brute_force.record(failure, at)
+            });
+        if let Some(detection) = &detection {
+            monitor.brute_force(detection).await;
+        }
+        let cursor = lines
+            .cursor()
+            .map(|cursor| cursor.to_bytes().to_vec())
+            .or_else(|| record.cursor.map(String::into_bytes));
+        let notified = login.is_some() || detection.is_some();
+        monitor
+            .cursor
+            .save(cursor.as_deref(), notified, Instant::now());
     }
+    error!("Stopped following the SSH log");
 }
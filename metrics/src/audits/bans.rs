@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use nexus_api::{ProcessCommand, RuntimeRef, StorageRef};
+use serde::Deserialize;
+use tracing::{info, warn};
+
+/// Command banning an IP, e.g. by adding it to an nftables or ipset set,
+/// and the one unbanning it. `{ip}` is replaced in their arguments.
+///
+/// ```toml
+/// [plugins.nexus-metrics.settings.ssh_alerts.brute_force.ban]
+/// command = ["nft", "add", "element", "inet", "filter", "ssh_banned", "{ {ip} }"]
+/// unban_command = ["nft", "delete", "element", "inet", "filter", "ssh_banned", "{ {ip} }"]
+/// duration = "1h"
+/// ```
+///
+/// The program needs an exec permission.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BanConfig {
+    pub command: Vec<String>,
+    /// Run once `duration` has passed, never if empty
+    #[serde(default)]
+    pub unban_command: Vec<String>,
+    #[serde(
+        default = "default_duration",
+        deserialize_with = "crate::config::duration"
+    )]
+    pub duration: Duration,
+    /// Log the commands instead of running them
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+const fn default_duration() -> Duration {
+    Duration::from_hours(1)
+}
+
+impl BanConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        let placeholder = |command: &[String]| {
+            command.iter().any(|arg| arg.contains(IP_PLACEHOLDER))
+        };
+        if !placeholder(&self.command) {
+            return Err("the ban `command` must contain `{ip}`".into());
+        }
+        if !self.unban_command.is_empty() && !placeholder(&self.unban_command) {
+            return Err("the `unban_command` must contain `{ip}`".into());
+        }
+        Ok(())
+    }
+}
+
+/// Replaced by the IP in the commands' arguments
+#[allow(clippy::literal_string_with_formatting_args)]
+const IP_PLACEHOLDER: &str = "{ip}";
+
+/// How long a command may take
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Prefix of the keys of the bans, which are kept across restarts
+const BAN_PREFIX: &str = "audits.bans.";
+
+/// Bans IPs, and unbans them once they've served their time.
+#[derive(Debug, Clone)]
+pub struct Bans {
+    config: Arc<BanConfig>,
+    runtime: RuntimeRef,
+    storage: Option<StorageRef>,
+    /// Banned IPs, until when
+    banned: Arc<Mutex<HashMap<IpAddr, SystemTime>>>,
+}
+
+impl Bans {
+    pub fn new(
+        config: &BanConfig,
+        runtime: RuntimeRef,
+        storage: Option<StorageRef>,
+    ) -> Self {
+        Self {
+            config: Arc::new(config.clone()),
+            runtime,
+            storage,
+            banned: Arc::default(),
+        }
+    }
+
+    /// Schedules unbanning the IPs banned before a restart, right away if
+    /// their time is up
+    pub fn restore(&self) {
+        let Some(storage) = &self.storage else {
+            return;
+        };
+        let saved = match storage.scan_prefix(BAN_PREFIX.as_bytes()) {
+            Ok(saved) => saved,
+            Err(e) => {
+                warn!("Failed to read SSH bans: {e}");
+                return;
+            }
+        };
+        for (key, until) in saved {
+            let ip = key
+                .strip_prefix(BAN_PREFIX.as_bytes())
+                .and_then(|ip| std::str::from_utf8(ip).ok())
+                .and_then(|ip| ip.parse().ok());
+            let until = <[u8; 8]>::try_from(until.as_slice())
+                .map(|secs| Duration::from_secs(u64::from_be_bytes(secs)));
+            if let (Some(ip), Ok(until)) = (ip, until) {
+                let until = SystemTime::UNIX_EPOCH + until;
+                self.lock().insert(ip, until);
+                self.schedule_unban(ip, until);
+            }
+        }
+    }
+
+    /// Whether `ip` was banned, i.e. the command succeeded or would have
+    pub async fn ban(&self, ip: IpAddr) -> bool {
+        if self.lock().contains_key(&ip) {
+            return false;
+        }
+        if !self.run(&self.config.command, ip, "ban").await {
+            return false;
+        }
+        let until = SystemTime::now() + self.config.duration;
+        self.lock().insert(ip, until);
+        if !self.config.dry_run
+            && let Some(storage) = &self.storage
+        {
+            let secs = until
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            if let Err(e) = storage.put(&key(ip), &secs.to_be_bytes()) {
+                warn!(
+                    "Failed to save the ban of {ip}, it won't be lifted on restart: {e}"
+                );
+            }
+        }
+        self.schedule_unban(ip, until);
+        true
+    }
+
+    fn schedule_unban(&self, ip: IpAddr, until: SystemTime) {
+        let bans = self.clone();
+        let wait = until.duration_since(SystemTime::now()).unwrap_or_default();
+        let cancellation = self.runtime.cancellation().clone();
+        self.runtime.spawn_named("ssh unban", async move {
+            // Unbanned on the next start instead, as the ban is saved
+            let waited = cancellation
+                .run_until_cancelled(bans.runtime.sleep(wait))
+                .await;
+            if waited.is_ok() {
+                bans.unban(ip).await;
+            }
+        });
+    }
+
+    async fn unban(&self, ip: IpAddr) {
+        let command = &self.config.unban_command;
+        if !command.is_empty() && !self.run(command, ip, "unban").await {
+            // Kept, so that it's tried again on the next start
+            return;
+        }
+        self.lock().remove(&ip);
+        if let Some(storage) = &self.storage
+            && let Err(e) = storage.delete(&key(ip))
+        {
+            warn!("Failed to forget the ban of {ip}: {e}");
+        }
+    }
+
+    /// Runs `command` for `ip`, returning whether it succeeded
+    async fn run(&self, command: &[String], ip: IpAddr, action: &str) -> bool {
+        let args = command
+            .iter()
+            .map(|arg| arg.replace(IP_PLACEHOLDER, &ip.to_string()));
+        let args = args.collect::<Vec<_>>();
+        let Some((program, args)) = args.split_first() else {
+            return false;
+        };
+        if self.config.dry_run {
+            info!("Dry run, not running: {}", args_line(program, args));
+            return true;
+        }
+
+        let process = ProcessCommand::new(program)
+            .args(args)
+            .timeout(COMMAND_TIMEOUT);
+        match self.runtime.run_process(process).await {
+            Ok(output) if output.status.success() => {
+                info!("Ran the SSH {action} command of {ip}");
+                true
+            }
+            Ok(output) => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                warn!(
+                    "The SSH {action} command of {ip} failed with {}: {}",
+                    output.status,
+                    stderr.trim()
+                );
+                false
+            }
+            Err(e) => {
+                warn!("Failed to run the SSH {action} command of {ip}: {e}");
+                false
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<IpAddr, SystemTime>> {
+        self.banned.lock().expect("Bans poisoned")
+    }
+}
+
+fn key(ip: IpAddr) -> Vec<u8> {
+    format!("{BAN_PREFIX}{ip}").into_bytes()
+}
+
+fn args_line(program: &str, args: &[String]) -> String {
+    std::iter::once(program)
+        .chain(args.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,370 @@
+use std::collections::{BTreeSet, HashMap};
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime};
+
+use serde::Deserialize;
+
+use super::LogEntry;
+use super::bans::BanConfig;
+use super::sshd::Address;
+
+/// Alerts on IPs failing to log in too often.
+///
+/// ```toml
+/// [plugins.nexus-metrics.settings.ssh_alerts.brute_force]
+/// max_failures = 10
+/// window = "10m"
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BruteForceConfig {
+    pub enabled: bool,
+    /// Failures of an IP within `window` that raise an alert
+    pub max_failures: usize,
+    #[serde(deserialize_with = "crate::config::duration")]
+    pub window: Duration,
+    /// Command banning the IPs alerted on, none if unset
+    pub ban: Option<BanConfig>,
+}
+
+impl Default for BruteForceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_failures: 10,
+            window: Duration::from_mins(10),
+            ban: None,
+        }
+    }
+}
+
+/// Failed or aborted login attempt, as logged by the SSH daemon.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    pub ip: IpAddr,
+    pub port: Option<u16>,
+    /// None if the client disconnected before sending one
+    pub user: Option<String>,
+    /// Whether credentials were actually rejected, as opposed to an
+    /// unknown user or a disconnection before authenticating
+    pub attempt: bool,
+}
+
+impl Failure {
+    /// From the messages of failed authentications, e.g.
+    /// `Failed password for invalid user bob from 10.0.0.1 port 22 ssh2`,
+    /// `Invalid user bob from 10.0.0.1 port 22`, or pre-auth disconnects,
+    /// e.g. `Connection closed by 10.0.0.1 port 22 [preauth]`
+    pub fn parse(entry: &LogEntry) -> Option<Self> {
+        let message = entry.message.as_str();
+        if let Some(rest) = message.strip_prefix("Failed ") {
+            let (_method, rest) = rest.split_once(" for ")?;
+            let rest = rest.strip_prefix("invalid user ").unwrap_or(rest);
+            let (user, rest) = rest.rsplit_once(" from ")?;
+            return Self::new(Some(user), rest, true);
+        }
+        if let Some(rest) = message.strip_prefix("Invalid user ") {
+            let (user, rest) = rest.rsplit_once(" from ")?;
+            return Self::new(Some(user), rest, false);
+        }
+
+        let rest = message.strip_suffix(" [preauth]")?;
+        let rest = ["Disconnected from ", "Connection closed by "]
+            .into_iter()
+            .find_map(|prefix| rest.strip_prefix(prefix))
+            .or_else(|| rest.strip_prefix("Received disconnect from "))?;
+        let user = ["authenticating user ", "invalid user "]
+            .into_iter()
+            .find_map(|prefix| rest.strip_prefix(prefix));
+        match user {
+            // `user 10.0.0.1 port 22`, where the user may hold spaces
+            Some(rest) => {
+                let (user, rest) = rest.rsplit_once(" port ")?;
+                let (user, ip) = user.rsplit_once(' ')?;
+                Self::new(Some(user), &format!("{ip} port {rest}"), false)
+            }
+            None => Self::new(None, rest, false),
+        }
+    }
+
+    /// From `ip port 22 ...`
+    fn new(user: Option<&str>, rest: &str, attempt: bool) -> Option<Self> {
+        let (Address { ip, port }, _) = Address::parse(rest)?;
+        Some(Self {
+            ip,
+            port,
+            user: user.map(str::to_string),
+            attempt,
+        })
+    }
+}
+
+/// IP whose failures crossed the threshold.
+#[derive(Debug)]
+pub struct Detection {
+    pub ip: IpAddr,
+    pub failures: usize,
+    /// Users it tried, as many as were logged
+    pub users: BTreeSet<String>,
+}
+
+/// Counts failures per IP over a sliding window.
+#[derive(Debug)]
+pub struct BruteForce {
+    max_failures: usize,
+    window: Duration,
+    /// Along with when they were logged
+    failures: HashMap<IpAddr, Vec<(SystemTime, Failure)>>,
+    /// IPs already alerted on, so that they're only alerted on again once
+    /// the window has passed
+    alerted: HashMap<IpAddr, SystemTime>,
+}
+
+impl BruteForce {
+    pub fn new(config: &BruteForceConfig) -> Self {
+        Self {
+            max_failures: config.max_failures,
+            window: config.window,
+            failures: HashMap::new(),
+            alerted: HashMap::new(),
+        }
+    }
+
+    /// Records `failure`, logged at `now`, returning a detection if its IP
+    /// now failed too often. Timed as logged rather than read, so that a
+    /// backlog read at once, e.g. after a restart, isn't mistaken for a
+    /// burst.
+    pub fn record(
+        &mut self,
+        failure: Failure,
+        now: SystemTime,
+    ) -> Option<Detection> {
+        let window = self.window;
+        // Entries may be slightly out of order
+        let recent = |at: &SystemTime| {
+            now.duration_since(*at).unwrap_or_default() < window
+        };
+        self.alerted.retain(|_, at| recent(at));
+        self.failures.retain(|_, failures| {
+            failures.retain(|(at, _)| recent(at));
+            !failures.is_empty()
+        });
+
+        let ip = failure.ip;
+        if self.alerted.contains_key(&ip) {
+            return None;
+        }
+        let failures = self.failures.entry(ip).or_default();
+        failures.push((now, failure));
+        let count = count(failures);
+        if count < self.max_failures {
+            return None;
+        }
+
+        let failures = self.failures.remove(&ip).unwrap_or_default();
+        self.alerted.insert(ip, now);
+        let users = failures.into_iter().filter_map(|(_, f)| f.user);
+        Some(Detection {
+            ip,
+            failures: count,
+            users: users.collect(),
+        })
+    }
+}
+
+/// A single try usually logs several messages, e.g. `Invalid user`, then
+/// `Failed password for invalid user`, then a pre-auth disconnect. Rejected
+/// credentials are counted each, and other messages once per connection,
+/// unless credentials were rejected on it.
+fn count(failures: &[(SystemTime, Failure)]) -> usize {
+    let attempts = failures.iter().filter(|(_, f)| f.attempt);
+    let tried = attempts
+        .clone()
+        .map(|(_, f)| f.port)
+        .collect::<BTreeSet<_>>();
+    let connections = failures
+        .iter()
+        .filter(|(_, f)| !f.attempt && !tried.contains(&f.port))
+        .map(|(_, f)| f.port)
+        .collect::<BTreeSet<_>>();
+    attempts.count() + connections.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IP: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(192, 0, 2, 1));
+
+    fn entry(message: &str) -> LogEntry {
+        LogEntry {
+            time: String::new(),
+            at: SystemTime::UNIX_EPOCH,
+            host: None,
+            pid: None,
+            message: message.into(),
+        }
+    }
+
+    fn failure(port: u16, user: Option<&str>, attempt: bool) -> Failure {
+        Failure {
+            ip: IP,
+            port: Some(port),
+            user: user.map(Into::into),
+            attempt,
+        }
+    }
+
+    fn brute_force(max_failures: usize) -> BruteForce {
+        BruteForce::new(&BruteForceConfig {
+            max_failures,
+            ..BruteForceConfig::default()
+        })
+    }
+
+    fn at(mins: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH
+            + Duration::from_hours(1000)
+            + Duration::from_mins(mins)
+    }
+
+    #[test]
+    fn parses_failures() {
+        let parse = |message| Failure::parse(&entry(message));
+        assert_eq!(
+            parse("Failed password for root from 192.0.2.1 port 22 ssh2"),
+            Some(failure(22, Some("root"), true))
+        );
+        assert_eq!(
+            parse(
+                "Failed password for invalid user bob smith from 192.0.2.1 \
+                 port 22 ssh2"
+            ),
+            Some(failure(22, Some("bob smith"), true))
+        );
+        assert_eq!(
+            parse("Invalid user bob from 192.0.2.1 port 22"),
+            Some(failure(22, Some("bob"), false))
+        );
+        assert_eq!(
+            parse("Connection closed by 192.0.2.1 port 22 [preauth]"),
+            Some(failure(22, None, false))
+        );
+        assert_eq!(
+            parse(
+                "Disconnected from authenticating user root 192.0.2.1 port \
+                 22 [preauth]"
+            ),
+            Some(failure(22, Some("root"), false))
+        );
+        assert_eq!(
+            parse(
+                "Received disconnect from 192.0.2.1 port 22:11: Bye Bye \
+                 [preauth]"
+            ),
+            Some(failure(22, None, false))
+        );
+        // Disconnects after logging in aren't failures
+        assert_eq!(parse("Disconnected from 192.0.2.1 port 22"), None);
+        assert_eq!(
+            parse("Accepted publickey for root from 192.0.2.1 port 22 ssh2"),
+            None
+        );
+    }
+
+    #[test]
+    fn counts_rejected_credentials_each() {
+        let failures = [
+            (at(0), failure(22, Some("root"), true)),
+            (at(0), failure(22, Some("root"), true)),
+            (at(0), failure(22, None, false)),
+        ];
+        assert_eq!(count(&failures), 2);
+    }
+
+    #[test]
+    fn counts_other_failures_once_per_connection() {
+        let failures = [
+            (at(0), failure(22, Some("bob"), false)),
+            (at(0), failure(22, Some("bob"), false)),
+            (at(0), failure(23, None, false)),
+            (at(0), failure(24, None, false)),
+        ];
+        assert_eq!(count(&failures), 3);
+    }
+
+    #[test]
+    fn detects_too_many_failures_within_the_window() {
+        let mut brute_force = brute_force(3);
+        assert!(
+            brute_force
+                .record(failure(1, Some("root"), true), at(0))
+                .is_none()
+        );
+        assert!(
+            brute_force
+                .record(failure(2, Some("admin"), false), at(1))
+                .is_none()
+        );
+        let detection = brute_force
+            .record(failure(3, Some("root"), true), at(2))
+            .unwrap();
+        assert_eq!(detection.ip, IP);
+        assert_eq!(detection.failures, 3);
+        assert_eq!(
+            detection.users.into_iter().collect::<Vec<_>>(),
+            ["admin", "root"]
+        );
+    }
+
+    #[test]
+    fn alerts_on_an_ip_again_once_the_window_passed() {
+        let mut brute_force = brute_force(2);
+        brute_force.record(failure(1, None, true), at(0));
+        assert!(brute_force.record(failure(2, None, true), at(1)).is_some());
+        assert!(brute_force.record(failure(3, None, true), at(2)).is_none());
+        assert!(brute_force.record(failure(4, None, true), at(3)).is_none());
+
+        brute_force.record(failure(5, None, true), at(12));
+        assert!(brute_force.record(failure(6, None, true), at(13)).is_some());
+    }
+
+    #[test]
+    fn ignores_failures_spread_beyond_the_window() {
+        // e.g. read at once after a restart, but logged over an hour
+        let mut brute_force = brute_force(3);
+        for mins in [0, 6, 12, 18, 24, 30] {
+            assert!(
+                brute_force
+                    .record(failure(1, None, true), at(mins))
+                    .is_none()
+            );
+        }
+    }
+
+    #[test]
+    fn counts_each_ip_on_its_own() {
+        let mut brute_force = brute_force(3);
+        let other = |port| Failure {
+            ip: "2001:db8::1".parse().unwrap(),
+            ..failure(port, None, true)
+        };
+        for port in 1..=2 {
+            assert!(
+                brute_force
+                    .record(failure(port, None, true), at(0))
+                    .is_none()
+            );
+            assert!(brute_force.record(other(port), at(0)).is_none());
+        }
+        let detection = brute_force.record(other(3), at(1)).unwrap();
+        assert_eq!(detection.ip, other(3).ip);
+    }
+
+    #[test]
+    fn tolerates_entries_out_of_order() {
+        let mut brute_force = brute_force(2);
+        brute_force.record(failure(1, None, true), at(5));
+        assert!(brute_force.record(failure(2, None, true), at(4)).is_some());
+    }
+}
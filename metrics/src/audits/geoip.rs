@@ -0,0 +1,181 @@
+use std::collections::{BTreeMap, HashSet};
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+use maxminddb::{MaxMindDBError, Reader, geoip2};
+use serde::Deserialize;
+
+/// Location of the IPs of SSH alerts, looked up offline in `MaxMind`
+/// (`.mmdb`) databases, e.g. `GeoLite2`.
+///
+/// ```toml
+/// [plugins.nexus-metrics.settings.ssh_alerts.geoip]
+/// database = "/var/lib/GeoIP/GeoLite2-City.mmdb"
+/// asn_database = "/var/lib/GeoIP/GeoLite2-ASN.mmdb"
+/// allowed_countries = ["FR", "BE"]
+/// ```
+///
+/// The databases need a read permission.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GeoIpConfig {
+    /// City or country database
+    pub database: Option<PathBuf>,
+    pub asn_database: Option<PathBuf>,
+    /// ISO codes of the countries logins are expected from, any if empty.
+    /// Addresses without a country, e.g. private ones, are never flagged.
+    /// Needs a `database`, as the ASN one has no countries.
+    pub allowed_countries: HashSet<String>,
+}
+
+impl GeoIpConfig {
+    pub const fn enabled(&self) -> bool {
+        self.database.is_some() || self.asn_database.is_some()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        // Or every login would go unflagged
+        if !self.allowed_countries.is_empty() && self.database.is_none() {
+            return Err(
+                "`geoip.allowed_countries` needs a city or country `database`"
+                    .into(),
+            );
+        }
+        let invalid = self.allowed_countries.iter().find(|code| {
+            code.len() != 2 || !code.bytes().all(|b| b.is_ascii_alphabetic())
+        });
+        invalid.map_or(Ok(()), |code| {
+            Err(format!("invalid country code `{code}`"))
+        })
+    }
+}
+
+/// Where an IP is, as far as the databases know.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// ISO code, e.g. `FR`
+    pub country: Option<String>,
+    pub country_name: Option<String>,
+    pub city: Option<String>,
+    pub asn: Option<u32>,
+    /// Owner of the autonomous system
+    pub organization: Option<String>,
+}
+
+impl Location {
+    /// e.g. `Paris, France (FR)`
+    pub fn place(&self) -> Option<String> {
+        let country = match (&self.country_name, &self.country) {
+            (Some(name), Some(code)) => Some(format!("{name} ({code})")),
+            (name, code) => name.clone().or_else(|| code.clone()),
+        };
+        match (&self.city, country) {
+            (Some(city), Some(country)) => Some(format!("{city}, {country}")),
+            (city, country) => country.or_else(|| city.clone()),
+        }
+    }
+
+    /// e.g. `AS3215 Orange`
+    pub fn network(&self) -> Option<String> {
+        match (self.asn, &self.organization) {
+            (Some(asn), Some(organization)) => {
+                Some(format!("AS{asn} {organization}"))
+            }
+            (Some(asn), None) => Some(format!("AS{asn}")),
+            (None, organization) => organization.clone(),
+        }
+    }
+}
+
+/// Databases loaded in memory, along with the allowed countries.
+pub struct GeoIp {
+    city: Option<Reader<Vec<u8>>>,
+    asn: Option<Reader<Vec<u8>>>,
+    allowed_countries: HashSet<String>,
+}
+
+impl GeoIp {
+    /// From the contents of the databases
+    ///
+    /// # Errors
+    /// If either isn't a `.mmdb` database.
+    pub fn new(
+        config: &GeoIpConfig,
+        city: Option<Vec<u8>>,
+        asn: Option<Vec<u8>>,
+    ) -> Result<Self, MaxMindDBError> {
+        Ok(Self {
+            city: city.map(Reader::from_source).transpose()?,
+            asn: asn.map(Reader::from_source).transpose()?,
+            allowed_countries: config
+                .allowed_countries
+                .iter()
+                .map(|code| code.to_ascii_uppercase())
+                .collect(),
+        })
+    }
+
+    /// Everything known of `ip`, nothing if it's not in the databases
+    pub fn lookup(&self, ip: IpAddr) -> Location {
+        let mut location = Location::default();
+        let english = |names: Option<BTreeMap<&str, &str>>| {
+            names?.get("en").map(|name| (*name).to_string())
+        };
+        if let Some(city) = &self.city
+            && let Ok(found) = city.lookup::<geoip2::City<'_>>(ip)
+        {
+            if let Some(country) = found.country {
+                location.country = country.iso_code.map(str::to_string);
+                location.country_name = english(country.names);
+            }
+            location.city = found.city.and_then(|city| english(city.names));
+        }
+        // Also in some city databases
+        let asn = self.asn.as_ref().or(self.city.as_ref());
+        if let Some(asn) = asn
+            && let Ok(found) = asn.lookup::<geoip2::Asn<'_>>(ip)
+        {
+            location.asn = found.autonomous_system_number;
+            location.organization =
+                found.autonomous_system_organization.map(str::to_string);
+        }
+        location
+    }
+
+    /// Whether `location` is in a country logins aren't expected from
+    pub fn is_flagged(&self, location: &Location) -> bool {
+        !self.allowed_countries.is_empty()
+            && location
+                .country
+                .as_ref()
+                .is_some_and(|code| !self.allowed_countries.contains(code))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(database: Option<&str>, countries: &[&str]) -> GeoIpConfig {
+        GeoIpConfig {
+            database: database.map(PathBuf::from),
+            asn_database: Some("GeoLite2-ASN.mmdb".into()),
+            allowed_countries: countries.iter().map(|c| (*c).into()).collect(),
+        }
+    }
+
+    #[test]
+    fn validates_allowed_countries() {
+        let city = Some("GeoLite2-City.mmdb");
+        assert_eq!(config(city, &["FR", "be"]).validate(), Ok(()));
+        assert_eq!(config(None, &[]).validate(), Ok(()));
+        assert!(config(city, &["FRA"]).validate().is_err());
+        assert!(config(city, &["F1"]).validate().is_err());
+    }
+
+    #[test]
+    fn rejects_allowed_countries_without_a_country_database() {
+        let error = config(None, &["FR"]).validate().unwrap_err();
+        assert!(error.contains("database"), "{error}");
+    }
+}
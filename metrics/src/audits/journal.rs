@@ -0,0 +1,405 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use nexus_api::ProcessCommand;
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::{LogEntry, Record};
+
+/// systemd journal, read instead of a syslog file.
+///
+/// ```toml
+/// [plugins.nexus-metrics.settings.ssh_alerts]
+/// source = "journal"
+///
+/// [plugins.nexus-metrics.settings.ssh_alerts.journal]
+/// units = ["sshd.service"]
+/// ```
+///
+/// Unless `path` is set, entries are read from `journalctl`, which needs an
+/// exec permission. Those of the units, or of `ssh_alerts.identifiers`
+/// matched on `SYSLOG_IDENTIFIER`, are read.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JournalConfig {
+    pub format: JournalFormat,
+    /// File of entries to follow, e.g. `journalctl -f -o json` redirected
+    pub path: Option<PathBuf>,
+    /// Matched on `_SYSTEMD_UNIT`
+    pub units: Vec<String>,
+}
+
+impl Default for JournalConfig {
+    fn default() -> Self {
+        Self {
+            format: JournalFormat::Json,
+            path: None,
+            // `ssh.service` on Debian and its derivatives
+            units: vec!["sshd.service".into(), "ssh.service".into()],
+        }
+    }
+}
+
+impl JournalConfig {
+    /// `journalctl` following the matching entries, after `cursor` if it's
+    /// resuming, else from now on
+    pub fn command(
+        &self,
+        identifiers: &[String],
+        cursor: Option<&str>,
+    ) -> ProcessCommand {
+        let output = match self.format {
+            JournalFormat::Json => "--output=json",
+            JournalFormat::Export => "--output=export",
+        };
+        let command = ProcessCommand::new("journalctl").args([
+            "--follow",
+            "--no-pager",
+            "--quiet",
+            output,
+        ]);
+        let command = match cursor {
+            Some(cursor) => command.arg(format!("--after-cursor={cursor}")),
+            None => command.arg("--lines=0"),
+        };
+        // Matches separated by `+` are alternatives
+        let units = self
+            .units
+            .iter()
+            .map(|unit| format!("_SYSTEMD_UNIT={unit}"));
+        let identifiers = identifiers
+            .iter()
+            .map(|identifier| format!("SYSLOG_IDENTIFIER={identifier}"));
+        let mut args = Vec::new();
+        for field in units.chain(identifiers) {
+            if !args.is_empty() {
+                args.push("+".to_string());
+            }
+            args.push(field);
+        }
+        command.args(args)
+    }
+}
+
+/// Output format of `journalctl`
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalFormat {
+    /// `-o json`, an entry per line
+    Json,
+    /// `-o export`, a `FIELD=value` per line, entries separated by an
+    /// empty line
+    Export,
+}
+
+/// Assembles journal entries from the lines they're read as.
+#[derive(Debug)]
+pub struct JournalReader {
+    format: JournalFormat,
+    units: Vec<String>,
+    identifiers: Vec<String>,
+    /// Fields of the export entry being read
+    fields: HashMap<String, String>,
+    /// Binary field of the export entry being read, if any: its name, and
+    /// what was read of its size and value
+    binary: Option<(String, Vec<u8>)>,
+}
+
+impl JournalReader {
+    pub fn new(config: &JournalConfig, identifiers: &[String]) -> Self {
+        Self {
+            format: config.format,
+            units: config.units.clone(),
+            identifiers: identifiers.to_vec(),
+            fields: HashMap::new(),
+            binary: None,
+        }
+    }
+
+    /// The entry `line`, as read without its `\n`, completes, if any
+    pub fn push(&mut self, line: &[u8]) -> Option<Record> {
+        match self.format {
+            JournalFormat::Json => {
+                let fields = json_fields(line)?;
+                Some(self.record(fields))
+            }
+            JournalFormat::Export => self.push_export(line),
+        }
+    }
+
+    fn push_export(&mut self, line: &[u8]) -> Option<Record> {
+        if let Some((name, mut read)) = self.binary.take() {
+            read.extend_from_slice(line);
+            if let Some(value) = binary_value(&read) {
+                let value = String::from_utf8_lossy(value).into_owned();
+                self.fields.insert(name, value);
+            } else {
+                // The line break is part of the value
+                read.push(b'\n');
+                self.binary = Some((name, read));
+            }
+            return None;
+        }
+        if line.is_empty() {
+            if self.fields.is_empty() {
+                return None;
+            }
+            let fields = std::mem::take(&mut self.fields);
+            return Some(self.record(fields));
+        }
+        let line = String::from_utf8_lossy(line);
+        match line.split_once('=') {
+            Some((name, value)) if is_field_name(name) => {
+                self.fields.insert(name.into(), value.into());
+            }
+            // Binary field, e.g. a message with control characters: its
+            // name alone, then its size and value on the following lines
+            None if is_field_name(&line) => {
+                self.binary = Some((line.into_owned(), Vec::new()));
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn matches(&self, fields: &HashMap<String, String>) -> bool {
+        let field = |name: &str| fields.get(name).map(String::as_str);
+        field("_SYSTEMD_UNIT")
+            .is_some_and(|unit| self.units.iter().any(|u| u == unit))
+            || field("SYSLOG_IDENTIFIER").is_some_and(|identifier| {
+                self.identifiers.iter().any(|i| i == identifier)
+            })
+    }
+
+    fn record(&self, mut fields: HashMap<String, String>) -> Record {
+        let entry = self.matches(&fields).then(|| {
+            let time = fields
+                .get("__REALTIME_TIMESTAMP")
+                .and_then(|micros| micros.parse().ok())
+                .map_or_else(SystemTime::now, |micros| {
+                    SystemTime::UNIX_EPOCH + Duration::from_micros(micros)
+                });
+            let pid = fields
+                .get("_PID")
+                .or_else(|| fields.get("SYSLOG_PID"))
+                .and_then(|pid| pid.parse().ok());
+            LogEntry {
+                time: humantime::format_rfc3339_seconds(time).to_string(),
+                at: time,
+                host: fields.remove("_HOSTNAME"),
+                pid,
+                message: fields.remove("MESSAGE").unwrap_or_default(),
+            }
+        });
+        Record {
+            entry,
+            cursor: fields.remove("__CURSOR"),
+        }
+    }
+}
+
+/// Value of a binary field of the export format, once `read` holds all of
+/// it: its size as a little-endian 64-bit integer, then as many bytes
+fn binary_value(read: &[u8]) -> Option<&[u8]> {
+    let (size, value) = read.split_first_chunk::<8>()?;
+    let size = usize::try_from(u64::from_le_bytes(*size)).ok()?;
+    value.get(..size)
+}
+
+/// Fields of a `-o json` entry, as strings
+fn json_fields(line: &[u8]) -> Option<HashMap<String, String>> {
+    let Ok(Value::Object(entry)) = serde_json::from_slice(line) else {
+        return None;
+    };
+    let fields = entry.into_iter().filter_map(|(name, value)| {
+        let value = match value {
+            Value::String(value) => value,
+            // Values that aren't valid UTF-8 are arrays of bytes
+            Value::Array(bytes) => {
+                let bytes = bytes
+                    .iter()
+                    .map(|b| b.as_u64().and_then(|b| u8::try_from(b).ok()))
+                    .collect::<Option<Vec<_>>>()?;
+                String::from_utf8_lossy(&bytes).into_owned()
+            }
+            _ => return None,
+        };
+        Some((name, value))
+    });
+    Some(fields.collect())
+}
+
+/// Uppercase letters, digits and underscores, as the journal requires
+fn is_field_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_uppercase() || b.is_ascii_digit() || b == b'_')
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::*;
+
+    const ACCEPTED: &str = "Accepted publickey for deploy from 198.51.100.7 \
+        port 50022 ssh2: ED25519 \
+        SHA256:Yk2DSaCv1+Q0WnEJ4SXjwJvLDDprFgN2ZDv9fTALd4Q";
+    const FAILED: &str = "Failed password for invalid user \u{fffd}admin \
+        from 203.0.113.9 port 4711 ssh2\n\nMESSAGE=forged";
+    const DISCONNECTED: &str = "Received disconnect from 198.51.100.7 port \
+        50022:11: disconnected by user";
+
+    fn config(format: JournalFormat, units: &[&str]) -> JournalConfig {
+        JournalConfig {
+            format,
+            units: units.iter().map(ToString::to_string).collect(),
+            ..JournalConfig::default()
+        }
+    }
+
+    /// Records of `fixtures/journal/<file>`, pushed line by line
+    fn records(
+        file: &str,
+        config: &JournalConfig,
+        identifiers: &[&str],
+    ) -> Vec<Record> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures/journal")
+            .join(file);
+        let read = fs::read(path).unwrap();
+        let identifiers: Vec<_> =
+            identifiers.iter().map(ToString::to_string).collect();
+        let mut reader = JournalReader::new(config, &identifiers);
+        read.strip_suffix(b"\n")
+            .unwrap()
+            .split(|&b| b == b'\n')
+            .filter_map(|line| reader.push(line))
+            .collect()
+    }
+
+    /// Cursor of each record, with the message of its entry if it matched
+    fn messages(records: &[Record]) -> Vec<(&str, Option<&str>)> {
+        records
+            .iter()
+            .map(|record| {
+                let entry = record.entry.as_ref();
+                (
+                    record.cursor.as_deref().unwrap(),
+                    entry.map(|entry| entry.message.as_str()),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reads_both_formats() {
+        for (file, format) in [
+            ("sshd.json", JournalFormat::Json),
+            ("sshd.export", JournalFormat::Export),
+        ] {
+            let config = config(format, &["sshd.service", "ssh.service"]);
+            let records = records(file, &config, &["sshd", "sshd-session"]);
+            assert_eq!(
+                messages(&records),
+                [
+                    ("s=1;i=1", Some(ACCEPTED)),
+                    ("s=1;i=2", None),
+                    ("s=1;i=3", Some(FAILED)),
+                    ("s=1;i=4", Some(DISCONNECTED)),
+                    ("s=1;i=5", None),
+                ],
+                "{file}"
+            );
+
+            let accepted = records[0].entry.as_ref().unwrap();
+            assert_eq!(accepted.time, "2026-01-01T00:00:00Z", "{file}");
+            assert_eq!(
+                accepted.at,
+                humantime::parse_rfc3339("2026-01-01T00:00:00Z").unwrap(),
+                "{file}"
+            );
+            assert_eq!(accepted.host.as_deref(), Some("bastion"), "{file}");
+            assert_eq!(accepted.pid, Some(1201), "{file}");
+
+            // Read after the binary fields
+            let failed = records[2].entry.as_ref().unwrap();
+            assert_eq!(failed.host.as_deref(), Some("bastion"), "{file}");
+            assert_eq!(failed.pid, Some(1300), "{file}");
+        }
+    }
+
+    #[test]
+    fn matches_units_or_identifiers() {
+        for (file, format) in [
+            ("sshd.json", JournalFormat::Json),
+            ("sshd.export", JournalFormat::Export),
+        ] {
+            let units = config(format, &["sshd.service"]);
+            let units = records(file, &units, &[]);
+            assert_eq!(
+                messages(&units),
+                [
+                    ("s=1;i=1", Some(ACCEPTED)),
+                    ("s=1;i=2", None),
+                    ("s=1;i=3", None),
+                    ("s=1;i=4", None),
+                    ("s=1;i=5", None),
+                ],
+                "{file}"
+            );
+
+            let identifiers = config(format, &[]);
+            let identifiers = records(file, &identifiers, &["sshd-session"]);
+            assert_eq!(
+                messages(&identifiers),
+                [
+                    ("s=1;i=1", None),
+                    ("s=1;i=2", None),
+                    ("s=1;i=3", Some(FAILED)),
+                    ("s=1;i=4", None),
+                    ("s=1;i=5", None),
+                ],
+                "{file}"
+            );
+        }
+    }
+
+    #[test]
+    fn waits_for_the_whole_binary_value() {
+        let mut read = 5u64.to_le_bytes().to_vec();
+        read.extend_from_slice(b"ab\n");
+        assert_eq!(binary_value(&read), None);
+        read.extend_from_slice(b"cd");
+        assert_eq!(binary_value(&read), Some(&b"ab\ncd"[..]));
+        assert_eq!(binary_value(&read[..7]), None);
+    }
+
+    #[test]
+    fn follows_matching_entries() {
+        let config = config(JournalFormat::Export, &["sshd.service"]);
+        let identifiers = ["sshd".to_string()];
+        let command = config.command(&identifiers, Some("s=1;i=4"));
+        assert_eq!(command.program, "journalctl");
+        assert_eq!(
+            command.args,
+            [
+                "--follow",
+                "--no-pager",
+                "--quiet",
+                "--output=export",
+                "--after-cursor=s=1;i=4",
+                "_SYSTEMD_UNIT=sshd.service",
+                "+",
+                "SYSLOG_IDENTIFIER=sshd",
+            ]
+        );
+
+        let command = config.command(&identifiers, None);
+        assert_eq!(command.args[4], "--lines=0");
+    }
+}
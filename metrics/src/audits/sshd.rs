@@ -0,0 +1,423 @@
+//! Grammar of the SSH daemon's log, as written by syslog daemons of
+//! various distributions. Samples of each are in `fixtures/sshd`.
+
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime};
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct",
+    "Nov", "Dec",
+];
+
+/// Line of a syslog file, e.g. `/var/log/auth.log`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyslogLine<'a> {
+    /// As written, e.g. `Jan 5 10:00:00` or `2024-01-05T10:00:00+00:00`
+    pub time: String,
+    pub host: &'a str,
+    /// e.g. `sshd`
+    pub program: &'a str,
+    pub pid: Option<u32>,
+    pub message: &'a str,
+}
+
+impl<'a> SyslogLine<'a> {
+    /// Parses `[<PRI>]TIMESTAMP HOST [FACILITY.LEVEL] PROGRAM[PID]: MESSAGE`,
+    /// where the timestamp is either `Mmm dd hh:mm:ss` (RFC 3164) or
+    /// RFC 3339, or RFC 5424's
+    /// `<PRI>1 TIMESTAMP HOST PROGRAM PID MSGID STRUCTURED-DATA MESSAGE`.
+    pub fn parse(line: &'a str) -> Option<Self> {
+        let mut words = Words(line.trim_end());
+        // Only in lines forwarded as they were sent
+        if let Some(rest) = words.0.strip_prefix('<') {
+            let (_priority, rest) = rest.split_once('>')?;
+            if let Some(rest) = rest.strip_prefix("1 ") {
+                return Self::rfc5424(Words(rest));
+            }
+            words = Words(rest);
+        }
+
+        let first = words.next()?;
+        let time = if is_rfc3339(first) {
+            first.to_string()
+        } else {
+            let (day, time) = (words.next()?, words.next()?);
+            let month = first.len() == 3
+                && first.bytes().all(|b| b.is_ascii_alphabetic());
+            if !month || day.parse::<u8>().is_err() || !is_clock(time) {
+                return None;
+            }
+            // Without the padding of single digit days
+            format!("{first} {day} {time}")
+        };
+        let host = words.next()?;
+        let mut tag = words.next()?;
+        // BusyBox's syslogd adds e.g. `auth.info`
+        if !tag.ends_with(':') && is_facility_level(tag) {
+            tag = words.next()?;
+        }
+        let tag = tag.strip_suffix(':')?;
+        let (program, pid) = match tag.split_once('[') {
+            Some((program, pid)) => {
+                (program, Some(pid.strip_suffix(']')?.parse().ok()?))
+            }
+            None => (tag, None),
+        };
+        Some(Self {
+            time,
+            host,
+            program,
+            pid,
+            message: words.rest(),
+        })
+    }
+
+    /// When the line was logged, `now` if its time can't be read.
+    /// RFC 3164 timestamps have neither a year nor a zone: they're read as
+    /// UTC, in the year up to `now`, which keeps the time between lines
+    /// right.
+    pub fn logged_at(&self, now: SystemTime) -> SystemTime {
+        let at = if is_rfc3339(&self.time) {
+            rfc3339(&self.time)
+        } else {
+            rfc3164(&self.time, now)
+        };
+        at.unwrap_or(now)
+    }
+
+    fn rfc5424(mut words: Words<'a>) -> Option<Self> {
+        let time = words.next()?.to_string();
+        let host = words.next()?;
+        let program = words.next()?;
+        let pid = words.next()?.parse().ok();
+        let _message_id = words.next()?;
+        // `-`, or `[id key="value" ...]` elements, which may hold spaces
+        let rest = words.rest();
+        let message = if let Some(rest) = rest.strip_prefix('-') {
+            rest
+        } else {
+            let mut rest = rest;
+            while let Some(element) = rest.strip_prefix('[') {
+                let end = element.find(']')?;
+                rest = &element[end + 1..];
+            }
+            rest
+        };
+        Some(Self {
+            time,
+            host,
+            program,
+            pid,
+            message: message.trim_start().trim_start_matches('\u{feff}'),
+        })
+    }
+}
+
+/// Remote end of a connection, from `ADDRESS port PORT`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Address {
+    pub ip: IpAddr,
+    pub port: Option<u16>,
+}
+
+impl Address {
+    /// Parses the address at the start of `rest`, returning what follows
+    pub fn parse(rest: &str) -> Option<(Self, &str)> {
+        let mut words = Words(rest);
+        let ip = words.next()?;
+        // Link-local addresses may come with their interface
+        let ip = ip.split_once('%').map_or(ip, |(ip, _zone)| ip);
+        let ip = ip.parse().ok()?;
+        let mut after = words;
+        let port = match (after.next(), after.next()) {
+            (Some("port"), Some(port)) => {
+                // `port 22:11:` in `Received disconnect`
+                let port = port.split(':').next()?;
+                words = after;
+                Some(port.parse().ok()?)
+            }
+            _ => None,
+        };
+        Some((Self { ip, port }, words.rest()))
+    }
+}
+
+/// `Accepted METHOD for USER from ADDRESS port PORT PROTOCOL[: KEY]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Accepted<'a> {
+    /// e.g. `publickey` or `keyboard-interactive/pam`
+    pub method: &'a str,
+    pub user: &'a str,
+    pub address: Address,
+    /// e.g. `ED25519`, with public keys
+    pub key_type: Option<&'a str>,
+    /// e.g. `SHA256:...`, with public keys
+    pub fingerprint: Option<&'a str>,
+}
+
+impl<'a> Accepted<'a> {
+    pub fn parse(message: &'a str) -> Option<Self> {
+        let rest = message.strip_prefix("Accepted ")?;
+        let (method, rest) = rest.split_once(" for ")?;
+        // Users may hold spaces, addresses can't
+        let (user, rest) = rest.rsplit_once(" from ")?;
+        let (address, rest) = Address::parse(rest)?;
+
+        // `ssh2: RSA SHA256:...`, or with certificates
+        // `ssh2: ED25519-CERT SHA256:... ID name (serial 1) CA ...`
+        let key = rest.split_once(": ").map(|(_protocol, key)| Words(key));
+        let (key_type, fingerprint) = key.map_or((None, None), |mut key| {
+            let key_type = key.next();
+            let fingerprint = key.next().filter(|f| f.contains(':'));
+            (key_type, fingerprint)
+        });
+        Some(Self {
+            method,
+            user,
+            address,
+            key_type,
+            fingerprint,
+        })
+    }
+}
+
+/// Splits whitespace separated words off the front.
+#[derive(Debug, Clone, Copy)]
+struct Words<'a>(&'a str);
+
+impl<'a> Words<'a> {
+    fn next(&mut self) -> Option<&'a str> {
+        let rest = self.0.trim_start();
+        if rest.is_empty() {
+            return None;
+        }
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let (word, rest) = rest.split_at(end);
+        self.0 = rest;
+        Some(word)
+    }
+
+    /// Whatever is left, without its leading whitespace
+    fn rest(self) -> &'a str {
+        self.0.trim_start()
+    }
+}
+
+/// From e.g. `2024-01-05T10:00:00.123456+01:00`, to the second
+fn rfc3339(time: &str) -> Option<SystemTime> {
+    let (local, zone) = time.split_at_checked(19)?;
+    let at = humantime::parse_rfc3339_weak(local).ok()?;
+    let zone =
+        zone.trim_start_matches(|c: char| c == '.' || c.is_ascii_digit());
+    if zone.eq_ignore_ascii_case("z") {
+        return Some(at);
+    }
+    let (sign, offset) = zone.split_at_checked(1)?;
+    let (hours, minutes) = offset.split_once(':')?;
+    let offset =
+        hours.parse::<u64>().ok()? * 3600 + minutes.parse::<u64>().ok()? * 60;
+    let offset = Duration::from_secs(offset);
+    match sign {
+        "+" => at.checked_sub(offset),
+        "-" => at.checked_add(offset),
+        _ => None,
+    }
+}
+
+/// From e.g. `Jan 5 10:00:00`, in UTC and the year up to `now`
+fn rfc3164(time: &str, now: SystemTime) -> Option<SystemTime> {
+    let mut words = Words(time);
+    let (month, day, clock) = (words.next()?, words.next()?, words.next()?);
+    let month = MONTHS.iter().position(|m| *m == month)? + 1;
+    let day = day.parse::<u8>().ok()?;
+    let at = |year: u32| {
+        let time = format!("{year}-{month:02}-{day:02}T{clock}Z");
+        humantime::parse_rfc3339(&time).ok()
+    };
+    let year = humantime::format_rfc3339_seconds(now).to_string();
+    let year = year.get(..4)?.parse().ok()?;
+    // Logged in December and read in January, allowing for time zones
+    match at(year) {
+        Some(at) if at <= now + Duration::from_hours(24) => Some(at),
+        _ => at(year - 1),
+    }
+}
+
+/// e.g. `2024-01-05T10:00:00.123456+01:00`
+fn is_rfc3339(word: &str) -> bool {
+    let bytes = word.as_bytes();
+    bytes.len() >= 20
+        && bytes[..4].iter().all(u8::is_ascii_digit)
+        && bytes[4] == b'-'
+        && matches!(bytes[10], b'T' | b't')
+        && word.get(11..19).is_some_and(is_clock)
+}
+
+/// `hh:mm:ss`
+fn is_clock(word: &str) -> bool {
+    let bytes = word.as_bytes();
+    bytes.len() == 8
+        && bytes[2] == b':'
+        && bytes[5] == b':'
+        && [0, 1, 3, 4, 6, 7]
+            .iter()
+            .all(|&i| bytes[i].is_ascii_digit())
+}
+
+/// e.g. `auth.info`
+fn is_facility_level(word: &str) -> bool {
+    word.contains('.')
+        && word.split('.').all(|part| {
+            !part.is_empty() && part.bytes().all(|b| b.is_ascii_alphanumeric())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use serde_json::{Value, json};
+
+    use super::super::LogEntry;
+    use super::super::brute_force::Failure;
+    use super::*;
+
+    /// What a line parses to, as in the `.jsonl` of the samples
+    fn parsed(line: &str) -> Value {
+        let Some(line) = SyslogLine::parse(line) else {
+            return Value::Null;
+        };
+        let login = Accepted::parse(line.message).map(|login| {
+            json!({
+                "method": login.method,
+                "user": login.user,
+                "ip": login.address.ip,
+                "port": login.address.port,
+                "key_type": login.key_type,
+                "fingerprint": login.fingerprint,
+            })
+        });
+        let entry = LogEntry {
+            time: line.time.clone(),
+            at: SystemTime::UNIX_EPOCH,
+            host: Some(line.host.into()),
+            pid: line.pid,
+            message: line.message.into(),
+        };
+        let failure = Failure::parse(&entry).map(|failure| {
+            json!({
+                "user": failure.user,
+                "ip": failure.ip,
+                "port": failure.port,
+                "attempt": failure.attempt,
+            })
+        });
+        json!({
+            "time": line.time,
+            "host": line.host,
+            "program": line.program,
+            "pid": line.pid,
+            "login": login,
+            "failure": failure,
+        })
+    }
+
+    #[test]
+    fn parses_the_samples() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/sshd");
+        let mut samples = 0;
+        for file in fs::read_dir(&dir).unwrap() {
+            let path = file.unwrap().path();
+            if path.extension().is_none_or(|e| e != "log") {
+                continue;
+            }
+            let log = fs::read_to_string(&path).unwrap();
+            let expected =
+                fs::read_to_string(path.with_extension("jsonl")).unwrap();
+            assert_eq!(
+                log.lines().count(),
+                expected.lines().count(),
+                "{}",
+                path.display()
+            );
+            for (i, (line, expected)) in
+                log.lines().zip(expected.lines()).enumerate()
+            {
+                let expected: Value = serde_json::from_str(expected).unwrap();
+                assert_eq!(
+                    parsed(line),
+                    expected,
+                    "{}:{}",
+                    path.display(),
+                    i + 1
+                );
+            }
+            samples += 1;
+        }
+        assert_eq!(samples, 6);
+    }
+
+    fn rfc3339(time: &str) -> SystemTime {
+        humantime::parse_rfc3339(time).unwrap()
+    }
+
+    fn logged_at(line: &str, now: &str) -> SystemTime {
+        SyslogLine::parse(line).unwrap().logged_at(rfc3339(now))
+    }
+
+    #[test]
+    fn reads_rfc3339_times_with_their_zone() {
+        let message = "host sshd[1]: Accepted";
+        let now = "2026-06-01T00:00:00Z";
+        assert_eq!(
+            logged_at(
+                &format!("2024-01-05T10:00:00.123456+01:00 {message}"),
+                now
+            ),
+            rfc3339("2024-01-05T09:00:00Z")
+        );
+        assert_eq!(
+            logged_at(&format!("2024-01-05T10:00:00-05:30 {message}"), now),
+            rfc3339("2024-01-05T15:30:00Z")
+        );
+        assert_eq!(
+            logged_at(&format!("2024-01-05T10:00:00Z {message}"), now),
+            rfc3339("2024-01-05T10:00:00Z")
+        );
+        assert_eq!(
+            logged_at(
+                "<38>1 2024-01-05T10:00:00.5Z host sshd 1 - - Accepted",
+                now
+            ),
+            rfc3339("2024-01-05T10:00:00Z")
+        );
+    }
+
+    #[test]
+    fn reads_rfc3164_times_in_the_current_year() {
+        assert_eq!(
+            logged_at(
+                "Jan  5 10:00:00 host sshd[1]: Accepted",
+                "2026-06-01T00:00:00Z"
+            ),
+            rfc3339("2026-01-05T10:00:00Z")
+        );
+        // Logged before the new year
+        assert_eq!(
+            logged_at(
+                "Dec 31 23:59:59 host sshd[1]: Accepted",
+                "2026-01-01T00:00:10Z"
+            ),
+            rfc3339("2025-12-31T23:59:59Z")
+        );
+    }
+
+    #[test]
+    fn falls_back_to_now() {
+        let now = rfc3339("2026-06-01T00:00:00Z");
+        let line =
+            SyslogLine::parse("<38>1 - host sshd 1 - - Accepted").unwrap();
+        assert_eq!(line.logged_at(now), now);
+    }
+}
@@ -1,52 +1,325 @@
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
 use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
-use tokio::fs;
 
+use crate::alerts::{AlertRule, Comparison};
+use crate::audits::{BruteForceConfig, GeoIpConfig, JournalConfig, LogSource};
+use crate::docker::DockerConfig;
+use crate::processes::ProcessesConfig;
+
+/// Settings of the plugin, from `[plugins.nexus-metrics.settings]` in the
+/// host's `nexus.toml`. Every field is optional.
 #[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Config {
-    pub webhook_url: String,
+    /// Discord webhook the status embed is posted to, none if unset
+    pub webhook_url: Option<String>,
     pub embed_title: String,
-    pub embed_color: String,
-    pub update_interval: String,
+    /// Hex color of the embed, e.g. `#5865F2`
+    #[serde(deserialize_with = "color")]
+    pub embed_color: u32,
+    /// Time between updates, e.g. `30s` or `5m`
+    #[serde(deserialize_with = "duration")]
+    pub update_interval: Duration,
     pub optional_message: String,
+    /// Mentions added to the status message, e.g. `<@1234>` or `@here`
     pub user_tags: Vec<String>,
     pub show_memory: bool,
+    /// Memory in MiB, rather than the most readable unit
     pub memory_in_mb: bool,
     pub show_cpu: bool,
+    /// Usage of each core
+    pub show_cpu_cores: bool,
+    pub show_load_average: bool,
+    pub show_swap: bool,
+    /// Uptime and boot time
+    pub show_uptime: bool,
+    /// Sensors' temperatures
+    pub show_temperatures: bool,
     pub show_network_usage: bool,
+    /// Interfaces to monitor, all of them if empty
     pub network_interfaces: HashSet<String>,
     pub optional_message_enabled: bool,
     pub user_tags_enabled: bool,
+    /// Edit a single status message in place, instead of sending new ones
     pub update_previous_message: bool,
+    /// Message to edit, until the plugin sends one of its own
     pub message_id: Option<String>,
     pub show_disk_usage: bool,
+    /// Disks to monitor by device, e.g. `/dev/sda1`, all of them if empty
     pub disk_drives: HashSet<String>,
+    /// Names displayed instead of the devices
     pub disk_names: HashMap<String, String>,
     pub ssh_alerts: SshAlertsConfig,
+    /// Replaces the default alert rules
+    pub alerts: Vec<AlertRule>,
+    pub processes: ProcessesConfig,
+    pub docker: DockerConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            webhook_url: None,
+            embed_title: "System metrics".to_string(),
+            embed_color: 0x58_65_F2,
+            update_interval: Duration::from_secs(30),
+            optional_message: String::new(),
+            user_tags: Vec::new(),
+            show_memory: true,
+            memory_in_mb: false,
+            show_cpu: true,
+            show_cpu_cores: false,
+            show_load_average: true,
+            show_swap: true,
+            show_uptime: true,
+            show_temperatures: false,
+            show_network_usage: true,
+            network_interfaces: HashSet::new(),
+            optional_message_enabled: false,
+            user_tags_enabled: false,
+            update_previous_message: false,
+            message_id: None,
+            show_disk_usage: true,
+            disk_drives: HashSet::new(),
+            disk_names: HashMap::new(),
+            ssh_alerts: SshAlertsConfig::default(),
+            alerts: AlertRule::defaults(),
+            processes: ProcessesConfig::default(),
+            docker: DockerConfig::default(),
+        }
+    }
 }
 
+/// Alerts on SSH logins, read from the SSH daemon's log.
 #[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct SshAlertsConfig {
     pub enabled: bool,
-    pub log_path: String,
-    pub ssh_alert_webhook_url: String,
+    /// `syslog`, or `journal` on hosts without `log_path`
+    pub source: LogSource,
+    /// Needs a read permission, e.g. on `/var/log`
+    pub log_path: PathBuf,
+    pub journal: JournalConfig,
+    /// Programs whose messages are read, matched on the syslog tag, e.g.
+    /// `sshd` of `sshd[123]:`
+    pub identifiers: Vec<String>,
+    /// Webhook of the login embeds, the status one's if unset
+    pub ssh_alert_webhook_url: Option<String>,
+    /// Logins of the same user from the same IP within this window are
+    /// only notified once
+    #[serde(deserialize_with = "duration")]
+    pub dedup_window: Duration,
+    pub brute_force: BruteForceConfig,
+    pub geoip: GeoIpConfig,
+}
+
+impl Default for SshAlertsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            source: LogSource::Syslog,
+            log_path: PathBuf::from("/var/log/auth.log"),
+            journal: JournalConfig::default(),
+            // Logins are logged by `sshd-session` since OpenSSH 9.8
+            identifiers: vec!["sshd".into(), "sshd-session".into()],
+            ssh_alert_webhook_url: None,
+            dedup_window: Duration::from_mins(1),
+            brute_force: BruteForceConfig::default(),
+            geoip: GeoIpConfig::default(),
+        }
+    }
+}
+
+impl SshAlertsConfig {
+    /// File that's followed, none if the journal is read from `journalctl`
+    pub fn followed_path(&self) -> Option<&Path> {
+        match self.source {
+            LogSource::Syslog => Some(&self.log_path),
+            LogSource::Journal => self.journal.path.as_deref(),
+        }
+    }
+}
+
+/// Why the settings were rejected.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// Not valid TOML, or not matching [`Config`]
+    Parse(toml::de::Error),
+    /// Well-formed, but unusable
+    Invalid(String),
 }
 
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse(e) => write!(f, "failed to parse settings: {e}"),
+            Self::Invalid(e) => write!(f, "invalid settings: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
 impl Config {
-    pub async fn load(path: &str) -> Self {
-        let config_content = fs::read_to_string(path)
-            .await
-            .expect("Failed to read config.toml");
-        toml::from_str(&config_content).expect("Failed to parse config.toml")
+    /// Parses and validates the plugin's settings, defaults if there's none
+    pub fn load(settings: Option<&str>) -> Result<Self, ConfigError> {
+        let config = match settings {
+            Some(settings) => {
+                toml::from_str::<Self>(settings).map_err(ConfigError::Parse)?
+            }
+            None => Self::default(),
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |e: String| Err(ConfigError::Invalid(e));
+        if self.update_interval.is_zero() {
+            return invalid("`update_interval` must not be zero".into());
+        }
+        let urls = [
+            ("webhook_url", &self.webhook_url),
+            ("ssh_alert_webhook_url", &self.ssh_alerts.ssh_alert_webhook_url),
+        ];
+        for (field, url) in urls {
+            if let Some(url) = url
+                && !url.starts_with("https://")
+                && !url.starts_with("http://")
+            {
+                return invalid(format!("`{field}` isn't a URL: {url}"));
+            }
+        }
+        let ssh = &self.ssh_alerts;
+        if ssh.enabled
+            && ssh
+                .followed_path()
+                .is_some_and(|path| path.as_os_str().is_empty())
+        {
+            return invalid("`ssh_alerts.log_path` must be set".into());
+        }
+        if ssh.enabled
+            && matches!(ssh.source, LogSource::Journal)
+            && ssh.journal.units.is_empty()
+            && ssh.identifiers.is_empty()
+        {
+            return invalid(
+                "`ssh_alerts` needs journal `units` or `identifiers`".into(),
+            );
+        }
+        let brute_force = &ssh.brute_force;
+        if brute_force.enabled && brute_force.max_failures == 0 {
+            return invalid(
+                "`brute_force.max_failures` must not be zero".into(),
+            );
+        }
+        if let Some(ban) = &brute_force.ban {
+            ban.validate().map_err(ConfigError::Invalid)?;
+        }
+        ssh.geoip.validate().map_err(ConfigError::Invalid)?;
+        if let Some(rule) = self.alerts.iter().find(|r| r.hysteresis < 0.0) {
+            return invalid(format!(
+                "alert `{}` has a negative hysteresis",
+                rule.name
+            ));
+        }
+        let mut names = HashSet::new();
+        for watched in &self.processes.watch {
+            if watched.name.is_empty() || watched.name.contains('*') {
+                return invalid(format!(
+                    "invalid watched process name `{}`",
+                    watched.name
+                ));
+            }
+            if !names.insert(&watched.name) {
+                return invalid(format!(
+                    "process `{}` is watched twice",
+                    watched.name
+                ));
+            }
+        }
+        Ok(())
     }
 
-    pub fn get_embed_color(&self) -> Result<u32, std::num::ParseIntError> {
-        u32::from_str_radix(&self.embed_color.trim_start_matches('#'), 16)
+    /// Configured alert rules, along with those of watched processes and
+    /// containers
+    pub fn alert_rules(&self) -> Vec<AlertRule> {
+        let mut rules = self.alerts.clone();
+        if self.docker.enabled {
+            rules.push(AlertRule {
+                name: "Container unhealthy".to_string(),
+                series: "container.*.healthy".to_string(),
+                when: Comparison::Below,
+                threshold: 1.0,
+                hysteresis: 0.0,
+                for_: Duration::ZERO,
+            });
+        }
+        for watched in &self.processes.watch {
+            let name = &watched.name;
+            rules.push(AlertRule {
+                name: format!("{name} is down"),
+                series: format!("process.{name}.up"),
+                when: Comparison::Below,
+                threshold: 1.0,
+                hysteresis: 0.0,
+                for_: Duration::ZERO,
+            });
+            if let Some(limit) = watched.memory_limit_mb {
+                // As a float first, so that huge limits don't overflow
+                #[allow(clippy::cast_precision_loss)]
+                let limit = limit as f64 * 1_024.0 * 1_024.0;
+                rules.push(AlertRule {
+                    name: format!("{name} uses too much memory"),
+                    series: format!("process.{name}.memory"),
+                    when: Comparison::Above,
+                    threshold: limit,
+                    hysteresis: limit / 20.0,
+                    for_: Duration::ZERO,
+                });
+            }
+        }
+        rules
     }
 
-    pub fn update_interval(&self) -> Duration {
-        humantime::parse_duration(&self.update_interval)
-            .expect("Failed to parse update interval from configuration")
+    /// Text sent along the status embed, if any
+    pub fn message_content(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if self.optional_message_enabled && !self.optional_message.is_empty()
+        {
+            parts.push(self.optional_message.as_str());
+        }
+        if self.user_tags_enabled {
+            parts.extend(self.user_tags.iter().map(String::as_str));
+        }
+        (!parts.is_empty()).then(|| parts.join(" "))
     }
+
+    /// Name a disk is displayed under
+    pub fn disk_name<'a>(&'a self, device: &'a str) -> &'a str {
+        self.disk_names.get(device).map_or(device, String::as_str)
+    }
+}
+
+/// Human-readable duration, e.g. `1h 30m`
+pub fn duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Duration, D::Error> {
+    let duration = String::deserialize(deserializer)?;
+    humantime::parse_duration(&duration).map_err(serde::de::Error::custom)
+}
+
+/// Hex color, with or without a leading `#`
+fn color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let color = String::deserialize(deserializer)?;
+    u32::from_str_radix(color.trim_start_matches('#'), 16)
+        .ok()
+        .filter(|color| *color <= 0xFF_FF_FF)
+        .ok_or_else(|| {
+            serde::de::Error::custom(format!("invalid color `{color}`"))
+        })
 }
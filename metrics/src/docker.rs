@@ -0,0 +1,635 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use nexus_api::{HttpError, HttpRequest, RuntimeRef};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use tracing::{info, warn};
+
+use crate::alerts::{AlertEvent, AlertState};
+use crate::sys_info::{MemoryMetrics, NetworkMetrics};
+
+/// Containers of the Docker Engine, read from its API socket. Disabled by
+/// default.
+///
+/// ```toml
+/// [plugins.nexus-metrics.settings.docker]
+/// enabled = true
+/// containers = ["web", "db"]
+/// ```
+///
+/// The socket needs a permission, e.g.
+/// `sockets = ["/var/run/docker.sock"]`.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DockerConfig {
+    pub enabled: bool,
+    pub socket: PathBuf,
+    /// Names of the containers to monitor, all of them if empty
+    pub containers: HashSet<String>,
+}
+
+impl Default for DockerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            socket: PathBuf::from("/var/run/docker.sock"),
+            containers: HashSet::new(),
+        }
+    }
+}
+
+/// How long a request to the Engine may take
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// State of a container, with its usage if it's running.
+pub struct ContainerMetrics {
+    pub image: String,
+    /// e.g. `running` or `exited`
+    pub state: String,
+    /// `healthy`, `unhealthy` or `starting`, if it has a health check
+    pub health: Option<String>,
+    pub restart_count: u64,
+    /// Percent, of a single core
+    pub cpu: Option<f64>,
+    pub memory: Option<MemoryMetrics>,
+    pub network: Option<NetworkMetrics>,
+    /// Exit code, if it stopped since the previous collection
+    pub exited: Option<i64>,
+    /// Whether it started again since the previous collection
+    pub restarted: bool,
+}
+
+impl ContainerMetrics {
+    pub fn is_running(&self) -> bool {
+        self.state == "running"
+    }
+
+    /// One-off alerts on the container exiting or restarting since the
+    /// previous collection
+    pub fn events(&self, name: &str) -> Vec<AlertEvent> {
+        let event = |rule: String, series: &str, value| AlertEvent {
+            rule,
+            series: format!("container.{name}.{series}"),
+            value,
+            threshold: None,
+            state: AlertState::Triggered,
+        };
+        let mut events = Vec::new();
+        if let Some(code) = self.exited {
+            let rule = format!("Container {name} exited with {code}");
+            #[allow(clippy::cast_precision_loss)]
+            events.push(event(rule, "exit_code", code as f64));
+        }
+        if self.restarted {
+            #[allow(clippy::cast_precision_loss)]
+            let count = self.restart_count as f64;
+            let rule = format!("Container {name} restarted");
+            events.push(event(rule, "restarts", count));
+        }
+        events
+    }
+}
+
+/// Entry of `GET /containers/json`
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Summary {
+    id: String,
+    names: Vec<String>,
+    image: String,
+}
+
+/// `GET /containers/{id}/json`, only what's used
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Inspect {
+    restart_count: u64,
+    state: InspectState,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct InspectState {
+    status: String,
+    exit_code: i64,
+    started_at: String,
+    health: Option<InspectHealth>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct InspectHealth {
+    status: String,
+}
+
+/// `GET /containers/{id}/stats?stream=false&one-shot=true`, only what's
+/// used. Counters are cumulative, so rates are computed between two
+/// collections.
+#[derive(Debug, Deserialize)]
+struct Stats {
+    #[serde(rename = "cpu_stats")]
+    cpu: CpuStats,
+    #[serde(rename = "memory_stats")]
+    memory: MemoryStats,
+    /// By interface, none with the host's network
+    #[serde(default)]
+    networks: HashMap<String, NetworkStats>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CpuStats {
+    cpu_usage: CpuUsage,
+    system_cpu_usage: Option<u64>,
+    online_cpus: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CpuUsage {
+    total_usage: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct MemoryStats {
+    usage: Option<u64>,
+    limit: Option<u64>,
+    #[serde(default)]
+    stats: HashMap<String, u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct NetworkStats {
+    rx_bytes: u64,
+    tx_bytes: u64,
+    rx_packets: u64,
+    tx_packets: u64,
+    rx_errors: u64,
+    tx_errors: u64,
+}
+
+/// What's kept of a container between two collections
+struct Previous {
+    running: bool,
+    started_at: String,
+    /// CPU time of the container and of the host, in nanoseconds
+    cpu: Option<(u64, u64)>,
+    network: Option<(Instant, NetworkStats)>,
+}
+
+/// Collects the containers' metrics from the Engine's API.
+pub struct Docker {
+    runtime: RuntimeRef,
+    socket: PathBuf,
+    containers: HashSet<String>,
+    /// By container ID
+    previous: HashMap<String, Previous>,
+    /// Whether the Engine answered last time, so that it's only logged
+    /// when it goes away or comes back
+    reachable: Option<bool>,
+}
+
+impl Docker {
+    pub fn new(runtime: RuntimeRef, config: &DockerConfig) -> Self {
+        Self {
+            runtime,
+            socket: config.socket.clone(),
+            containers: config.containers.clone(),
+            previous: HashMap::new(),
+            reachable: None,
+        }
+    }
+
+    /// Monitored containers by name, none if the Engine can't be reached
+    pub async fn collect(
+        &mut self,
+    ) -> Option<HashMap<Box<str>, ContainerMetrics>> {
+        let listed =
+            self.get::<Vec<Summary>>("/containers/json?all=true").await;
+        let listed = match listed {
+            Ok(listed) => {
+                if self.reachable == Some(false) {
+                    info!("Docker Engine is reachable again");
+                }
+                self.reachable = Some(true);
+                listed
+            }
+            Err(e) => {
+                if self.reachable != Some(false) {
+                    warn!("Failed to list Docker containers: {e}");
+                }
+                self.reachable = Some(false);
+                return None;
+            }
+        };
+
+        let mut containers = HashMap::new();
+        let mut previous = HashMap::new();
+        for summary in listed {
+            // Names are prefixed with a `/`
+            let Some(name) = summary.names.first() else {
+                continue;
+            };
+            let name = name.trim_start_matches('/');
+            if !self.containers.is_empty() && !self.containers.contains(name) {
+                continue;
+            }
+            match self.container(&summary).await {
+                Ok((metrics, kept)) => {
+                    containers.insert(Box::from(name), metrics);
+                    previous.insert(summary.id, kept);
+                }
+                Err(e) => warn!("Failed to read Docker container {name}: {e}"),
+            }
+        }
+        // Containers that were removed start over if they come back
+        self.previous = previous;
+        Some(containers)
+    }
+
+    async fn container(
+        &self,
+        summary: &Summary,
+    ) -> Result<(ContainerMetrics, Previous), String> {
+        let id = &summary.id;
+        let inspect = self
+            .get::<Inspect>(&format!("/containers/{id}/json"))
+            .await?;
+        let state = inspect.state;
+        let running = state.status == "running";
+        let previous = self.previous.get(id);
+        let usage = if running {
+            let path =
+                format!("/containers/{id}/stats?stream=false&one-shot=true");
+            Some(self.get::<Stats>(&path).await?)
+        } else {
+            None
+        };
+
+        let now = Instant::now();
+        let cpu = usage.as_ref().and_then(|usage| {
+            let cpu = &usage.cpu;
+            Some((cpu.cpu_usage.total_usage, cpu.system_cpu_usage?))
+        });
+        let cpu_usage = usage.as_ref().and_then(|usage| {
+            let (container, system) = cpu?;
+            let (before, system_before) = previous?.cpu?;
+            let cores = usage.cpu.online_cpus.unwrap_or(1);
+            cpu_percent(container, before, system, system_before, cores)
+        });
+        let memory = usage.as_ref().and_then(|usage| memory(&usage.memory));
+        let network_total =
+            usage.map(|usage| total(usage.networks.into_values()));
+        let network = network_total.as_ref().and_then(|current| {
+            let (at, before) = previous?.network.as_ref()?;
+            rates(current, before, now.checked_duration_since(*at)?)
+        });
+
+        let restarted = previous.is_some_and(|previous| {
+            running && previous.started_at != state.started_at
+        });
+        let exited = previous
+            .filter(|previous| previous.running && !running)
+            .map(|_| state.exit_code);
+        let metrics = ContainerMetrics {
+            image: summary.image.clone(),
+            state: state.status,
+            health: state.health.map(|health| health.status),
+            restart_count: inspect.restart_count,
+            cpu: cpu_usage,
+            memory,
+            network,
+            exited,
+            restarted,
+        };
+        let kept = Previous {
+            running,
+            started_at: state.started_at,
+            cpu,
+            network: network_total.map(|total| (now, total)),
+        };
+        Ok((metrics, kept))
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, String> {
+        let request = HttpRequest::get(path).timeout(REQUEST_TIMEOUT);
+        let response = self
+            .runtime
+            .unix_request(&self.socket, request)
+            .await
+            .map_err(|e: HttpError| e.to_string())?;
+        if !response.is_success() {
+            let status = response.status;
+            return Err(format!(
+                "{path} answered {status}: {}",
+                response.text()
+            ));
+        }
+        serde_json::from_slice(&response.body)
+            .map_err(|e| format!("invalid answer to {path}: {e}"))
+    }
+}
+
+/// Usage between two readings, as `docker stats` computes it
+fn cpu_percent(
+    container: u64,
+    before: u64,
+    system: u64,
+    system_before: u64,
+    cores: u32,
+) -> Option<f64> {
+    let container = container.checked_sub(before)?;
+    let system = system.checked_sub(system_before)?;
+    if system == 0 {
+        return None;
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let share = container as f64 / system as f64;
+    Some(share * f64::from(cores) * 100.0)
+}
+
+/// Memory in use, without the page cache, as `docker stats` shows it
+fn memory(stats: &MemoryStats) -> Option<MemoryMetrics> {
+    // `inactive_file` with cgroup v2, `total_inactive_file` with v1
+    let cache = ["inactive_file", "total_inactive_file"]
+        .iter()
+        .find_map(|key| stats.stats.get(*key))
+        .copied()
+        .unwrap_or_default();
+    Some(MemoryMetrics {
+        used: stats.usage?.saturating_sub(cache),
+        total: stats.limit?,
+    })
+}
+
+/// Counters of every interface of a container, summed
+fn total(networks: impl Iterator<Item = NetworkStats>) -> NetworkStats {
+    networks.fold(NetworkStats::default(), |total, network| NetworkStats {
+        rx_bytes: total.rx_bytes + network.rx_bytes,
+        tx_bytes: total.tx_bytes + network.tx_bytes,
+        rx_packets: total.rx_packets + network.rx_packets,
+        tx_packets: total.tx_packets + network.tx_packets,
+        rx_errors: total.rx_errors + network.rx_errors,
+        tx_errors: total.tx_errors + network.tx_errors,
+    })
+}
+
+/// Rates over `elapsed`, none if a counter went backwards
+fn rates(
+    current: &NetworkStats,
+    before: &NetworkStats,
+    elapsed: Duration,
+) -> Option<NetworkMetrics> {
+    if elapsed.is_zero() {
+        return None;
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let delta = |now: u64, before: u64| {
+        now.checked_sub(before).map(|delta| delta as f64)
+    };
+    let percentage = |errors: f64, packets: f64| {
+        if packets == 0.0 {
+            0.0
+        } else {
+            100.0 * errors / packets
+        }
+    };
+    let elapsed = elapsed.as_secs_f64();
+    Some(NetworkMetrics {
+        bytes_received: delta(current.rx_bytes, before.rx_bytes)? / elapsed,
+        bytes_transmitted: delta(current.tx_bytes, before.tx_bytes)? / elapsed,
+        received_error_percentage: percentage(
+            delta(current.rx_errors, before.rx_errors)?,
+            delta(current.rx_packets, before.rx_packets)?,
+        ),
+        transmit_error_percentage: percentage(
+            delta(current.tx_errors, before.tx_errors)?,
+            delta(current.tx_packets, before.tx_packets)?,
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use nexus_api::{CancellationToken, Permissions, TokioRuntimeHandle};
+    use serde_json::{Value, json};
+    use tempfile::TempDir;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::UnixListener;
+
+    use super::*;
+
+    /// Answers of the Engine by path, changed between collections
+    type Answers = Arc<Mutex<HashMap<String, Value>>>;
+
+    /// Serves `answers` on a socket in `dir`, 404 on other paths
+    fn engine(dir: &TempDir, answers: &Answers) -> PathBuf {
+        let socket = dir.path().join("docker.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        let answers = Arc::clone(answers);
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0; 1_024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                }
+                let request = String::from_utf8(request).unwrap();
+                let path = request.split(' ').nth(1).unwrap();
+                let answer = answers.lock().unwrap().get(path).cloned();
+                let (status, body) = answer.map_or_else(
+                    || ("404 Not Found", "{}".to_string()),
+                    |answer| ("200 OK", answer.to_string()),
+                );
+                let answer = format!(
+                    "HTTP/1.1 {status}\r\nContent-Length: {}\r\n\r\n{body}",
+                    body.len()
+                );
+                stream.write_all(answer.as_bytes()).await.unwrap();
+            }
+        });
+        socket.canonicalize().unwrap()
+    }
+
+    fn docker(socket: PathBuf, containers: &[&str]) -> Docker {
+        let permissions = Permissions {
+            sockets: vec![socket.clone()],
+            ..Permissions::default()
+        };
+        let runtime = TokioRuntimeHandle::new(
+            tokio::runtime::Handle::current(),
+            "nexus-metrics",
+            CancellationToken::new(),
+        )
+        .with_permissions(permissions);
+        let config = DockerConfig {
+            enabled: true,
+            socket,
+            containers: containers.iter().map(|c| (*c).into()).collect(),
+        };
+        Docker::new(Arc::new(runtime), &config)
+    }
+
+    fn inspect(status: &str, started_at: &str, restarts: u64) -> Value {
+        json!({
+            "RestartCount": restarts,
+            "State": {
+                "Status": status,
+                "ExitCode": if status == "running" { 0 } else { 137 },
+                "StartedAt": started_at,
+                "Health": { "Status": "healthy" },
+            },
+        })
+    }
+
+    /// Cumulative CPU times, in nanoseconds, and received bytes
+    fn stats(container: u64, system: u64, received: u64) -> Value {
+        json!({
+            "cpu_stats": {
+                "cpu_usage": { "total_usage": container },
+                "system_cpu_usage": system,
+                "online_cpus": 2,
+            },
+            "memory_stats": {
+                "usage": 500,
+                "limit": 1_000,
+                "stats": { "inactive_file": 100 },
+            },
+            "networks": {
+                "eth0": {
+                    "rx_bytes": received,
+                    "tx_bytes": 0,
+                    "rx_packets": 10,
+                    "tx_packets": 0,
+                    "rx_errors": 0,
+                    "tx_errors": 0,
+                },
+            },
+        })
+    }
+
+    const STATS: &str = "stats?stream=false&one-shot=true";
+
+    /// `web` and `db` running, along with an unmonitored `cache`
+    fn answers() -> Answers {
+        let answers = HashMap::from([
+            (
+                "/containers/json?all=true".into(),
+                json!([
+                    { "Id": "w1", "Names": ["/web"], "Image": "nginx" },
+                    { "Id": "d1", "Names": ["/db"], "Image": "postgres" },
+                    { "Id": "c1", "Names": ["/cache"], "Image": "redis" },
+                ]),
+            ),
+            ("/containers/w1/json".into(), inspect("running", "t0", 0)),
+            (format!("/containers/w1/{STATS}"), stats(1_000, 10_000, 0)),
+            ("/containers/d1/json".into(), inspect("running", "t0", 0)),
+            (format!("/containers/d1/{STATS}"), stats(0, 10_000, 0)),
+        ]);
+        Arc::new(Mutex::new(answers))
+    }
+
+    fn answer(answers: &Answers, path: &str, answer: Value) {
+        answers.lock().unwrap().insert(path.into(), answer);
+    }
+
+    fn events(containers: &HashMap<Box<str>, ContainerMetrics>) -> Vec<String> {
+        let mut events = containers
+            .iter()
+            .flat_map(|(name, container)| container.events(name))
+            .map(|event| event.rule)
+            .collect::<Vec<_>>();
+        events.sort();
+        events
+    }
+
+    #[tokio::test]
+    async fn collects_the_monitored_containers() {
+        let dir = tempfile::tempdir().unwrap();
+        let answers = answers();
+        let mut docker = docker(engine(&dir, &answers), &["web", "db"]);
+
+        let containers = docker.collect().await.unwrap();
+        let mut names =
+            containers.keys().map(AsRef::as_ref).collect::<Vec<&str>>();
+        names.sort_unstable();
+        assert_eq!(names, ["db", "web"]);
+        let web = &containers["web"];
+        assert_eq!(web.image, "nginx");
+        assert!(web.is_running());
+        assert_eq!(web.health.as_deref(), Some("healthy"));
+        // Without the page cache
+        let memory = web.memory.as_ref().unwrap();
+        assert_eq!((memory.used, memory.total), (400, 1_000));
+        // Rates need a previous collection
+        assert!(web.cpu.is_none());
+        assert!(web.network.is_none());
+        assert!(events(&containers).is_empty());
+    }
+
+    #[tokio::test]
+    async fn computes_cpu_usage_across_collections() {
+        let dir = tempfile::tempdir().unwrap();
+        let answers = answers();
+        let mut docker = docker(engine(&dir, &answers), &[]);
+        docker.collect().await.unwrap();
+
+        // A quarter of the host's CPU time, of 2 cores
+        let path = format!("/containers/w1/{STATS}");
+        answer(&answers, &path, stats(1_500, 12_000, 1_000));
+        let idle = stats(0, 12_000, 0);
+        answer(&answers, &format!("/containers/d1/{STATS}"), idle);
+        let containers = docker.collect().await.unwrap();
+        assert_eq!(containers["web"].cpu, Some(50.0));
+        let network = containers["web"].network.as_ref().unwrap();
+        assert!(network.bytes_received > 0.0);
+        // Idle
+        assert_eq!(containers["db"].cpu, Some(0.0));
+
+        // Reset by a restart
+        answer(&answers, &path, stats(100, 14_000, 0));
+        let containers = docker.collect().await.unwrap();
+        assert_eq!(containers["web"].cpu, None);
+        assert!(containers["web"].network.is_none());
+    }
+
+    #[tokio::test]
+    async fn alerts_on_exits_and_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let answers = answers();
+        let mut docker = docker(engine(&dir, &answers), &["web", "db"]);
+        docker.collect().await.unwrap();
+
+        answer(&answers, "/containers/d1/json", inspect("exited", "t0", 0));
+        answer(&answers, "/containers/w1/json", inspect("running", "t1", 1));
+        let containers = docker.collect().await.unwrap();
+        assert!(!containers["db"].is_running());
+        assert!(containers["db"].cpu.is_none());
+        assert_eq!(
+            events(&containers),
+            ["Container db exited with 137", "Container web restarted"]
+        );
+
+        // Only once
+        let containers = docker.collect().await.unwrap();
+        assert!(events(&containers).is_empty());
+    }
+
+    #[tokio::test]
+    async fn waits_for_the_engine() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().canonicalize().unwrap().join("docker.sock");
+        let mut docker = docker(socket, &[]);
+        assert!(docker.collect().await.is_none());
+        assert_eq!(docker.reachable, Some(false));
+
+        engine(&dir, &answers());
+        // Without `cache`, which can't be inspected
+        assert_eq!(docker.collect().await.unwrap().len(), 2);
+        assert_eq!(docker.reachable, Some(true));
+    }
+}
@@ -0,0 +1,408 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use nexus_api::{HttpResponse, IncomingRequest};
+use serde_json::json;
+
+/// Resolution and retention of each tier, finest first.
+/// The first tier keeps every sample as it was collected.
+const TIERS: [(Duration, Duration); 3] = [
+    (Duration::ZERO, Duration::from_hours(1)),
+    (Duration::from_mins(1), Duration::from_hours(24)),
+    (Duration::from_mins(15), Duration::from_hours(30 * 24)),
+];
+
+/// How long a series is kept after its last sample, e.g. once its
+/// container is removed
+const LONGEST_RETENTION: u64 = TIERS[TIERS.len() - 1].1.as_secs();
+
+/// Aggregate of the samples collected within a bucket.
+#[derive(Debug, Clone, Copy)]
+pub struct Point {
+    /// Unix time of the start of the bucket, or of the sample itself
+    pub at: u64,
+    pub min: f64,
+    pub max: f64,
+    sum: f64,
+    count: u32,
+}
+
+impl Point {
+    const fn new(at: u64, value: f64) -> Self {
+        Self {
+            at,
+            min: value,
+            max: value,
+            sum: value,
+            count: 1,
+        }
+    }
+
+    fn add(&mut self, value: f64) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value;
+        self.count += 1;
+    }
+
+    pub fn avg(&self) -> f64 {
+        self.sum / f64::from(self.count)
+    }
+}
+
+/// Points of a series at one resolution.
+#[derive(Debug)]
+struct Tier {
+    resolution: u64,
+    retention: u64,
+    points: VecDeque<Point>,
+    /// Bucket still being filled, only for aggregated tiers
+    pending: Option<Point>,
+}
+
+impl Tier {
+    const fn new((resolution, retention): (Duration, Duration)) -> Self {
+        Self {
+            resolution: resolution.as_secs(),
+            retention: retention.as_secs(),
+            points: VecDeque::new(),
+            pending: None,
+        }
+    }
+
+    fn record(&mut self, at: u64, value: f64) {
+        if self.resolution == 0 {
+            self.points.push_back(Point::new(at, value));
+        } else {
+            let bucket = at - at % self.resolution;
+            match &mut self.pending {
+                Some(pending) if pending.at == bucket => pending.add(value),
+                pending => {
+                    if let Some(done) =
+                        pending.replace(Point::new(bucket, value))
+                    {
+                        self.points.push_back(done);
+                    }
+                }
+            }
+        }
+
+        let oldest = at.saturating_sub(self.retention);
+        while self.points.front().is_some_and(|p| p.at < oldest) {
+            self.points.pop_front();
+        }
+    }
+
+    /// Start of the latest point, if any is left
+    fn newest(&self) -> Option<u64> {
+        self.pending
+            .or_else(|| self.points.back().copied())
+            .map(|p| p.at)
+    }
+
+    fn range(&self, from: u64, to: u64) -> impl Iterator<Item = Point> + '_ {
+        self.points
+            .iter()
+            .chain(&self.pending)
+            .filter(move |p| p.at >= from && p.at <= to)
+            .copied()
+    }
+}
+
+/// History of every series, e.g. `cpu` or `disk./dev/sda1.used`.
+#[derive(Debug, Default)]
+pub struct History {
+    series: BTreeMap<String, [Tier; 3]>,
+}
+
+impl History {
+    /// Records samples collected at `at`
+    pub fn record(
+        &mut self,
+        at: SystemTime,
+        samples: impl IntoIterator<Item = (String, f64)>,
+    ) {
+        let at = unix_secs(at);
+        for (key, value) in samples {
+            if !value.is_finite() {
+                continue;
+            }
+            let tiers = self
+                .series
+                .entry(key)
+                .or_insert_with(|| TIERS.map(Tier::new));
+            for tier in tiers {
+                tier.record(at, value);
+            }
+        }
+
+        let oldest = at.saturating_sub(LONGEST_RETENTION);
+        self.series.retain(|_, tiers| {
+            tiers
+                .iter()
+                .filter_map(Tier::newest)
+                .max()
+                .is_some_and(|newest| newest >= oldest)
+        });
+    }
+
+    /// Names of every recorded series
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.series.keys().map(String::as_str)
+    }
+
+    /// Points of `key` between `from` and `to`, from the finest tier that
+    /// still covers `from`. Returns the resolution used along with them.
+    pub fn query(
+        &self,
+        key: &str,
+        from: SystemTime,
+        to: SystemTime,
+    ) -> Option<(Duration, Vec<Point>)> {
+        let tiers = self.series.get(key)?;
+        let (from, to) = (unix_secs(from), unix_secs(to));
+        let now = unix_secs(SystemTime::now());
+
+        let tier = tiers
+            .iter()
+            .find(|tier| from >= now.saturating_sub(tier.retention))
+            .unwrap_or(&tiers[tiers.len() - 1]);
+        let points = tier.range(from, to).collect();
+        Some((Duration::from_secs(tier.resolution), points))
+    }
+}
+
+/// Time range asked for by `request`
+fn range(
+    request: &IncomingRequest,
+) -> Result<(SystemTime, SystemTime), String> {
+    let time = |name| {
+        request
+            .query_param(name)
+            .map(|secs| {
+                // Out of range of `SystemTime` rather than panicking
+                secs.parse()
+                    .ok()
+                    .and_then(|secs| {
+                        UNIX_EPOCH.checked_add(Duration::from_secs(secs))
+                    })
+                    .ok_or_else(|| format!("`{name}` must be a unix time"))
+            })
+            .transpose()
+    };
+
+    let now = SystemTime::now();
+    let to = time("to")?.unwrap_or(now);
+    if let Some(from) = time("from")? {
+        return Ok((from, to));
+    }
+    let since = request.query_param("since");
+    let since = humantime::parse_duration(since.as_deref().unwrap_or("1h"))
+        .map_err(|e| format!("Invalid `since`: {e}"))?;
+    Ok((now.checked_sub(since).unwrap_or(UNIX_EPOCH), to))
+}
+
+fn unix_secs(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// Answers `GET /history`.
+///
+/// - Without parameters, lists the recorded series.
+/// - `series` picks one, between `from` and `to` (unix times), or over
+///   the last `since` (e.g. `24h`, the default being `1h`).
+pub fn handle(
+    history: &Mutex<History>,
+    request: &IncomingRequest,
+) -> HttpResponse {
+    let Some(key) = request.query_param("series") else {
+        let keys = history
+            .lock()
+            .expect("History poisoned")
+            .keys()
+            .map(str::to_owned)
+            .collect::<Vec<_>>();
+        return HttpResponse::json(json!({ "series": keys }).to_string());
+    };
+
+    let (from, to) = match range(request) {
+        Ok(range) => range,
+        Err(e) => return HttpResponse::new(400).with_body(e),
+    };
+    let found = history
+        .lock()
+        .expect("History poisoned")
+        .query(&key, from, to);
+    let Some((resolution, points)) = found else {
+        return HttpResponse::new(404).with_body(format!("No series `{key}`"));
+    };
+
+    let points = points
+        .iter()
+        .map(|p| json!({ "at": p.at, "min": p.min, "avg": p.avg(), "max": p.max }))
+        .collect::<Vec<_>>();
+    HttpResponse::json(
+        json!({
+            "series": key,
+            "resolution_secs": resolution.as_secs(),
+            "points": points,
+        })
+        .to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use nexus_api::Method;
+    use serde_json::Value;
+
+    use super::*;
+
+    fn secs(at: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(at)
+    }
+
+    /// Start of a minute, a couple of hours ago
+    fn two_hours_ago() -> u64 {
+        let at = unix_secs(SystemTime::now()) - 2 * 3600;
+        at - at % 60
+    }
+
+    fn get(history: &Mutex<History>, query: &str) -> (u16, Value) {
+        let request = IncomingRequest {
+            method: Method::Get,
+            path: "/history".into(),
+            query: (!query.is_empty()).then(|| query.into()),
+            headers: Vec::new(),
+            body: Vec::new(),
+        };
+        let response = handle(history, &request);
+        let body = serde_json::from_slice(&response.body)
+            .unwrap_or_else(|_| Value::String(response.text()));
+        (response.status, body)
+    }
+
+    #[test]
+    fn downsamples_into_buckets() {
+        let start = two_hours_ago();
+        let mut history = History::default();
+        for (offset, value) in [(0, 1.0), (20, 5.0), (40, 3.0), (60, 7.0)] {
+            history.record(secs(start + offset), [("cpu".into(), value)]);
+        }
+
+        let (resolution, points) = history
+            .query("cpu", secs(start), secs(start + 3600))
+            .unwrap();
+        assert_eq!(resolution, Duration::from_mins(1));
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].at, start);
+        assert!((points[0].min - 1.0).abs() < f64::EPSILON);
+        assert!((points[0].max - 5.0).abs() < f64::EPSILON);
+        assert!((points[0].avg() - 3.0).abs() < f64::EPSILON);
+        // The bucket still being filled is included
+        assert_eq!(points[1].at, start + 60);
+        assert!((points[1].avg() - 7.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn keeps_every_sample_of_the_last_hour() {
+        let now = unix_secs(SystemTime::now());
+        let mut history = History::default();
+        for offset in [30, 20, 10] {
+            history.record(secs(now - offset), [("cpu".into(), 1.0)]);
+        }
+        let (resolution, points) =
+            history.query("cpu", secs(now - 60), secs(now)).unwrap();
+        assert_eq!(resolution, Duration::ZERO);
+        assert_eq!(points.len(), 3);
+    }
+
+    #[test]
+    fn skips_values_that_are_not_finite() {
+        let mut history = History::default();
+        history.record(SystemTime::now(), [("cpu".into(), f64::NAN)]);
+        assert_eq!(history.keys().count(), 0);
+    }
+
+    #[test]
+    fn drops_series_no_longer_reported() {
+        let start = two_hours_ago();
+        let mut history = History::default();
+        history.record(
+            secs(start),
+            [("container.gone".into(), 1.0), ("cpu".into(), 1.0)],
+        );
+        // Still within the retention of the coarsest tier
+        history.record(secs(start + 24 * 3600), [("cpu".into(), 1.0)]);
+        assert_eq!(history.keys().count(), 2);
+
+        history.record(
+            secs(start + LONGEST_RETENTION + 60),
+            [("cpu".into(), 1.0)],
+        );
+        assert_eq!(history.keys().collect::<Vec<_>>(), ["cpu"]);
+    }
+
+    #[test]
+    fn lists_series_without_parameters() {
+        let mut history = History::default();
+        history.record(
+            SystemTime::now(),
+            [("cpu".into(), 1.0), ("memory".into(), 2.0)],
+        );
+        let (status, body) = get(&Mutex::new(history), "");
+        assert_eq!(status, 200);
+        assert_eq!(body["series"], serde_json::json!(["cpu", "memory"]));
+    }
+
+    #[test]
+    fn queries_a_series_by_range() {
+        let start = two_hours_ago();
+        let mut history = History::default();
+        for offset in [0, 60, 120] {
+            history.record(secs(start + offset), [("cpu".into(), 1.0)]);
+        }
+        let history = Mutex::new(history);
+
+        let query =
+            format!("series=cpu&from={}&to={}", start + 60, start + 120);
+        let (status, body) = get(&history, &query);
+        assert_eq!(status, 200);
+        assert_eq!(body["series"], "cpu");
+        assert_eq!(body["resolution_secs"], 60);
+        let at = body["points"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["at"].as_u64().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(at, [start + 60, start + 120]);
+
+        let (status, body) = get(&history, "series=cpu&since=3h");
+        assert_eq!(status, 200);
+        assert_eq!(body["points"].as_array().unwrap().len(), 3);
+        // Only the last hour by default
+        let (_, body) = get(&history, "series=cpu");
+        assert_eq!(body["points"].as_array().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn rejects_invalid_parameters() {
+        let mut history = History::default();
+        history.record(SystemTime::now(), [("cpu".into(), 1.0)]);
+        let history = Mutex::new(history);
+
+        assert_eq!(get(&history, "series=disk").0, 404);
+        assert_eq!(get(&history, "series=cpu&from=yesterday").0, 400);
+        assert_eq!(get(&history, "series=cpu&to=-1").0, 400);
+        assert_eq!(get(&history, "series=cpu&since=forever").0, 400);
+        let max = format!("series=cpu&from={}", u64::MAX);
+        assert_eq!(get(&history, &max).0, 400);
+        let max = format!("series=cpu&to={}", u64::MAX);
+        assert_eq!(get(&history, &max).0, 400);
+    }
+}
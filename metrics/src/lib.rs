@@ -1,11 +1,31 @@
+mod alerts;
+mod audits;
+mod config;
+mod docker;
+mod history;
+mod processes;
+mod prometheus;
+mod status;
 mod sys_info;
 
-use std::time::Duration;
-use nexus_api::{r#impl, Meta};
-use sys_info::SysInfo;
-use tracing::info;
-
-pub const DELAY_SECS: f64 = 30.0;
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use alerts::Alerts;
+use audits::{AuditMonitor, Bans, GeoIp, GeoIpConfig};
+use config::{Config, SshAlertsConfig};
+use docker::Docker;
+use history::History;
+use nexus_api::{
+    r#impl, ChildProcess, HttpResponse, Lines, Meta, Method, RouteError,
+    TailCursor, TailOptions,
+};
+use nexus_utils::discord::Webhook;
+use status::StatusMessage;
+use sys_info::{Metrics, Report, SysInfo};
+use tracing::{debug, error, info, warn};
 
 r#impl! {
     pub static META: Meta = Meta {
@@ -15,26 +35,271 @@ r#impl! {
     };
 
     async fn main(&self) {
+        let config = match Config::load(self.settings()) {
+            Ok(config) => config,
+            Err(e) => {
+                error!("Not collecting system metrics, {e}");
+                return;
+            }
+        };
+        let config = Arc::new(config);
         info!("Now collecting system metrics");
 
         // Initialize components
-        let mut metrics_collector = SysInfo::new();
-        
-        // // Start SSH audit monitoring if enabled
-        // if true { // TODO
-        //     let audit_monitor = AuditMonitor::new(Arc::clone(&config), Arc::clone(&notifier));
-        //     audit_monitor.start().await;
-        // }
+        let mut metrics_collector = SysInfo::new(Arc::clone(&config));
+        let mut status = self.status_message(&config);
+        let mut docker = config.docker.enabled.then(|| {
+            Docker::new(Arc::clone(self.runtime()), &config.docker)
+        });
+
+        // Latest metrics, in Prometheus format, for the host's `/metrics`
+        let exposition = Arc::new(Mutex::new(None::<String>));
+        let latest = Arc::clone(&exposition);
+        let route = self.route(Method::Get, "/metrics", move |_| {
+            let latest = latest.lock().expect("Exposition poisoned").clone();
+            async move {
+                latest.map_or_else(
+                    || HttpResponse::new(503).with_body("No metrics collected yet"),
+                    |body| {
+                        HttpResponse::new(200)
+                            .with_header("Content-Type", prometheus::CONTENT_TYPE)
+                            .with_body(body)
+                    },
+                )
+            }
+        });
+        match route {
+            Ok(()) | Err(RouteError::Unavailable) => {}
+            Err(e) => warn!("Failed to serve metrics: {e}"),
+        }
+
+        let mut alerts = Alerts::new(config.alert_rules());
+
+        // Kept in memory, downsampled as it ages
+        let history = Arc::new(Mutex::new(History::default()));
+        let queried = Arc::clone(&history);
+        let route = self.route(Method::Get, "/history", move |request| {
+            let response = history::handle(&queried, &request);
+            async move { response }
+        });
+        match route {
+            Ok(()) | Err(RouteError::Unavailable) => {}
+            Err(e) => warn!("Failed to serve metrics history: {e}"),
+        }
+
+        // Start SSH audit monitoring if enabled
+        if config.ssh_alerts.enabled {
+            self.start_audits(&config).await;
+        }
 
         // Main loop interval
         loop {
-            // Collect system metrics
-            let metrics = metrics_collector.collect();
-            info!("### Metrics update\n{metrics}" );
+            let pids = self.read_pid_files(&config).await;
+            // Collect system metrics, `/proc` reads are blocking
+            let collected = self
+                .spawn_blocking("collect", move || {
+                    let metrics = metrics_collector.collect(&pids);
+                    (metrics_collector, metrics)
+                })
+                .await;
+            let mut metrics = match collected {
+                Ok((collector, metrics)) => {
+                    metrics_collector = collector;
+                    metrics
+                }
+                Err(e) => {
+                    error!("Failed to collect system metrics: {e}");
+                    break;
+                }
+            };
+            if let Some(docker) = &mut docker {
+                metrics.containers = docker.collect().await;
+            }
+            let report = Report {
+                metrics: &metrics,
+                config: &config,
+            }
+            .to_string();
+            info!("### Metrics update\n{report}");
+            if let Some(status) = &mut status {
+                status.post(&config, report).await;
+            }
+            *exposition.lock().expect("Exposition poisoned") =
+                Some(prometheus::render(&metrics));
+            let samples = metrics.samples();
+            for event in alerts.evaluate(self.now(), &samples) {
+                event.emit();
+            }
+            emit_restarts(&metrics);
+            history
+                .lock()
+                .expect("History poisoned")
+                .record(SystemTime::now(), samples);
             // Wait for the next update interval using runtime handle
-            #[allow(clippy::cast_sign_loss)]
-            #[allow(clippy::cast_possible_truncation)]
-            self.sleep(Duration::from_secs(DELAY_SECS as u64)).await;
+            if self
+                .sleep_cancellable(config.update_interval)
+                .await
+                .is_err()
+            {
+                break;
+            }
+        }
+
+        info!("Stopped collecting system metrics");
+    }
+}
+
+/// One-off alerts on watched processes and containers that restarted or
+/// exited since the previous collection
+fn emit_restarts(metrics: &Metrics) {
+    let watched = metrics.watched_processes.iter().flatten();
+    for (name, watched) in watched {
+        if let Some(event) = watched.restart(name) {
+            event.emit();
+        }
+    }
+    let containers = metrics.containers.iter().flatten();
+    for (name, container) in containers {
+        for event in container.events(name) {
+            event.emit();
         }
     }
 }
+
+impl Instance {
+    /// Status message, if there's a webhook to post it to
+    fn status_message(&self, config: &Config) -> Option<StatusMessage> {
+        let url = config.webhook_url.clone()?;
+        let http = self
+            .http()
+            .inspect_err(|e| warn!("Not posting status updates: {e}"))
+            .ok()?;
+        let storage = self
+            .storage()
+            .inspect_err(|e| warn!("Status message won't be kept: {e}"))
+            .ok();
+        let webhook = Webhook::new(url, http);
+        Some(StatusMessage::new(webhook, storage, config))
+    }
+
+    /// PIDs in the pid files of the watched processes, read through the
+    /// host so that its read permissions apply
+    async fn read_pid_files(&self, config: &Config) -> HashMap<Box<str>, u32> {
+        let mut pids = HashMap::new();
+        for watched in &config.processes.watch {
+            let Some(pid_file) = &watched.pid_file else {
+                continue;
+            };
+            let pid = match self.read_file(pid_file).await {
+                Ok(pid) => String::from_utf8_lossy(&pid).trim().parse(),
+                Err(e) => {
+                    debug!("Failed to read {}: {e}", pid_file.display());
+                    continue;
+                }
+            };
+            if let Ok(pid) = pid {
+                pids.insert(Box::from(watched.name.as_str()), pid);
+            }
+        }
+        pids
+    }
+
+    /// Follows the SSH log in a task of its own
+    async fn start_audits(&self, config: &Config) {
+        let ssh = &config.ssh_alerts;
+        let storage = self
+            .storage()
+            .inspect_err(|e| warn!("SSH log position won't be kept: {e}"))
+            .ok();
+        // Picks up where it stopped, so no login is missed or repeated
+        let cursor = audits::cursor(storage.clone(), ssh);
+        let saved = cursor.load();
+        let (lines, journalctl) = match self.read_ssh_log(ssh, saved).await {
+            Ok(read) => read,
+            Err(e) => {
+                let source = ssh.followed_path().map_or_else(
+                    || "journalctl".to_string(),
+                    |path| path.display().to_string(),
+                );
+                error!(
+                    "Not monitoring SSH logins, failed to read {source}: {e}"
+                );
+                return;
+            }
+        };
+        let url = ssh
+            .ssh_alert_webhook_url
+            .clone()
+            .or_else(|| config.webhook_url.clone());
+        let webhook = url.and_then(|url| {
+            let http = self
+                .http()
+                .inspect_err(|e| warn!("Not posting SSH logins: {e}"))
+                .ok()?;
+            Some(Webhook::new(url, http))
+        });
+
+        let bans = (ssh.brute_force.ban.as_ref())
+            .filter(|_| ssh.brute_force.enabled)
+            .map(|ban| {
+                Bans::new(ban, Arc::clone(self.runtime()), storage.clone())
+            });
+        let geoip = self.geoip(&ssh.geoip).await;
+        let monitor = AuditMonitor::new(ssh, webhook, cursor, bans, geoip);
+        self.spawn_cancellable("ssh audits", async move {
+            // `journalctl` is killed once dropped
+            let _journalctl = journalctl;
+            monitor.start(lines).await;
+        });
+        info!("Now monitoring SSH logins");
+    }
+
+    /// Location databases, if configured and readable
+    async fn geoip(&self, config: &GeoIpConfig) -> Option<GeoIp> {
+        if !config.enabled() {
+            return None;
+        }
+        let read = async |path: &Option<PathBuf>| match path {
+            Some(path) => self.read_file(path).await.map(Some),
+            None => Ok(None),
+        };
+        let city = read(&config.database).await;
+        let asn = read(&config.asn_database).await;
+        let (city, asn) = match (city, asn) {
+            (Ok(city), Ok(asn)) => (city, asn),
+            (Err(e), _) | (_, Err(e)) => {
+                warn!("Not locating SSH logins, failed to read database: {e}");
+                return None;
+            }
+        };
+        GeoIp::new(config, city, asn)
+            .inspect_err(|e| warn!("Not locating SSH logins: {e}"))
+            .ok()
+    }
+
+    /// Lines of the SSH log, along with the process they're read from, if
+    /// it's `journalctl`
+    async fn read_ssh_log(
+        &self,
+        ssh: &SshAlertsConfig,
+        saved: Option<Vec<u8>>,
+    ) -> io::Result<(Lines, Option<ChildProcess>)> {
+        let Some(path) = ssh.followed_path() else {
+            let cursor =
+                saved.and_then(|cursor| String::from_utf8(cursor).ok());
+            let command =
+                ssh.journal.command(&ssh.identifiers, cursor.as_deref());
+            let mut child = self.spawn_process(command)?;
+            let lines = child
+                .take_stdout()
+                .ok_or_else(|| io::Error::other("no output to read"))?;
+            return Ok((lines, Some(child)));
+        };
+        let options = TailOptions {
+            resume: saved.and_then(|cursor| TailCursor::from_bytes(&cursor)),
+            ..TailOptions::default()
+        };
+        let lines = self.tail_file(path, options).await?;
+        Ok((lines, None))
+    }
+}
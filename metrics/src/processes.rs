@@ -0,0 +1,153 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use serde::Deserialize;
+use sysinfo::{Pid, Process, System};
+
+use crate::alerts::{AlertEvent, AlertState};
+
+/// Opt-in process monitoring, disabled by default.
+///
+/// ```toml
+/// [plugins.nexus-metrics.settings.processes]
+/// top = 5
+///
+/// [[plugins.nexus-metrics.settings.processes.watch]]
+/// name = "nginx"
+/// pid_file = "/run/nginx.pid"
+/// memory_limit_mb = 512
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProcessesConfig {
+    /// How many processes to list by CPU and by memory, none if zero
+    pub top: usize,
+    pub watch: Vec<WatchedProcess>,
+}
+
+impl ProcessesConfig {
+    pub const fn enabled(&self) -> bool {
+        self.top > 0 || !self.watch.is_empty()
+    }
+}
+
+/// Process that raises an alert when it disappears, restarts or uses
+/// too much memory.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WatchedProcess {
+    /// Executable name, matched unless there's a `pid_file`
+    pub name: String,
+    /// Read through the host, so it needs a read permission
+    pub pid_file: Option<PathBuf>,
+    pub memory_limit_mb: Option<u64>,
+}
+
+pub struct ProcessMetrics {
+    pub pid: u32,
+    pub name: Box<str>,
+    pub cpu: f32,    // percent, of a single core
+    pub memory: u64, // bytes
+}
+
+impl ProcessMetrics {
+    fn new(process: &Process) -> Self {
+        Self {
+            pid: process.pid().as_u32(),
+            name: process.name().to_string_lossy().into(),
+            cpu: process.cpu_usage(),
+            memory: process.memory(),
+        }
+    }
+}
+
+pub struct TopProcesses {
+    pub by_cpu: Vec<ProcessMetrics>,
+    pub by_memory: Vec<ProcessMetrics>,
+}
+
+/// State of a watched process, down if there's no PID
+pub struct WatchedMetrics {
+    pub pid: Option<u32>,
+    /// PID it had at the previous collection, if it restarted since
+    pub restarted_from: Option<u32>,
+    /// Of all its matching processes, bytes
+    pub memory: u64,
+}
+
+impl WatchedMetrics {
+    /// One-off alert if the process restarted since the last collection
+    pub fn restart(&self, name: &str) -> Option<AlertEvent> {
+        let previous = self.restarted_from?;
+        Some(AlertEvent {
+            rule: format!("{name} restarted, was PID {previous}"),
+            series: format!("process.{name}.pid"),
+            value: f64::from(self.pid?),
+            threshold: None,
+            state: AlertState::Triggered,
+        })
+    }
+}
+
+/// The `n` processes using the most CPU and memory
+pub fn top(system: &System, n: usize) -> TopProcesses {
+    let mut processes = system.processes().values().collect::<Vec<_>>();
+
+    processes.sort_by(|a, b| b.cpu_usage().total_cmp(&a.cpu_usage()));
+    let by_cpu = processes.iter().take(n).map(|p| ProcessMetrics::new(p));
+    let by_cpu = by_cpu.collect();
+
+    processes.sort_by_key(|p| std::cmp::Reverse(p.memory()));
+    let by_memory = processes.iter().take(n).map(|p| ProcessMetrics::new(p));
+    let by_memory = by_memory.collect();
+
+    TopProcesses { by_cpu, by_memory }
+}
+
+/// Looks up each watched process, given the PIDs in their pid files and
+/// the ones found last time
+pub fn watched(
+    system: &System,
+    watch: &[WatchedProcess],
+    pids: &HashMap<Box<str>, u32>,
+    previous: &HashMap<Box<str>, u32>,
+) -> HashMap<Box<str>, WatchedMetrics> {
+    watch
+        .iter()
+        .map(|watched| {
+            let (pid, memory) = watched.pid_file.as_ref().map_or_else(
+                || by_name(system, &watched.name),
+                |_| {
+                    let process = pids
+                        .get(watched.name.as_str())
+                        .and_then(|pid| system.process(Pid::from_u32(*pid)));
+                    let pid = process.map(|p| p.pid().as_u32());
+                    (pid, process.map_or(0, Process::memory))
+                },
+            );
+            let restarted_from = previous
+                .get(watched.name.as_str())
+                .copied()
+                .filter(|previous| pid.is_some_and(|pid| pid != *previous));
+            let metrics = WatchedMetrics {
+                pid,
+                restarted_from,
+                memory,
+            };
+            (Box::from(watched.name.as_str()), metrics)
+        })
+        .collect()
+}
+
+/// The oldest process named `name`, and the memory of all of them,
+/// as a service's workers are usually forked from its main process
+fn by_name(system: &System, name: &str) -> (Option<u32>, u64) {
+    let matching = system
+        .processes_by_exact_name(name.as_ref())
+        .collect::<Vec<_>>();
+    let pid = matching
+        .iter()
+        .min_by_key(|p| (p.start_time(), p.pid()))
+        .map(|p| p.pid().as_u32());
+    let memory = matching.iter().map(|p| p.memory()).sum();
+    (pid, memory)
+}
@@ -0,0 +1,413 @@
+use std::fmt;
+use std::time::UNIX_EPOCH;
+
+use nexus_utils::prometheus::{self, gauge, sample};
+
+use crate::docker::ContainerMetrics;
+use crate::sys_info::{Metrics, NetworkMetrics};
+
+pub use prometheus::CONTENT_TYPE;
+
+/// Name, help and value of the series exposed per network interface
+type NetworkSeries = (&'static str, &'static str, fn(&NetworkMetrics) -> f64);
+
+const NETWORK_SERIES: [NetworkSeries; 4] = [
+    (
+        "nexus_network_receive_bytes_per_second",
+        "Bytes received per second.",
+        |n| n.bytes_received,
+    ),
+    (
+        "nexus_network_transmit_bytes_per_second",
+        "Bytes transmitted per second.",
+        |n| n.bytes_transmitted,
+    ),
+    (
+        "nexus_network_receive_errors_percent",
+        "Share of received packets with errors.",
+        |n| n.received_error_percentage,
+    ),
+    (
+        "nexus_network_transmit_errors_percent",
+        "Share of transmitted packets with errors.",
+        |n| n.transmit_error_percentage,
+    ),
+];
+
+/// Name, help and value, if known, of the series exposed per container
+type ContainerSeries =
+    (&'static str, &'static str, fn(&ContainerMetrics) -> Option<f64>);
+
+#[allow(clippy::cast_precision_loss)]
+const CONTAINER_SERIES: [ContainerSeries; 8] = [
+    ("nexus_container_up", "Whether a container is running.", |c| {
+        Some(if c.is_running() { 1.0 } else { 0.0 })
+    }),
+    (
+        "nexus_container_healthy",
+        "Whether a container passes its health check.",
+        |c| {
+            let health = c.health.as_deref()?;
+            Some(if health == "unhealthy" { 0.0 } else { 1.0 })
+        },
+    ),
+    (
+        "nexus_container_restarts",
+        "Times a container was restarted by its restart policy.",
+        |c| Some(c.restart_count as f64),
+    ),
+    (
+        "nexus_container_cpu_usage_percent",
+        "CPU usage of a container, per core.",
+        |c| c.cpu,
+    ),
+    (
+        "nexus_container_memory_used_bytes",
+        "Memory a container uses, without its page cache.",
+        |c| c.memory.as_ref().map(|m| m.used as f64),
+    ),
+    (
+        "nexus_container_memory_limit_bytes",
+        "Memory a container may use.",
+        |c| c.memory.as_ref().map(|m| m.total as f64),
+    ),
+    (
+        "nexus_container_network_receive_bytes_per_second",
+        "Bytes a container received per second.",
+        |c| c.network.as_ref().map(|n| n.bytes_received),
+    ),
+    (
+        "nexus_container_network_transmit_bytes_per_second",
+        "Bytes a container transmitted per second.",
+        |c| c.network.as_ref().map(|n| n.bytes_transmitted),
+    ),
+];
+
+/// Renders `metrics` in the Prometheus text exposition format.
+pub fn render(metrics: &Metrics) -> String {
+    prometheus::render(|out| write_metrics(out, metrics))
+}
+
+fn write_metrics(out: &mut String, metrics: &Metrics) -> fmt::Result {
+    gauge(out, "nexus_cpu_usage_percent", "Global CPU usage.")?;
+    sample(out, "nexus_cpu_usage_percent", &[], f64::from(metrics.cpu))?;
+
+    gauge(out, "nexus_memory_used_bytes", "Memory in use.")?;
+    #[allow(clippy::cast_precision_loss)]
+    sample(out, "nexus_memory_used_bytes", &[], metrics.ram.used as f64)?;
+    gauge(out, "nexus_memory_total_bytes", "Total memory.")?;
+    #[allow(clippy::cast_precision_loss)]
+    sample(
+        out,
+        "nexus_memory_total_bytes",
+        &[],
+        metrics.ram.total as f64,
+    )?;
+
+    write_optional(out, metrics)?;
+    write_processes(out, metrics)?;
+    write_containers(out, metrics)?;
+
+    if let Some(disks) = &metrics.disks {
+        let mut disks = disks.iter().collect::<Vec<_>>();
+        disks.sort_by_key(|(name, _)| *name);
+
+        gauge(out, "nexus_disk_used_bytes", "Disk space in use.")?;
+        for (name, disk) in &disks {
+            let labels = [("device", &***name)];
+            #[allow(clippy::cast_precision_loss)]
+            sample(out, "nexus_disk_used_bytes", &labels, disk.used as f64)?;
+        }
+        gauge(out, "nexus_disk_total_bytes", "Total disk space.")?;
+        for (name, disk) in &disks {
+            let labels = [("device", &***name)];
+            #[allow(clippy::cast_precision_loss)]
+            sample(out, "nexus_disk_total_bytes", &labels, disk.total as f64)?;
+        }
+    }
+
+    if let Some(interfaces) = &metrics.net_interfaces {
+        let mut interfaces = interfaces.iter().collect::<Vec<_>>();
+        interfaces.sort_by_key(|(name, _)| *name);
+
+        for (name, help, value) in NETWORK_SERIES {
+            gauge(out, name, help)?;
+            for (interface, network) in &interfaces {
+                let labels = [("interface", &***interface)];
+                sample(out, name, &labels, value(network))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Series of the metrics that are only collected if enabled
+fn write_optional(out: &mut String, metrics: &Metrics) -> fmt::Result {
+    if let Some(cores) = &metrics.cpu_cores {
+        let name = "nexus_cpu_core_usage_percent";
+        gauge(out, name, "CPU usage of each core.")?;
+        for (core, usage) in cores.iter().enumerate() {
+            let core = core.to_string();
+            sample(out, name, &[("core", &core)], f64::from(*usage))?;
+        }
+    }
+
+    if let Some(load) = &metrics.load_average {
+        for (name, minutes, value) in [
+            ("nexus_load1", 1, load.one),
+            ("nexus_load5", 5, load.five),
+            ("nexus_load15", 15, load.fifteen),
+        ] {
+            let help = format!("{minutes}m load average.");
+            gauge(out, name, &help)?;
+            sample(out, name, &[], value)?;
+        }
+    }
+
+    if let Some(swap) = &metrics.swap {
+        gauge(out, "nexus_swap_used_bytes", "Swap in use.")?;
+        #[allow(clippy::cast_precision_loss)]
+        sample(out, "nexus_swap_used_bytes", &[], swap.used as f64)?;
+        gauge(out, "nexus_swap_total_bytes", "Total swap.")?;
+        #[allow(clippy::cast_precision_loss)]
+        sample(out, "nexus_swap_total_bytes", &[], swap.total as f64)?;
+    }
+
+    if let Some(uptime) = &metrics.uptime {
+        let boot_time = uptime
+            .boot_time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        gauge(out, "nexus_uptime_seconds", "Time since boot.")?;
+        sample(
+            out,
+            "nexus_uptime_seconds",
+            &[],
+            uptime.uptime.as_secs_f64(),
+        )?;
+        gauge(out, "nexus_boot_time_seconds", "Boot time, as a timestamp.")?;
+        sample(out, "nexus_boot_time_seconds", &[], boot_time.as_secs_f64())?;
+    }
+
+    if let Some(temperatures) = &metrics.temperatures {
+        let mut temperatures = temperatures.iter().collect::<Vec<_>>();
+        temperatures.sort_by_key(|(sensor, _)| *sensor);
+
+        let name = "nexus_temperature_celsius";
+        gauge(out, name, "Temperature of each sensor.")?;
+        for (sensor, celsius) in temperatures {
+            let labels = [("sensor", &**sensor)];
+            sample(out, name, &labels, f64::from(*celsius))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Series of the Docker containers, if enabled
+fn write_containers(out: &mut String, metrics: &Metrics) -> fmt::Result {
+    let Some(containers) = &metrics.containers else {
+        return Ok(());
+    };
+    let mut containers = containers.iter().collect::<Vec<_>>();
+    containers.sort_by_key(|(name, _)| *name);
+
+    for (name, help, value) in CONTAINER_SERIES {
+        gauge(out, name, help)?;
+        for (container, metrics) in &containers {
+            if let Some(value) = value(metrics) {
+                let labels = [("name", &***container)];
+                sample(out, name, &labels, value)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Series of the top and watched processes, if enabled
+fn write_processes(out: &mut String, metrics: &Metrics) -> fmt::Result {
+    if let Some(top) = &metrics.top_processes {
+        // A process may be in both lists
+        let mut processes = top
+            .by_cpu
+            .iter()
+            .chain(&top.by_memory)
+            .map(|p| (p.pid, p))
+            .collect::<Vec<_>>();
+        processes.sort_by_key(|(pid, _)| *pid);
+        processes.dedup_by_key(|(pid, _)| *pid);
+
+        let name = "nexus_process_cpu_percent";
+        gauge(out, name, "CPU usage of the top processes, per core.")?;
+        for (pid, process) in &processes {
+            let pid = pid.to_string();
+            let labels = [("pid", pid.as_str()), ("name", &*process.name)];
+            sample(out, name, &labels, f64::from(process.cpu))?;
+        }
+        let name = "nexus_process_memory_bytes";
+        gauge(out, name, "Memory of the top processes.")?;
+        for (pid, process) in &processes {
+            let pid = pid.to_string();
+            let labels = [("pid", pid.as_str()), ("name", &*process.name)];
+            #[allow(clippy::cast_precision_loss)]
+            sample(out, name, &labels, process.memory as f64)?;
+        }
+    }
+
+    if let Some(watched) = &metrics.watched_processes {
+        let mut watched = watched.iter().collect::<Vec<_>>();
+        watched.sort_by_key(|(name, _)| *name);
+
+        let name = "nexus_watched_process_up";
+        gauge(out, name, "Whether a watched process is running.")?;
+        for (process, metrics) in &watched {
+            let up = if metrics.pid.is_some() { 1.0 } else { 0.0 };
+            sample(out, name, &[("name", process)], up)?;
+        }
+        let name = "nexus_watched_process_memory_bytes";
+        gauge(out, name, "Memory of a watched process and its workers.")?;
+        for (process, metrics) in &watched {
+            #[allow(clippy::cast_precision_loss)]
+            sample(out, name, &[("name", process)], metrics.memory as f64)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::sys_info::MemoryMetrics;
+
+    use super::*;
+
+    const fn memory(used: u64, total: u64) -> MemoryMetrics {
+        MemoryMetrics { used, total }
+    }
+
+    fn container(running: bool) -> ContainerMetrics {
+        ContainerMetrics {
+            image: "postgres:17".into(),
+            state: if running { "running" } else { "exited" }.into(),
+            health: running.then(|| "healthy".into()),
+            restart_count: if running { 2 } else { 0 },
+            cpu: running.then_some(1.5),
+            memory: running.then(|| memory(100, 200)),
+            network: running.then_some(NetworkMetrics {
+                bytes_received: 10.0,
+                bytes_transmitted: 20.0,
+                received_error_percentage: 0.0,
+                transmit_error_percentage: 0.0,
+            }),
+            exited: None,
+            restarted: false,
+        }
+    }
+
+    fn metrics() -> Metrics {
+        Metrics {
+            cpu: 12.5,
+            cpu_cores: Some(vec![10.0, 15.0]),
+            load_average: None,
+            ram: memory(1_024, 4_096),
+            swap: None,
+            uptime: None,
+            temperatures: Some(HashMap::from([("acpitz".into(), 45.5)])),
+            disks: Some(HashMap::from([
+                ("/mnt/\"backup\"".into(), memory(1, 2)),
+                ("/dev/sda1".into(), memory(10, 20)),
+            ])),
+            net_interfaces: Some(HashMap::from([(
+                "eth0".into(),
+                NetworkMetrics {
+                    bytes_received: 100.0,
+                    bytes_transmitted: 50.5,
+                    // Nothing received, so a share of nothing
+                    received_error_percentage: f64::INFINITY,
+                    transmit_error_percentage: 0.0,
+                },
+            )])),
+            top_processes: None,
+            watched_processes: None,
+            containers: Some(HashMap::from([
+                ("web".into(), container(true)),
+                ("db".into(), container(false)),
+            ])),
+        }
+    }
+
+    #[test]
+    fn renders_metrics() {
+        let out = render(&metrics());
+        let samples = out
+            .lines()
+            .filter(|line| !line.starts_with("# "))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            samples,
+            [
+                "nexus_cpu_usage_percent 12.5",
+                "nexus_memory_used_bytes 1024",
+                "nexus_memory_total_bytes 4096",
+                "nexus_cpu_core_usage_percent{core=\"0\"} 10",
+                "nexus_cpu_core_usage_percent{core=\"1\"} 15",
+                "nexus_temperature_celsius{sensor=\"acpitz\"} 45.5",
+                "nexus_container_up{name=\"db\"} 0",
+                "nexus_container_up{name=\"web\"} 1",
+                // No health check, nor stats, for a stopped container
+                "nexus_container_healthy{name=\"web\"} 1",
+                "nexus_container_restarts{name=\"db\"} 0",
+                "nexus_container_restarts{name=\"web\"} 2",
+                "nexus_container_cpu_usage_percent{name=\"web\"} 1.5",
+                "nexus_container_memory_used_bytes{name=\"web\"} 100",
+                "nexus_container_memory_limit_bytes{name=\"web\"} 200",
+                "nexus_container_network_receive_bytes_per_second{name=\"web\"} 10",
+                "nexus_container_network_transmit_bytes_per_second{name=\"web\"} 20",
+                "nexus_disk_used_bytes{device=\"/dev/sda1\"} 10",
+                "nexus_disk_used_bytes{device=\"/mnt/\\\"backup\\\"\"} 1",
+                "nexus_disk_total_bytes{device=\"/dev/sda1\"} 20",
+                "nexus_disk_total_bytes{device=\"/mnt/\\\"backup\\\"\"} 2",
+                "nexus_network_receive_bytes_per_second{interface=\"eth0\"} 100",
+                "nexus_network_transmit_bytes_per_second{interface=\"eth0\"} 50.5",
+                "nexus_network_receive_errors_percent{interface=\"eth0\"} +Inf",
+                "nexus_network_transmit_errors_percent{interface=\"eth0\"} 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn describes_every_series_once() {
+        let out = render(&metrics());
+        let mut described = out
+            .lines()
+            .filter_map(|line| line.strip_prefix("# TYPE "))
+            .collect::<Vec<_>>();
+        assert!(described.iter().all(|line| line.ends_with(" gauge")));
+        assert_eq!(described.len(), 19);
+        described.sort_unstable();
+        described.dedup();
+        assert_eq!(described.len(), 19);
+        assert!(out.contains(
+            "# HELP nexus_cpu_usage_percent Global CPU usage.\n\
+             # TYPE nexus_cpu_usage_percent gauge\n\
+             nexus_cpu_usage_percent 12.5\n"
+        ));
+    }
+
+    #[test]
+    fn leaves_out_what_is_not_collected() {
+        let metrics = Metrics {
+            cpu_cores: None,
+            temperatures: None,
+            disks: None,
+            net_interfaces: None,
+            containers: None,
+            ..metrics()
+        };
+        let out = render(&metrics);
+        assert_eq!(out.lines().filter(|l| !l.starts_with("# ")).count(), 3);
+    }
+}
@@ -0,0 +1,90 @@
+use nexus_api::StorageRef;
+use nexus_utils::discord::Webhook;
+use tracing::{info, warn};
+
+use crate::config::Config;
+
+/// Key under which the edited message's ID is kept across restarts
+const MESSAGE_ID_KEY: &[u8] = b"status.message_id";
+
+/// Status embed posted to Discord at every update, either as a new message
+/// or, with `update_previous_message`, by editing a single one in place.
+pub struct StatusMessage {
+    webhook: Webhook,
+    storage: Option<StorageRef>,
+    /// Message edited in place, once known
+    message_id: Option<String>,
+}
+
+impl StatusMessage {
+    /// The message to edit is the last one the plugin sent, or else the
+    /// configured `message_id`
+    pub fn new(
+        webhook: Webhook,
+        storage: Option<StorageRef>,
+        config: &Config,
+    ) -> Self {
+        let stored = storage.as_ref().and_then(|storage| {
+            storage
+                .get(MESSAGE_ID_KEY)
+                .inspect_err(|e| warn!("Failed to read status message: {e}"))
+                .ok()
+                .flatten()
+                .and_then(|id| String::from_utf8(id).ok())
+        });
+        let message_id = config
+            .update_previous_message
+            .then(|| stored.or_else(|| config.message_id.clone()))
+            .flatten();
+
+        Self {
+            webhook,
+            storage,
+            message_id,
+        }
+    }
+
+    /// Posts `report`, failures are only logged
+    pub async fn post(&mut self, config: &Config, report: String) {
+        let embed = serde_json::json!({
+            "title": config.embed_title,
+            "description": report,
+            "color": config.embed_color,
+        });
+        let content = config.message_content();
+
+        if let Some(id) = &self.message_id {
+            let edited = self
+                .webhook
+                .edit(id, content.clone(), vec![embed.clone()])
+                .await;
+            match edited {
+                Ok(()) => return,
+                Err(e) if e.is_not_found() => {
+                    info!("Status message {id} is gone, sending a new one");
+                }
+                Err(e) => {
+                    warn!("Failed to update status message: {e}");
+                    return;
+                }
+            }
+        }
+
+        match self.webhook.send(content, vec![embed]).await {
+            Ok(id) if config.update_previous_message => self.remember(id),
+            Ok(_) => {}
+            Err(e) => warn!("Failed to post status update: {e}"),
+        }
+    }
+
+    fn remember(&mut self, id: String) {
+        if let Some(storage) = &self.storage
+            && let Err(e) = storage.put(MESSAGE_ID_KEY, id.as_bytes())
+        {
+            warn!(
+                "Failed to save status message, a new one will be sent on restart: {e}"
+            );
+        }
+        self.message_id = Some(id);
+    }
+}
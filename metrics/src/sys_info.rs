@@ -1,15 +1,119 @@
 use std::{
-    collections::{HashMap, HashSet},
-    fmt::Display,
+    collections::HashMap,
+    fmt::{Display, Write},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use sysinfo::{
+    Components, CpuRefreshKind, Disks, LoadAvg, MemoryRefreshKind,
+    NetworkData, Networks, ProcessRefreshKind, ProcessesToUpdate, RefreshKind,
+    System,
 };
-use sysinfo::{Disks, Networks, RefreshKind, System};
 
-use crate::DELAY_SECS;
+use crate::config::Config;
+use crate::docker::ContainerMetrics;
+use crate::processes::{self, TopProcesses, WatchedMetrics};
 
 pub struct SysInfo {
     system: System,
     networks: Networks,
     disks: Disks,
+    /// Sensors, only refreshed if temperatures are enabled
+    components: Components,
+    /// What to collect, and from which disks and interfaces
+    config: Arc<Config>,
+    /// Counters of each interface at the previous collection
+    counters: HashMap<String, NetworkCounters>,
+    /// PIDs of the watched processes at the previous collection
+    watched_pids: HashMap<Box<str>, u32>,
+}
+
+/// Cumulative counters of an interface, as of `at`
+struct NetworkCounters {
+    at: Instant,
+    received: u64,
+    transmitted: u64,
+    packets_received: u64,
+    packets_transmitted: u64,
+    errors_on_received: u64,
+    errors_on_transmitted: u64,
+}
+impl NetworkCounters {
+    fn read(at: Instant, data: &NetworkData) -> Self {
+        Self {
+            at,
+            received: data.total_received(),
+            transmitted: data.total_transmitted(),
+            packets_received: data.total_packets_received(),
+            packets_transmitted: data.total_packets_transmitted(),
+            errors_on_received: data.total_errors_on_received(),
+            errors_on_transmitted: data.total_errors_on_transmitted(),
+        }
+    }
+
+    /// Rates since `previous`, none if any counter went backwards,
+    /// e.g. the interface was recreated
+    fn rates_since(&self, previous: &Self) -> Option<NetworkMetrics> {
+        let elapsed = self.at.checked_duration_since(previous.at)?;
+        if elapsed.is_zero() {
+            return None;
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let delta = |now: u64, before: u64| {
+            now.checked_sub(before).map(|delta| delta as f64)
+        };
+        let percentage = |errors: f64, packets: f64| {
+            if packets == 0.0 {
+                0.0
+            } else {
+                100.0 * errors / packets
+            }
+        };
+
+        let received = delta(self.received, previous.received)?;
+        let transmitted = delta(self.transmitted, previous.transmitted)?;
+        let packets_received =
+            delta(self.packets_received, previous.packets_received)?;
+        let packets_transmitted =
+            delta(self.packets_transmitted, previous.packets_transmitted)?;
+        let errors_on_received =
+            delta(self.errors_on_received, previous.errors_on_received)?;
+        let errors_on_transmitted =
+            delta(self.errors_on_transmitted, previous.errors_on_transmitted)?;
+
+        let elapsed = elapsed.as_secs_f64();
+        Some(NetworkMetrics {
+            bytes_received: received / elapsed,
+            bytes_transmitted: transmitted / elapsed,
+            received_error_percentage: percentage(
+                errors_on_received,
+                packets_received,
+            ),
+            transmit_error_percentage: percentage(
+                errors_on_transmitted,
+                packets_transmitted,
+            ),
+        })
+    }
+}
+
+/// Rates of each interface since `previous`, which `readings` then replace.
+/// Rates need a previous sample, from before any reset, so there's none for
+/// new interfaces or the ones whose counters went backwards.
+fn network_rates(
+    previous: &mut HashMap<String, NetworkCounters>,
+    readings: HashMap<String, NetworkCounters>,
+) -> HashMap<Box<str>, NetworkMetrics> {
+    let rates = readings
+        .iter()
+        .filter_map(|(name, current)| {
+            let metrics = current.rates_since(previous.get(name)?)?;
+            Some((Box::from(name.as_str()), metrics))
+        })
+        .collect();
+    // Interfaces that went away start over if they come back
+    *previous = readings;
+    rates
 }
 
 pub struct NetworkMetrics {
@@ -31,42 +135,201 @@ impl MemoryMetrics {
         100.0 * used / total
     }
 }
-/// All units are in bytes
+pub struct Uptime {
+    pub uptime: Duration,
+    pub boot_time: SystemTime,
+}
+/// All units are in bytes, the optional metrics are only collected
+/// if enabled in the config
 pub struct Metrics {
     pub cpu: f32,
+    pub cpu_cores: Option<Vec<f32>>,
+    pub load_average: Option<LoadAvg>,
     pub ram: MemoryMetrics, // bytes
+    pub swap: Option<MemoryMetrics>, // bytes
+    pub uptime: Option<Uptime>,
+    /// Degrees Celsius, by sensor
+    pub temperatures: Option<HashMap<Box<str>, f32>>,
     pub disks: Option<HashMap<Box<str>, MemoryMetrics>>,
     pub net_interfaces: Option<HashMap<Box<str>, NetworkMetrics>>,
+    pub top_processes: Option<TopProcesses>,
+    /// By name, as configured
+    pub watched_processes: Option<HashMap<Box<str>, WatchedMetrics>>,
+    /// By name, filled in from the Docker Engine after collecting the rest
+    pub containers: Option<HashMap<Box<str>, ContainerMetrics>>,
 }
-impl Display for Metrics {
+impl Metrics {
+    /// Every value as a named series, e.g. `disk./dev/sda1.used`
+    pub fn samples(&self) -> Vec<(String, f64)> {
+        let mut samples = vec![
+            ("cpu".into(), f64::from(self.cpu)),
+            #[allow(clippy::cast_precision_loss)]
+            ("memory.used".into(), self.ram.used as f64),
+            ("memory.percent".into(), self.ram.percentage()),
+        ];
+        for (core, usage) in self.cpu_cores.iter().flatten().enumerate() {
+            samples.push((format!("cpu.{core}"), f64::from(*usage)));
+        }
+        if let Some(load) = &self.load_average {
+            samples.extend([
+                ("load.1".into(), load.one),
+                ("load.5".into(), load.five),
+                ("load.15".into(), load.fifteen),
+            ]);
+        }
+        if let Some(swap) = &self.swap {
+            #[allow(clippy::cast_precision_loss)]
+            samples.push(("swap.used".into(), swap.used as f64));
+            samples.push(("swap.percent".into(), swap.percentage()));
+        }
+        if let Some(uptime) = &self.uptime {
+            samples.push(("uptime".into(), uptime.uptime.as_secs_f64()));
+        }
+        for (sensor, celsius) in self.temperatures.iter().flatten() {
+            samples.push((format!("temp.{sensor}"), f64::from(*celsius)));
+        }
+        for (name, watched) in self.watched_processes.iter().flatten() {
+            let up = if watched.pid.is_some() { 1.0 } else { 0.0 };
+            samples.push((format!("process.{name}.up"), up));
+            #[allow(clippy::cast_precision_loss)]
+            samples.push((format!("process.{name}.memory"), watched.memory as f64));
+        }
+        for (name, container) in self.containers.iter().flatten() {
+            container_samples(&mut samples, name, container);
+        }
+        for (name, disk) in self.disks.iter().flatten() {
+            #[allow(clippy::cast_precision_loss)]
+            samples.push((format!("disk.{name}.used"), disk.used as f64));
+            samples.push((format!("disk.{name}.percent"), disk.percentage()));
+        }
+        for (name, net) in self.net_interfaces.iter().flatten() {
+            samples.extend([
+                (format!("net.{name}.rx"), net.bytes_received),
+                (format!("net.{name}.tx"), net.bytes_transmitted),
+                (format!("net.{name}.rx_errors"), net.received_error_percentage),
+                (format!("net.{name}.tx_errors"), net.transmit_error_percentage),
+            ]);
+        }
+        samples
+    }
+}
+
+fn container_samples(
+    samples: &mut Vec<(String, f64)>,
+    name: &str,
+    container: &ContainerMetrics,
+) {
+    let flag = |set: bool| if set { 1.0 } else { 0.0 };
+    let up = flag(container.is_running());
+    samples.push((format!("container.{name}.up"), up));
+    // Only containers with a health check have one
+    if let Some(health) = &container.health {
+        let healthy = flag(health != "unhealthy");
+        samples.push((format!("container.{name}.healthy"), healthy));
+    }
+    if let Some(cpu) = container.cpu {
+        samples.push((format!("container.{name}.cpu"), cpu));
+    }
+    if let Some(memory) = &container.memory {
+        #[allow(clippy::cast_precision_loss)]
+        samples.push((format!("container.{name}.memory"), memory.used as f64));
+        samples.push((
+            format!("container.{name}.memory.percent"),
+            memory.percentage(),
+        ));
+    }
+    if let Some(network) = &container.network {
+        samples.extend([
+            (format!("container.{name}.rx"), network.bytes_received),
+            (format!("container.{name}.tx"), network.bytes_transmitted),
+        ]);
+    }
+}
+
+/// Human-readable summary of [`Metrics`], showing what `config` enables.
+pub struct Report<'a> {
+    pub metrics: &'a Metrics,
+    pub config: &'a Config,
+}
+impl Display for Report<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self { metrics, config } = self;
+        // Each section starts on a new line, the first one is trimmed
+        let mut out = String::new();
+
+        // Uptime
+        if let Some(Uptime { uptime, boot_time }) = &metrics.uptime {
+            write!(
+                out,
+                "**Uptime:** {} (since {})",
+                humantime::format_duration(*uptime),
+                humantime::format_rfc3339_seconds(*boot_time)
+            )?;
+        }
+
         // CPU
-        write!(f, "**CPU:** {:.2}%", self.cpu)?;
+        if config.show_cpu {
+            write!(out, "\n**CPU:** {:.2}%", metrics.cpu)?;
+        }
+        if let Some(cores) = &metrics.cpu_cores {
+            write!(out, "\n**CPU cores:**")?;
+            for usage in cores {
+                write!(out, " {usage:.0}%")?;
+            }
+        }
+        if let Some(LoadAvg { one, five, fifteen }) = &metrics.load_average {
+            write!(out, "\n**Load average:** {one:.2}, {five:.2}, {fifteen:.2}")?;
+        }
 
         // RAM
+        let fmt_memory = if config.memory_in_mb {
+            fmt_mebibytes
+        } else {
+            fmt_unit
+        };
         #[allow(clippy::cast_precision_loss)]
-        let total = self.ram.total as f64;
-        if total != 0.0 {
+        let total = metrics.ram.total as f64;
+        if config.show_memory && total != 0.0 {
             #[allow(clippy::cast_precision_loss)]
             write!(
-                f,
+                out,
                 "\n**Memory:** {}/{} ({:.2}%)",
-                fmt_unit(self.ram.used as f64),
-                fmt_unit(total),
-                self.ram.percentage()
+                fmt_memory(metrics.ram.used as f64),
+                fmt_memory(total),
+                metrics.ram.percentage()
+            )?;
+        }
+        if let Some(swap) = &metrics.swap {
+            #[allow(clippy::cast_precision_loss)]
+            write!(
+                out,
+                "\n**Swap:** {}/{} ({:.2}%)",
+                fmt_memory(swap.used as f64),
+                fmt_memory(swap.total as f64),
+                swap.percentage()
             )?;
         }
 
+        // Processes and containers
+        write_processes(&mut out, metrics, fmt_memory)?;
+        write_containers(&mut out, metrics, fmt_memory)?;
+
+        // Temperatures
+        write_temperatures(&mut out, metrics)?;
+
         // Disk
-        if let Some(disks) = &self.disks {
-            write!(f, "\n**Disks:**")?;
+        if let Some(disks) =
+            metrics.disks.as_ref().filter(|_| config.show_disk_usage)
+        {
+            write!(out, "\n**Disks:**")?;
             for (name, MemoryMetrics { used, total }) in disks {
+                let name = config.disk_name(name);
                 #[allow(clippy::cast_precision_loss)]
                 let used = *used as f64;
                 #[allow(clippy::cast_precision_loss)]
                 let total = *total as f64;
                 write!(
-                    f,
+                    out,
                     "\n- {name}: {}/{} ({:.2}%)",
                     fmt_unit(used),
                     fmt_unit(total),
@@ -76,8 +339,12 @@ impl Display for Metrics {
         }
 
         // Network
-        if let Some(network) = &self.net_interfaces {
-            write!(f, "\n**Network interfaces:**")?;
+        if let Some(network) = metrics
+            .net_interfaces
+            .as_ref()
+            .filter(|_| config.show_network_usage)
+        {
+            write!(out, "\n**Network interfaces:**")?;
             for (
                 name,
                 NetworkMetrics {
@@ -90,97 +357,216 @@ impl Display for Metrics {
             {
                 let received = fmt_unit_net(*bytes_received);
                 let transmitted = fmt_unit_net(*bytes_transmitted);
-                write!(f, "\n- {name}: {received} in, {received_error_percentage:.2}% errors.")?;
-                write!(f, " {transmitted} out, {transmit_error_percentage:.2}% errors.")?;
+                write!(out, "\n- {name}: {received} in, {received_error_percentage:.2}% errors.")?;
+                write!(out, " {transmitted} out, {transmit_error_percentage:.2}% errors.")?;
             }
         }
 
-        Ok(())
+        f.write_str(out.trim_start_matches('\n'))
+    }
+}
+
+fn write_processes(
+    out: &mut String,
+    metrics: &Metrics,
+    fmt_memory: fn(f64) -> String,
+) -> std::fmt::Result {
+    if let Some(TopProcesses { by_cpu, by_memory }) = &metrics.top_processes {
+        write!(out, "\n**Top processes by CPU:**")?;
+        for process in by_cpu {
+            let (name, pid, cpu) = (&process.name, process.pid, process.cpu);
+            write!(out, "\n- {name} ({pid}): {cpu:.2}%")?;
+        }
+        write!(out, "\n**Top processes by memory:**")?;
+        for process in by_memory {
+            let (name, pid) = (&process.name, process.pid);
+            #[allow(clippy::cast_precision_loss)]
+            let memory = fmt_memory(process.memory as f64);
+            write!(out, "\n- {name} ({pid}): {memory}")?;
+        }
+    }
+
+    if let Some(watched) = &metrics.watched_processes {
+        let mut watched = watched.iter().collect::<Vec<_>>();
+        watched.sort_by_key(|(name, _)| *name);
+        write!(out, "\n**Watched processes:**")?;
+        for (name, WatchedMetrics { pid, memory, .. }) in watched {
+            if let Some(pid) = pid {
+                #[allow(clippy::cast_precision_loss)]
+                let memory = fmt_memory(*memory as f64);
+                write!(out, "\n- {name}: PID {pid}, {memory}")?;
+            } else {
+                write!(out, "\n- {name}: down")?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn write_temperatures(
+    out: &mut String,
+    metrics: &Metrics,
+) -> std::fmt::Result {
+    let Some(temperatures) = &metrics.temperatures else {
+        return Ok(());
+    };
+    let mut temperatures = temperatures.iter().collect::<Vec<_>>();
+    temperatures.sort_by_key(|(sensor, _)| *sensor);
+    write!(out, "\n**Temperatures:**")?;
+    for (sensor, celsius) in temperatures {
+        write!(out, "\n- {sensor}: {celsius:.1}°C")?;
+    }
+    Ok(())
+}
+
+fn write_containers(
+    out: &mut String,
+    metrics: &Metrics,
+    fmt_memory: fn(f64) -> String,
+) -> std::fmt::Result {
+    let Some(containers) = &metrics.containers else {
+        return Ok(());
+    };
+    let mut containers = containers.iter().collect::<Vec<_>>();
+    containers.sort_by_key(|(name, _)| *name);
+    write!(out, "\n**Containers:**")?;
+    for (name, container) in containers {
+        write!(out, "\n- {name} ({}): {}", container.image, container.state)?;
+        if let Some(health) = &container.health {
+            write!(out, ", {health}")?;
+        }
+        if let Some(cpu) = container.cpu {
+            write!(out, ", CPU {cpu:.2}%")?;
+        }
+        if let Some(MemoryMetrics { used, total }) = &container.memory {
+            #[allow(clippy::cast_precision_loss)]
+            let (used, total) = (*used as f64, *total as f64);
+            write!(out, ", {}/{}", fmt_memory(used), fmt_memory(total))?;
+        }
+        if let Some(network) = &container.network {
+            let received = fmt_unit_net(network.bytes_received);
+            let transmitted = fmt_unit_net(network.bytes_transmitted);
+            write!(out, ", {received} in, {transmitted} out")?;
+        }
     }
+    Ok(())
 }
 
 impl SysInfo {
-    pub fn new() -> Self {
+    pub fn new(config: Arc<Config>) -> Self {
         Self {
             system: System::new_with_specifics(
                 RefreshKind::everything().without_processes(),
             ),
             networks: Networks::new_with_refreshed_list(),
             disks: Disks::new_with_refreshed_list(),
+            components: if config.show_temperatures {
+                Components::new_with_refreshed_list()
+            } else {
+                Components::new()
+            },
+            config,
+            counters: HashMap::new(),
+            watched_pids: HashMap::new(),
         }
     }
 
-    pub fn collect(&mut self) -> Metrics {
-        self.system.refresh_all();
+    /// Collects every metric, with `pids` read from the pid files of the
+    /// watched processes
+    pub fn collect(&mut self, pids: &HashMap<Box<str>, u32>) -> Metrics {
+        self.system.refresh_specifics(
+            RefreshKind::nothing()
+                .with_cpu(CpuRefreshKind::everything())
+                .with_memory(MemoryRefreshKind::everything()),
+        );
+        if self.config.processes.enabled() {
+            self.system.refresh_processes_specifics(
+                ProcessesToUpdate::All,
+                true,
+                ProcessRefreshKind::nothing().with_cpu().with_memory(),
+            );
+        }
 
         let cpu = self.system.global_cpu_usage();
         let ram = MemoryMetrics {
             used: self.system.used_memory(),
             total: self.system.total_memory(),
         };
-        let disk = {
-            let mut set = HashSet::new(); // TODO: Read from config
-            set.insert("/dev/sdb1");
-            self.collect_disk(&set)
-        };
-        let network = {
-            let mut set = HashSet::new(); // TODO: Read from config
-            set.insert("enp5s0");
-            self.collect_network(&set)
-        };
+        let cpu_cores = self.config.show_cpu_cores.then(|| {
+            self.system.cpus().iter().map(sysinfo::Cpu::cpu_usage).collect()
+        });
+        let load_average =
+            self.config.show_load_average.then(System::load_average);
+        let swap = Some(MemoryMetrics {
+            used: self.system.used_swap(),
+            total: self.system.total_swap(),
+        })
+        .filter(|swap| self.config.show_swap && swap.total != 0);
+        let uptime = self.config.show_uptime.then(|| Uptime {
+            uptime: Duration::from_secs(System::uptime()),
+            boot_time: UNIX_EPOCH + Duration::from_secs(System::boot_time()),
+        });
+        let temperatures = self.collect_temperatures();
+        let disk = self.collect_disk();
+        let network = self.collect_network();
+        let top = self.config.processes.top;
+        let top_processes =
+            (top > 0).then(|| processes::top(&self.system, top));
+        let watched_processes = self.collect_watched(pids);
 
         Metrics {
             cpu,
+            cpu_cores,
+            load_average,
             ram,
+            swap,
+            uptime,
+            temperatures,
             disks: disk,
             net_interfaces: network,
+            top_processes,
+            watched_processes,
+            containers: None,
         }
     }
 
-    fn collect_network(
+    fn collect_watched(
         &mut self,
-        interfaces: &HashSet<&str>,
-    ) -> Option<HashMap<Box<str>, NetworkMetrics>> {
-        if interfaces.is_empty() {
+        pids: &HashMap<Box<str>, u32>,
+    ) -> Option<HashMap<Box<str>, WatchedMetrics>> {
+        let watch = &self.config.processes.watch;
+        if watch.is_empty() {
             return None;
         }
 
+        let watched =
+            processes::watched(&self.system, watch, pids, &self.watched_pids);
+        self.watched_pids = watched
+            .iter()
+            .filter_map(|(name, watched)| Some((name.clone(), watched.pid?)))
+            .collect();
+        Some(watched)
+    }
+
+    fn collect_network(
+        &mut self,
+    ) -> Option<HashMap<Box<str>, NetworkMetrics>> {
         self.networks.refresh(true);
-        let res = self
+        let now = Instant::now();
+        let interfaces = &self.config.network_interfaces;
+        let readings = self
             .networks
             .list()
             .iter()
-            .filter_map(|(name, data)| {
-                if !interfaces.contains(&**name) {
-                    return None;
-                }
-
-                #[allow(clippy::cast_precision_loss)]
-                let received_error_percentage =
-                    data.errors_on_received() as f64 * 100f64
-                        / data.packets_received() as f64;
-                if !received_error_percentage.is_finite() {
-                    return None;
-                }
-                #[allow(clippy::cast_precision_loss)]
-                let transmit_error_percentage =
-                    data.errors_on_transmitted() as f64 * 100f64
-                        / data.packets_transmitted() as f64;
-                if !transmit_error_percentage.is_finite() {
-                    return None;
-                }
-
-                let metrics = NetworkMetrics {
-                    #[allow(clippy::cast_precision_loss)]
-                    bytes_received: data.received() as f64 / DELAY_SECS,
-                    #[allow(clippy::cast_precision_loss)]
-                    bytes_transmitted: data.transmitted() as f64 / DELAY_SECS,
-                    received_error_percentage,
-                    transmit_error_percentage,
-                };
-                Some((Box::from(name.clone()), metrics))
+            .filter(|&(name, _)| {
+                interfaces.is_empty() || interfaces.contains(name)
             })
-            .collect::<HashMap<_, _>>();
+            .map(|(name, data)| {
+                (name.clone(), NetworkCounters::read(now, data))
+            })
+            .collect();
+        let res = network_rates(&mut self.counters, readings);
 
         if res.is_empty() {
             None
@@ -189,14 +575,27 @@ impl SysInfo {
         }
     }
 
-    fn collect_disk(
-        &mut self,
-        disks: &HashSet<&str>,
-    ) -> Option<HashMap<Box<str>, MemoryMetrics>> {
-        if disks.is_empty() {
+    fn collect_temperatures(&mut self) -> Option<HashMap<Box<str>, f32>> {
+        if !self.config.show_temperatures {
             return None;
         }
 
+        self.components.refresh(true);
+        let res = self
+            .components
+            .list()
+            .iter()
+            .filter_map(|c| Some((Box::from(c.label()), c.temperature()?)))
+            .collect::<HashMap<_, _>>();
+
+        if res.is_empty() {
+            None
+        } else {
+            Some(res)
+        }
+    }
+
+    fn collect_disk(&mut self) -> Option<HashMap<Box<str>, MemoryMetrics>> {
         self.disks.refresh(true);
         let res = self
             .disks
@@ -204,7 +603,8 @@ impl SysInfo {
             .iter()
             .filter_map(|d| {
                 let name = d.name().to_string_lossy();
-                if !disks.contains(&*name) {
+                let drives = &self.config.disk_drives;
+                if !drives.is_empty() && !drives.contains(&*name) {
                     return None;
                 }
 
@@ -246,6 +646,10 @@ fn fmt_unit(mut bytes: f64) -> String {
     format!("{:.2}TiB", next(&mut bytes))
 }
 
+fn fmt_mebibytes(bytes: f64) -> String {
+    format!("{:.2}MiB", bytes / (1_024.0 * 1_024.0))
+}
+
 fn fmt_unit_net(bytes: f64) -> String {
     let mut bits = bytes * 8.0;
     let next = |c: &mut f64| {
@@ -266,3 +670,120 @@ fn fmt_unit_net(bytes: f64) -> String {
     }
     format!("{:.2}Tbps", next(&mut bits))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Counters with the same totals in both directions
+    const fn counters(
+        at: Instant,
+        bytes: u64,
+        packets: u64,
+        errors: u64,
+    ) -> NetworkCounters {
+        NetworkCounters {
+            at,
+            received: bytes,
+            transmitted: bytes,
+            packets_received: packets,
+            packets_transmitted: packets,
+            errors_on_received: errors,
+            errors_on_transmitted: errors,
+        }
+    }
+
+    fn readings(
+        at: Instant,
+        interfaces: &[(&str, u64)],
+    ) -> HashMap<String, NetworkCounters> {
+        interfaces
+            .iter()
+            .map(|(name, bytes)| {
+                ((*name).to_owned(), counters(at, *bytes, 0, 0))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn divides_by_the_measured_time() {
+        let start = Instant::now();
+        let previous = counters(start, 1_000, 100, 1);
+        let current = counters(start + Duration::from_secs(2), 5_000, 300, 11);
+        let rates = current.rates_since(&previous).unwrap();
+        assert!((rates.bytes_received - 2_000.0).abs() < f64::EPSILON);
+        assert!((rates.bytes_transmitted - 2_000.0).abs() < f64::EPSILON);
+        assert!((rates.received_error_percentage - 5.0).abs() < f64::EPSILON);
+        assert!((rates.transmit_error_percentage - 5.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn has_no_errors_without_packets() {
+        let start = Instant::now();
+        let previous = counters(start, 1_000, 100, 1);
+        let current = counters(start + Duration::from_secs(1), 1_000, 100, 1);
+        let rates = current.rates_since(&previous).unwrap();
+        assert!(rates.bytes_received.abs() < f64::EPSILON);
+        assert!(rates.received_error_percentage.abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn has_no_rates_across_a_counter_reset() {
+        let start = Instant::now();
+        let previous = counters(start, 5_000, 100, 1);
+        let current = counters(start + Duration::from_secs(1), 1_000, 200, 2);
+        assert!(current.rates_since(&previous).is_none());
+        // Nor without time passing
+        assert!(previous.rates_since(&previous).is_none());
+    }
+
+    #[test]
+    fn starts_rates_from_the_second_sample() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut previous = HashMap::new();
+
+        let rates =
+            network_rates(&mut previous, readings(at(0), &[("eth0", 0)]));
+        assert!(rates.is_empty());
+        let rates =
+            network_rates(&mut previous, readings(at(1), &[("eth0", 100)]));
+        assert!((rates["eth0"].bytes_received - 100.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn restarts_rates_after_a_reset() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut previous = HashMap::new();
+
+        network_rates(&mut previous, readings(at(0), &[("eth0", 1_000)]));
+        // Recreated interface, counting from zero again
+        let rates =
+            network_rates(&mut previous, readings(at(1), &[("eth0", 10)]));
+        assert!(rates.is_empty());
+        let rates =
+            network_rates(&mut previous, readings(at(2), &[("eth0", 60)]));
+        assert!((rates["eth0"].bytes_received - 50.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn restarts_rates_of_interfaces_that_come_back() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut previous = HashMap::new();
+
+        network_rates(
+            &mut previous,
+            readings(at(0), &[("eth0", 0), ("wg0", 0)]),
+        );
+        let rates =
+            network_rates(&mut previous, readings(at(1), &[("eth0", 10)]));
+        assert_eq!(rates.keys().collect::<Vec<_>>(), [&Box::from("eth0")]);
+        let rates = network_rates(
+            &mut previous,
+            readings(at(2), &[("eth0", 20), ("wg0", 500)]),
+        );
+        assert!(!rates.contains_key("wg0"));
+    }
+}